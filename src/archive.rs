@@ -0,0 +1,443 @@
+use log::{debug, error, info, warn};
+
+use crate::command::*;
+use crate::data::POOL;
+use crate::discord::*;
+use crate::guard::Guard;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::i18n::interpolate;
+use crate::permissions::Permissions;
+use crate::settings::{Key, Scope, Settings};
+use crate::storage;
+use actix::*;
+use actix_web::client;
+use actix_web::HttpMessage;
+use chrono::{Datelike, TimeZone, Utc};
+use futures::Future;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Guild saves attachments of its channels, nothing is saved until admins turn it on
+pub const ARCHIVE: Key<bool> = Key::new("archive");
+/// Folder for attachments in working directory
+pub const FILES_FOLDER: &str = "files";
+/// Old files, that were moved to path template, by their old names
+const MIGRATION_STORAGE: &str = "archive_migration";
+/// Value of ids, that are not known for old files
+const UNKNOWN: &str = "unknown";
+/// Attachment is saved right after its message, file saved much later is another one
+const SAVE_WINDOW_MILLIS: u64 = 10 * 60 * 1000;
+
+/// Where attachments are saved.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    /// Path template with {guild}, {channel}, {yyyy}, {mm}, {dd}, {millis},
+    /// {message_id}, {attachment_id} and {filename}
+    pub path: String,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        ArchiveConfig {
+            path: "files/{guild}/{channel}/{yyyy}/{mm}/{message_id}_{attachment_id}_{filename}"
+                .to_owned(),
+        }
+    }
+}
+
+/// What is known about saved file.
+struct PathValues<'a> {
+    guild: &'a str,
+    channel: &'a str,
+    message_id: &'a str,
+    attachment_id: &'a str,
+    filename: &'a str,
+    /// Time of message, unix milliseconds
+    millis: u64,
+}
+
+/// Fills path template. Values can not leave their folder, whatever is in them.
+fn render_path(template: &str, values: &PathValues) -> PathBuf {
+    let time = Utc
+        .timestamp_millis_opt(values.millis as i64)
+        .single()
+        .unwrap_or_else(Utc::now);
+    let year = format!("{:04}", time.year());
+    let month = format!("{:02}", time.month());
+    let day = format!("{:02}", time.day());
+    let guild = safe_name(values.guild);
+    let channel = safe_name(values.channel);
+    let message_id = safe_name(values.message_id);
+    let attachment_id = safe_name(values.attachment_id);
+    let filename = safe_name(values.filename);
+
+    PathBuf::from(interpolate(
+        template,
+        &[
+            ("guild", &guild),
+            ("channel", &channel),
+            ("yyyy", &year),
+            ("mm", &month),
+            ("dd", &day),
+            ("millis", &values.millis),
+            ("message_id", &message_id),
+            ("attachment_id", &attachment_id),
+            ("filename", &filename),
+        ],
+    ))
+}
+
+/// Name without separators, so "../" in file name does not go anywhere.
+fn safe_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            _ => c,
+        })
+        .collect();
+    match name.as_str() {
+        "" | "." | ".." => "_".to_owned(),
+        _ => name,
+    }
+}
+
+/// Path of attachment by path template from conf/bot.yaml.
+/// Guild is given separately, messages from REST do not know it.
+pub fn attachment_path(
+    guild_id: Option<&Snowflake>,
+    message: &MessagePacket,
+    attachment: &AttachmentPacket,
+) -> PathBuf {
+    render_path(
+        &POOL.config.archive.path,
+        &PathValues {
+            guild: guild_id.map(|g| g.0.as_str()).unwrap_or("direct"),
+            channel: &message.channel_id.0,
+            message_id: &message.id.0,
+            attachment_id: &attachment.id.0,
+            filename: &attachment.filename,
+            millis: message.id.created_at().unwrap_or_else(now_millis),
+        },
+    )
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// The same path, or the same with number, if there is such file already.
+fn unique_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+/// Whether attachments are saved. Direct messages are sent to bot itself, so they always are.
+fn is_archived(settings: &Settings, guild_id: Option<&Snowflake>) -> bool {
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        None => return true,
+    };
+    settings
+        .get(&ARCHIVE, Scope::Guild, guild_id)
+        .unwrap_or_else(|e| {
+            error!("Failed to get archive setting of {:?}: {}", guild_id, e);
+            None
+        })
+        .unwrap_or(false)
+}
+
+/// Saves every attachment by path template in guilds, that turned it on.
+pub struct Archive;
+
+impl EventHandler for Archive {
+    fn name(&self) -> &'static str {
+        "archive"
+    }
+
+    fn on_message(&mut self, ctx: &EventContext, message: &MessagePacket) -> HandlerResult {
+        if message.attachments.is_empty() {
+            debug!("No attachments found");
+            return Ok(());
+        }
+        if !is_archived(ctx.settings, message.guild_id.as_ref()) {
+            debug!("Guild {:?} does not save attachments", &message.guild_id);
+            return Ok(());
+        }
+
+        debug!("Found some attachments");
+        for att in &message.attachments {
+            let path = attachment_path(message.guild_id.as_ref(), message, att);
+            let url = &att.url;
+            debug!("File {:?} is from {}", path, url);
+
+            let f = client::get(url).finish().map_err(|e| e.to_string())?.send();
+            let f = f
+                .map_err(actix_web::error::Error::from)
+                .and_then(|resp| {
+                    resp.body()
+                        .limit(100 * 1024 * 1024)
+                        .map_err(actix_web::error::Error::from)
+                })
+                .map(move |body| {
+                    let path = unique_path(path);
+                    let res = path
+                        .parent()
+                        .map_or(Ok(()), |folder| std::fs::create_dir_all(folder))
+                        .and_then(|_| std::fs::write(&path, body.as_ref()));
+                    match res {
+                        Ok(()) => debug!("Saved {:?}", &path),
+                        Err(e) => error!("Failed to save {:?}: {}", &path, e),
+                    }
+                })
+                .map_err(|e| {
+                    error!("Error happened {}", e);
+                });
+            Arbiter::spawn(f);
+        }
+        Ok(())
+    }
+}
+
+/// Registers command, that turns saving of attachments on or off.
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        Command::new(
+            "archive",
+            "Shows whether attachments of this guild are saved, or turns it on or off",
+            |ctx, args| {
+                let guild_id = ctx.message.guild_id.as_ref().unwrap();
+                if let Some(state) = args.text("state") {
+                    let enabled = match state.to_lowercase().as_str() {
+                        "on" => true,
+                        "off" => false,
+                        _ => return Err(CommandError::Usage(ctx.text("archive-state", &[]))),
+                    };
+                    ctx.settings
+                        .set(&ARCHIVE, Scope::Guild, guild_id, &enabled)
+                        .map_err(|e| {
+                            error!("Failed to save archive setting of {:?}: {}", guild_id, e);
+                            CommandError::Failed(ctx.text("setting-failed", &[]))
+                        })?;
+                }
+                if is_archived(ctx.settings, Some(guild_id)) {
+                    ctx.reply(&ctx.text("archive-on", &[]));
+                } else {
+                    ctx.reply(&ctx.text("archive-off", &[]));
+                }
+                Ok(())
+            },
+        )
+        .optional_argument("state", ArgumentType::Text)
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+}
+
+/// Time of saving and original name of file from the old flat folder, named `{millis}_{filename}`.
+/// Templates may name files by ids too, but snowflakes are longer than 13 digits
+/// and the time has to be between DISCORD epoch and now.
+fn parse_old_name(name: &str) -> Option<(u64, &str)> {
+    let mut parts = name.splitn(2, '_');
+    let prefix = parts.next()?;
+    if prefix.len() != 13 || !prefix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let millis = prefix.parse::<u64>().ok()?;
+    if millis < DISCORD_EPOCH || millis > now_millis() {
+        return None;
+    }
+    let filename = parts.next().filter(|f| !f.is_empty())?;
+    Some((millis, filename))
+}
+
+/// Moves files from the old flat files folder to path template.
+/// Their ids are not known, only time from their names, so ids are "unknown".
+/// Moves are recorded in data folder, so exports still find old files.
+/// Returns how many files were moved.
+pub fn migrate(config: &ArchiveConfig) -> std::io::Result<usize> {
+    let mut moved: HashMap<String, PathBuf> = storage::load(MIGRATION_STORAGE);
+    let before = moved.len();
+    let res = migrate_files(config, &mut moved);
+    // Whatever was moved before failure has to be remembered
+    storage::save(MIGRATION_STORAGE, &moved)?;
+    res?;
+    Ok(moved.len() - before)
+}
+
+fn migrate_files(
+    config: &ArchiveConfig,
+    moved: &mut HashMap<String, PathBuf>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(FILES_FOLDER)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(name) => {
+                warn!("Skipping {:?}, its name is not unicode", name);
+                continue;
+            }
+        };
+        let (millis, filename) = match parse_old_name(&name) {
+            Some(parsed) => parsed,
+            None => {
+                warn!("Skipping {}, it was not saved by old archive", name);
+                continue;
+            }
+        };
+
+        let target = render_path(
+            &config.path,
+            &PathValues {
+                guild: UNKNOWN,
+                channel: UNKNOWN,
+                message_id: UNKNOWN,
+                attachment_id: UNKNOWN,
+                filename,
+                millis,
+            },
+        );
+        // Template may still be flat
+        if target == entry.path() {
+            continue;
+        }
+        let target = unique_path(target);
+        if let Some(folder) = target.parent() {
+            std::fs::create_dir_all(folder)?;
+        }
+        std::fs::rename(entry.path(), &target)?;
+        info!("Moved {} to {:?}", name, &target);
+        moved.insert(name, target);
+    }
+    Ok(())
+}
+
+/// Saved attachments, that are not at their template path.
+/// Old files were named `{millis}_{filename}` by time of saving, so they are matched to messages by time.
+pub struct ArchiveIndex {
+    /// Original name -> times of saving with saved files, earlier go first
+    old_files: HashMap<String, Vec<(u64, PathBuf)>>,
+}
+
+impl ArchiveIndex {
+    /// Reads names of old files, both still in flat folder and already migrated.
+    pub fn load() -> Self {
+        let mut old_files: HashMap<String, Vec<(u64, PathBuf)>> = HashMap::new();
+        let mut add = |name: &str, path: PathBuf| {
+            if let Some((millis, filename)) = parse_old_name(name) {
+                old_files
+                    .entry(filename.to_owned())
+                    .or_default()
+                    .push((millis, path));
+            }
+        };
+
+        let moved: HashMap<String, PathBuf> = storage::load(MIGRATION_STORAGE);
+        for (name, path) in moved {
+            add(&name, path);
+        }
+        match std::fs::read_dir(FILES_FOLDER) {
+            Ok(entries) => {
+                for entry in entries.filter_map(Result::ok) {
+                    if let Ok(name) = entry.file_name().into_string() {
+                        add(&name, entry.path());
+                    }
+                }
+            }
+            Err(e) => debug!("Could not read {}: {}", FILES_FOLDER, e),
+        }
+
+        for saved in old_files.values_mut() {
+            saved.sort();
+        }
+        ArchiveIndex { old_files }
+    }
+
+    /// Saved copy of attachment: the one at template path, or old file with its name,
+    /// that was saved soon after message.
+    pub fn find(
+        &self,
+        guild_id: Option<&Snowflake>,
+        message: &MessagePacket,
+        attachment: &AttachmentPacket,
+    ) -> Option<PathBuf> {
+        let path = attachment_path(guild_id, message, attachment);
+        if path.exists() {
+            return Some(path);
+        }
+
+        let sent = message.id.created_at()?;
+        self.old_files
+            .get(&attachment.filename)?
+            .iter()
+            .find(|(saved, _)| *saved >= sent && *saved - sent <= SAVE_WINDOW_MILLIS)
+            .map(|(_, path)| path.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_name() {
+        assert_eq!(
+            parse_old_name("1577836800000_cat.png"),
+            Some((1_577_836_800_000, "cat.png"))
+        );
+        assert_eq!(
+            parse_old_name("1577836800000_my_cat.png"),
+            Some((1_577_836_800_000, "my_cat.png"))
+        );
+    }
+
+    #[test]
+    fn not_old_name() {
+        // Snowflake, as `{attachment_id}_{filename}` template names files
+        assert_eq!(parse_old_name("661234567890123456_cat.png"), None);
+        // Before DISCORD and in far future
+        assert_eq!(parse_old_name("1000000000000_cat.png"), None);
+        assert_eq!(parse_old_name("9999999999999_cat.png"), None);
+        assert_eq!(parse_old_name("+577836800000_cat.png"), None);
+        assert_eq!(parse_old_name("1577836800000_"), None);
+        assert_eq!(parse_old_name("1577836800000"), None);
+        assert_eq!(parse_old_name("cat.png"), None);
+    }
+
+    #[test]
+    fn guilds_opt_in() {
+        let settings = Settings::open(":memory:").unwrap();
+        let guild_id = Snowflake("1".to_owned());
+        assert!(is_archived(&settings, None));
+        assert!(!is_archived(&settings, Some(&guild_id)));
+        settings
+            .set(&ARCHIVE, Scope::Guild, &guild_id, &true)
+            .unwrap();
+        assert!(is_archived(&settings, Some(&guild_id)));
+        settings
+            .set(&ARCHIVE, Scope::Guild, &guild_id, &false)
+            .unwrap();
+        assert!(!is_archived(&settings, Some(&guild_id)));
+    }
+}
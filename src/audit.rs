@@ -0,0 +1,291 @@
+use log::debug;
+
+use crate::builder::{truncate, EmbedBuilder, DESCRIPTION_LIMIT, FIELD_VALUE_LIMIT};
+use crate::cache::Cache;
+use crate::data::POOL;
+use crate::discord::*;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::i18n::{self, Language};
+use crate::lru::LruCache;
+use crate::modlog::{self, quote, DELETE_COLOUR, EDIT_COLOUR};
+use crate::sanitize::escape_markdown;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How many removed ids are remembered, deletion event comes right after request
+const REMOVED_LIMIT: usize = 1000;
+
+/// Ids of messages, that automod removes and logs on its own.
+/// Audit never sees these messages, so it would log their deletion as unknown.
+#[derive(Clone)]
+pub struct Removed {
+    ids: Rc<RefCell<LruCache<Snowflake, ()>>>,
+}
+
+impl Removed {
+    pub fn new() -> Self {
+        Removed {
+            ids: Rc::new(RefCell::new(LruCache::new(REMOVED_LIMIT))),
+        }
+    }
+
+    pub fn insert(&self, id: &Snowflake) {
+        self.ids.borrow_mut().insert(id.clone(), ());
+    }
+
+    /// Whether message was removed by automod, it is forgotten then.
+    fn take(&self, id: &Snowflake) -> bool {
+        self.ids.borrow_mut().remove(id).is_some()
+    }
+}
+
+/// Remembers recent guild messages and reports their edits and deletions
+/// to moderation log. DISCORD sends only ids of deleted messages.
+pub struct Audit {
+    messages: LruCache<Snowflake, MessagePacket>,
+    removed: Removed,
+}
+
+impl Audit {
+    pub fn new(removed: &Removed) -> Self {
+        Audit {
+            messages: LruCache::new(POOL.config.cache.max_messages),
+            removed: removed.clone(),
+        }
+    }
+}
+
+impl EventHandler for Audit {
+    fn name(&self) -> &'static str {
+        "audit"
+    }
+
+    fn on_message(&mut self, ctx: &EventContext, message: &MessagePacket) -> HandlerResult {
+        // Log channel would log itself otherwise
+        if message.guild_id.is_some() && message.author.id.ne(ctx.myself_id) {
+            self.messages.insert(message.id.clone(), message.clone());
+        }
+        Ok(())
+    }
+
+    fn on_message_update(
+        &mut self,
+        ctx: &EventContext,
+        update: &MessageUpdatePacket,
+    ) -> HandlerResult {
+        // Updates without content are embeds, that DISCORD resolved later
+        let (guild_id, after) = match (&update.guild_id, &update.content) {
+            (Some(guild_id), Some(after)) => (guild_id, after),
+            _ => return Ok(()),
+        };
+
+        let message = match self.messages.get_mut(&update.id) {
+            Some(message) => message,
+            None => {
+                debug!("Edited message {:?} is not cached", &update.id);
+                return Ok(());
+            }
+        };
+        // Attachments can only be removed by edit, but lets not assume it
+        let (removed, added) = match &update.attachments {
+            Some(attachments) => (
+                attachment_names(
+                    message
+                        .attachments
+                        .iter()
+                        .filter(|a| !attachments.iter().any(|b| b.id == a.id)),
+                ),
+                attachment_names(
+                    attachments
+                        .iter()
+                        .filter(|a| !message.attachments.iter().any(|b| b.id == a.id)),
+                ),
+            ),
+            None => (None, None),
+        };
+        if message.content.eq(after) && removed.is_none() && added.is_none() {
+            return Ok(());
+        }
+
+        let language = i18n::guild_language(ctx.settings, guild_id);
+        let text = |key| i18n::text(language, key, &[]);
+        let mut entry = EmbedBuilder::new()
+            .title(&text("audit-edited"))
+            .colour(EDIT_COLOUR)
+            .field(
+                &text("audit-author"),
+                &describe_author(ctx.cache, &message.author),
+                true,
+            )
+            .field(
+                &text("audit-channel"),
+                &format!("<#{}>", message.channel_id.0),
+                true,
+            )
+            .field(&text("audit-before"), &quote(&message.content), false)
+            .field(&text("audit-after"), &quote(after), false)
+            .footer(&footer(language, &message.id), None);
+        if let Some(removed) = removed {
+            entry = entry.field(&text("audit-removed-attachments"), &removed, false);
+        }
+        if let Some(added) = added {
+            entry = entry.field(&text("audit-added-attachments"), &added, false);
+        }
+
+        message.content = after.clone();
+        if let Some(attachments) = &update.attachments {
+            message.attachments = attachments.clone();
+        }
+        modlog::post(ctx, guild_id, entry);
+        Ok(())
+    }
+
+    fn on_message_delete(
+        &mut self,
+        ctx: &EventContext,
+        delete: &MessageDeletePacket,
+    ) -> HandlerResult {
+        let guild_id = match &delete.guild_id {
+            Some(guild_id) => guild_id,
+            None => return Ok(()),
+        };
+        if self.removed.take(&delete.id) {
+            debug!("Message {:?} was removed by automod", &delete.id);
+            return Ok(());
+        }
+
+        let language = i18n::guild_language(ctx.settings, guild_id);
+        let text = |key| i18n::text(language, key, &[]);
+        let entry = EmbedBuilder::new()
+            .title(&text("audit-deleted"))
+            .colour(DELETE_COLOUR)
+            .footer(&footer(language, &delete.id), None);
+        let entry = match self.messages.remove(&delete.id) {
+            Some(message) => {
+                let mut entry = entry
+                    .field(
+                        &text("audit-author"),
+                        &describe_author(ctx.cache, &message.author),
+                        true,
+                    )
+                    .field(
+                        &text("audit-channel"),
+                        &format!("<#{}>", message.channel_id.0),
+                        true,
+                    )
+                    .field(&text("audit-content"), &quote(&message.content), false);
+                if let Some(names) = attachment_names(message.attachments.iter()) {
+                    entry = entry.field(&text("audit-attachments"), &names, false);
+                }
+                entry
+            }
+            None => entry
+                .field(
+                    &text("audit-channel"),
+                    &format!("<#{}>", delete.channel_id.0),
+                    true,
+                )
+                .description(&text("audit-too-old")),
+        };
+
+        modlog::post(ctx, guild_id, entry);
+        Ok(())
+    }
+
+    fn on_message_delete_bulk(
+        &mut self,
+        ctx: &EventContext,
+        delete: &MessageDeleteBulkPacket,
+    ) -> HandlerResult {
+        let guild_id = match &delete.guild_id {
+            Some(guild_id) => guild_id,
+            None => return Ok(()),
+        };
+
+        let ids: Vec<&Snowflake> = delete
+            .ids
+            .iter()
+            .filter(|id| !self.removed.take(id))
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let lines: Vec<String> = ids
+            .iter()
+            .filter_map(|id| self.messages.remove(*id))
+            .map(|m| {
+                let mut line = format!(
+                    "**{}**: {}",
+                    escape_markdown(&m.author.username),
+                    escape_markdown(&m.content)
+                );
+                if let Some(names) = attachment_names(m.attachments.iter()) {
+                    line.push_str(&format!(" [{}]", names));
+                }
+                line
+            })
+            .collect();
+
+        let language = i18n::guild_language(ctx.settings, guild_id);
+        let mut entry = EmbedBuilder::new()
+            .title(&i18n::plural(
+                language,
+                "audit-deleted-bulk",
+                ids.len() as i64,
+                &[],
+            ))
+            .colour(DELETE_COLOUR)
+            .field(
+                &i18n::text(language, "audit-channel", &[]),
+                &format!("<#{}>", delete.channel_id.0),
+                true,
+            );
+        if !lines.is_empty() {
+            entry = entry.description(&truncate(&lines.join("\n"), DESCRIPTION_LIMIT));
+        }
+
+        modlog::post(ctx, guild_id, entry);
+        Ok(())
+    }
+}
+
+/// Footer with id of message, so log entries can be searched by it.
+fn footer(language: Language, message_id: &Snowflake) -> String {
+    i18n::text(language, "message-footer", &[("id", &message_id.0)])
+}
+
+fn describe_author(cache: &Cache, author: &UserPacket) -> String {
+    format!(
+        "<@{}>\n{}",
+        author.id.0,
+        escape_markdown(&cache.describe_user(&author.id))
+    )
+}
+
+/// Names of attachments for log entry, none if there are no attachments.
+fn attachment_names<'a>(attachments: impl Iterator<Item = &'a AttachmentPacket>) -> Option<String> {
+    let names = attachments
+        .map(|a| escape_markdown(&a.filename))
+        .collect::<Vec<String>>();
+    if names.is_empty() {
+        None
+    } else {
+        Some(truncate(&names.join(", "), FIELD_VALUE_LIMIT))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_is_taken_once() {
+        let removed = Removed::new();
+        let id = Snowflake("1".to_string());
+        removed.insert(&id);
+        assert!(removed.clone().take(&id));
+        assert!(!removed.take(&id));
+        assert!(!removed.take(&Snowflake("2".to_string())));
+    }
+}
@@ -0,0 +1,458 @@
+use log::{debug, error, info};
+
+use crate::audit::Removed;
+use crate::builder::EmbedBuilder;
+use crate::data::POOL;
+use crate::discord::*;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::i18n;
+use crate::modlog::{self, quote, AUTOMOD_COLOUR};
+use crate::permissions::Permissions;
+use crate::rest::{self, RestFuture};
+use crate::sanitize::escape_markdown;
+use crate::storage;
+use chrono::Utc;
+use futures::{future, Future};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Messages of banned users from the last day are deleted as well
+const BAN_DELETE_MESSAGE_DAYS: u8 = 1;
+/// Links to DISCORD invites
+const INVITE_PATTERN: &str = r"(?i)(discord\.gg|discord(app)?\.com/invite)/[a-z0-9-]+";
+/// Name of file in data folder, timeouts have to be taken back after restart too
+const TIMEOUTS_STORAGE: &str = "timeouts";
+
+/// What happens to author of violating message.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Delete,
+    /// Reply to author, reason is only in moderation log
+    Warn,
+    /// Give timeout role from config for a while
+    Timeout,
+    Kick,
+    Ban,
+}
+
+/// Banned words (case insensitive, whole words) and regular expressions.
+#[derive(Debug, Deserialize)]
+pub struct WordsRule {
+    #[serde(default)]
+    pub words: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    pub actions: Vec<Action>,
+}
+
+/// Same message `count` times within `seconds`.
+#[derive(Debug, Deserialize)]
+pub struct RepeatsRule {
+    pub count: usize,
+    pub seconds: u64,
+    pub actions: Vec<Action>,
+}
+
+/// More than `max` user and role mentions in one message, @everyone counts as one.
+#[derive(Debug, Deserialize)]
+pub struct MentionsRule {
+    pub max: usize,
+    pub actions: Vec<Action>,
+}
+
+/// Links to other DISCORD servers.
+#[derive(Debug, Deserialize)]
+pub struct InvitesRule {
+    pub actions: Vec<Action>,
+}
+
+/// More than `count` attachments within `seconds`.
+#[derive(Debug, Deserialize)]
+pub struct AttachmentsRule {
+    pub count: usize,
+    pub seconds: u64,
+    pub actions: Vec<Action>,
+}
+
+/// Auto moderation from conf/bot.yaml.
+/// Rule is enabled, if it is present in config.
+/// Members, that can manage messages, are not moderated.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AutomodConfig {
+    pub words: Option<WordsRule>,
+    pub repeats: Option<RepeatsRule>,
+    pub mentions: Option<MentionsRule>,
+    pub invites: Option<InvitesRule>,
+    pub attachments: Option<AttachmentsRule>,
+    /// Role, that is given by timeout action. It should deny sending messages
+    pub timeout_role: Option<Snowflake>,
+    /// How long timeout lasts, in seconds
+    pub timeout_seconds: u64,
+}
+
+impl Default for AutomodConfig {
+    fn default() -> Self {
+        AutomodConfig {
+            words: None,
+            repeats: None,
+            mentions: None,
+            invites: None,
+            attachments: None,
+            timeout_role: None,
+            timeout_seconds: 600,
+        }
+    }
+}
+
+/// Recent message of some member.
+struct Recent {
+    at: Instant,
+    content: String,
+    attachments: usize,
+}
+
+/// Timeout role, that has to be taken back.
+#[derive(Debug, Serialize, Deserialize)]
+struct Timeout {
+    /// Unix time in seconds
+    until: i64,
+    guild_id: Snowflake,
+    user_id: Snowflake,
+    role_id: Snowflake,
+}
+
+/// Broken rule.
+struct Violation {
+    rule: &'static str,
+    reason: String,
+    actions: &'static [Action],
+}
+
+/// Checks every guild message against rules from config.
+pub struct Automod {
+    config: &'static AutomodConfig,
+    /// Compiled words and patterns
+    filters: Vec<Regex>,
+    invites: Regex,
+    /// Guild id and user id -> their messages within the longest window
+    recent: HashMap<(Snowflake, Snowflake), VecDeque<Recent>>,
+    timeouts: Vec<Timeout>,
+    /// Audit does not log deletions, that automod logs
+    removed: Removed,
+}
+
+impl Automod {
+    /// Compiles rules from config, broken patterns are fatal as broken config is.
+    pub fn new(removed: &Removed) -> Self {
+        let config = &POOL.config.automod;
+        let filters = match &config.words {
+            Some(rule) => rule
+                .words
+                .iter()
+                .map(|w| format!(r"(?i)\b{}\b", regex::escape(w)))
+                .chain(rule.patterns.iter().cloned())
+                .map(|p| {
+                    Regex::new(&p).unwrap_or_else(|e| panic!("Bad automod pattern {}: {}", p, e))
+                })
+                .collect(),
+            None => vec![],
+        };
+
+        Automod {
+            config,
+            filters,
+            invites: Regex::new(INVITE_PATTERN).unwrap(),
+            recent: HashMap::new(),
+            timeouts: storage::load(TIMEOUTS_STORAGE),
+            removed: removed.clone(),
+        }
+    }
+
+    /// How long recent messages are remembered.
+    fn window(&self) -> Duration {
+        let repeats = self.config.repeats.as_ref().map(|r| r.seconds);
+        let attachments = self.config.attachments.as_ref().map(|r| r.seconds);
+        Duration::from_secs(repeats.max(attachments).unwrap_or(0))
+    }
+
+    /// Remembers message and finds the first broken rule.
+    fn check(&mut self, guild_id: &Snowflake, message: &MessagePacket) -> Option<Violation> {
+        let now = Instant::now();
+        let window = self.window();
+        let recent = self
+            .recent
+            .entry((guild_id.clone(), message.author.id.clone()))
+            .or_default();
+        while recent
+            .front()
+            .map(|r| now.duration_since(r.at) > window)
+            .unwrap_or(false)
+        {
+            recent.pop_front();
+        }
+        recent.push_back(Recent {
+            at: now,
+            content: message.content.trim().to_lowercase(),
+            attachments: message.attachments.len(),
+        });
+        let recent = &self.recent[&(guild_id.clone(), message.author.id.clone())];
+        let config = self.config;
+
+        if let Some(rule) = &config.words {
+            if let Some(found) = self.filters.iter().find_map(|f| f.find(&message.content)) {
+                return Some(Violation {
+                    rule: "words",
+                    reason: format!("Banned word `{}`", found.as_str()),
+                    actions: &rule.actions,
+                });
+            }
+        }
+
+        if let Some(rule) = &config.invites {
+            if self.invites.is_match(&message.content) {
+                return Some(Violation {
+                    rule: "invites",
+                    reason: "Invite links are not allowed".to_owned(),
+                    actions: &rule.actions,
+                });
+            }
+        }
+
+        if let Some(rule) = &config.mentions {
+            let mentions = message.mentions.len()
+                + message.mention_roles.len()
+                + message.mention_everyone as usize;
+            if mentions > rule.max {
+                return Some(Violation {
+                    rule: "mentions",
+                    reason: format!("{} mentions in one message", mentions),
+                    actions: &rule.actions,
+                });
+            }
+        }
+
+        if let Some(rule) = &config.repeats {
+            let content = &recent.back().unwrap().content;
+            let per = Duration::from_secs(rule.seconds);
+            let repeats = recent
+                .iter()
+                .filter(|r| now.duration_since(r.at) <= per && r.content.eq(content))
+                .count();
+            if !content.is_empty() && repeats >= rule.count {
+                return Some(Violation {
+                    rule: "repeats",
+                    reason: format!("Same message {} times", repeats),
+                    actions: &rule.actions,
+                });
+            }
+        }
+
+        if let Some(rule) = &config.attachments {
+            let per = Duration::from_secs(rule.seconds);
+            let attachments: usize = recent
+                .iter()
+                .filter(|r| now.duration_since(r.at) <= per)
+                .map(|r| r.attachments)
+                .sum();
+            if attachments > rule.count {
+                return Some(Violation {
+                    rule: "attachments",
+                    reason: format!("{} attachments in {} s", attachments, rule.seconds),
+                    actions: &rule.actions,
+                });
+            }
+        }
+
+        None
+    }
+
+    fn punish(
+        &mut self,
+        ctx: &EventContext,
+        guild_id: &Snowflake,
+        message: &MessagePacket,
+        violation: &Violation,
+    ) {
+        let user_id = &message.author.id;
+        let mut done: Vec<ActionFuture> = vec![];
+        for &action in violation.actions {
+            match action {
+                Action::Delete => {
+                    self.removed.insert(&message.id);
+                    done.push(outcome(
+                        action,
+                        ctx.rest.delete_message(&message.channel_id, &message.id),
+                    ))
+                }
+                Action::Warn => {
+                    // Reason may quote banned word, it goes only to moderation log
+                    let language = i18n::language(ctx.settings, Some(guild_id), user_id);
+                    let mut warning = MessageRequestPacket::simple_text(&i18n::text(
+                        language,
+                        "automod-warning",
+                        &[("user", &user_id.0)],
+                    ));
+                    warning.allowed_mentions =
+                        Some(AllowedMentionsPacket::users(vec![user_id.clone()]));
+                    ctx.rest.say(&message.channel_id, &warning);
+                    done.push(Box::new(future::ok(format!("{:?}", action))));
+                }
+                Action::Timeout => match &self.config.timeout_role {
+                    Some(role_id) => {
+                        done.push(outcome(
+                            action,
+                            ctx.rest.add_member_role(guild_id, user_id, role_id),
+                        ));
+                        let until = Utc::now().timestamp() + self.config.timeout_seconds as i64;
+                        // Repeated timeout only lasts longer
+                        let existing = self.timeouts.iter_mut().find(|t| {
+                            t.guild_id.eq(guild_id)
+                                && t.user_id.eq(user_id)
+                                && t.role_id.eq(role_id)
+                        });
+                        match existing {
+                            Some(timeout) => timeout.until = until,
+                            None => self.timeouts.push(Timeout {
+                                until,
+                                guild_id: guild_id.clone(),
+                                user_id: user_id.clone(),
+                                role_id: role_id.clone(),
+                            }),
+                        }
+                        if let Err(e) = storage::save(TIMEOUTS_STORAGE, &self.timeouts) {
+                            error!("Failed to save timeouts: {}", e);
+                        }
+                    }
+                    None => error!("Timeout action is used, but there is no timeout_role"),
+                },
+                Action::Kick => done.push(outcome(action, ctx.rest.kick(guild_id, user_id))),
+                Action::Ban => {
+                    // Ban takes the message away too
+                    self.removed.insert(&message.id);
+                    done.push(outcome(
+                        action,
+                        ctx.rest.ban(guild_id, user_id, BAN_DELETE_MESSAGE_DAYS),
+                    ))
+                }
+            }
+        }
+
+        let entry = EmbedBuilder::new()
+            .title("Auto moderation")
+            .colour(AUTOMOD_COLOUR)
+            .field(
+                "Author",
+                &format!(
+                    "<@{}>\n{}",
+                    user_id.0,
+                    escape_markdown(&ctx.cache.describe_user(user_id))
+                ),
+                true,
+            )
+            .field("Channel", &format!("<#{}>", message.channel_id.0), true)
+            .field("Rule", violation.rule, true)
+            .field("Reason", &violation.reason, false);
+        let content = quote(&message.content);
+        // Log tells what was really done, DISCORD may refuse some actions
+        let entry = future::join_all(done).map(move |actions| {
+            entry
+                .field("Actions", &actions.join(", "), false)
+                .field("Content", &content, false)
+        });
+        modlog::post_later(ctx, guild_id, entry);
+    }
+}
+
+type ActionFuture = Box<dyn Future<Item = String, Error = ()>>;
+
+/// Name of action, that tells whether DISCORD did it.
+fn outcome<T: 'static>(action: Action, request: RestFuture<T>) -> ActionFuture {
+    Box::new(request.then(move |res| match res {
+        Ok(_) => Ok(format!("{:?}", action)),
+        Err(e) => {
+            error!("Automod action {:?} failed: {}", action, e);
+            Ok(format!("{:?} (failed)", action))
+        }
+    }))
+}
+
+impl EventHandler for Automod {
+    fn name(&self) -> &'static str {
+        "automod"
+    }
+
+    fn on_message(&mut self, ctx: &EventContext, message: &MessagePacket) -> HandlerResult {
+        let guild_id = match &message.guild_id {
+            Some(guild_id) => guild_id,
+            None => return Ok(()),
+        };
+        if message.author.id.eq(ctx.myself_id) || message.author.bot.unwrap_or(false) {
+            return Ok(());
+        }
+
+        let roles = message
+            .member
+            .as_ref()
+            .map(|m| m.roles.as_slice())
+            .unwrap_or(&[]);
+        let is_moderator = ctx
+            .cache
+            .permissions(&message.channel_id, &message.author.id, roles)
+            .map(|p| p.contains(Permissions::MANAGE_MESSAGES))
+            .unwrap_or(false);
+        if is_moderator {
+            debug!("Moderators are not moderated");
+            return Ok(());
+        }
+
+        if let Some(violation) = self.check(guild_id, message) {
+            info!(
+                "Message {:?} of {} broke {} rule: {}",
+                &message.id,
+                ctx.cache.describe_user(&message.author.id),
+                violation.rule,
+                &violation.reason
+            );
+            self.punish(ctx, guild_id, message, &violation);
+            let removed = violation
+                .actions
+                .iter()
+                .any(|a| matches!(a, Action::Delete | Action::Kick | Action::Ban));
+            if removed {
+                ctx.stop();
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes back expired timeouts and forgets old messages.
+    fn on_tick(&mut self, ctx: &EventContext) -> HandlerResult {
+        let unix_now = Utc::now().timestamp();
+        if self.timeouts.iter().any(|t| t.until <= unix_now) {
+            let (expired, active): (Vec<Timeout>, Vec<Timeout>) =
+                self.timeouts.drain(..).partition(|t| t.until <= unix_now);
+            self.timeouts = active;
+            for timeout in expired {
+                debug!("Timeout of {:?} is over", &timeout.user_id);
+                rest::spawn(ctx.rest.remove_member_role(
+                    &timeout.guild_id,
+                    &timeout.user_id,
+                    &timeout.role_id,
+                ));
+            }
+            storage::save(TIMEOUTS_STORAGE, &self.timeouts)?;
+        }
+
+        let now = Instant::now();
+        let window = self.window();
+        for recent in self.recent.values_mut() {
+            recent.retain(|r| now.duration_since(r.at) <= window);
+        }
+        self.recent.retain(|_, recent| !recent.is_empty());
+        Ok(())
+    }
+}
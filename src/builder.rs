@@ -0,0 +1,524 @@
+use crate::discord::*;
+
+/// Limits of DISCORD for messages and embeds, counted in characters.
+pub const CONTENT_LIMIT: usize = 2000;
+pub const TITLE_LIMIT: usize = 256;
+pub const DESCRIPTION_LIMIT: usize = 4096;
+pub const FIELDS_LIMIT: usize = 25;
+pub const FIELD_NAME_LIMIT: usize = 256;
+pub const FIELD_VALUE_LIMIT: usize = 1024;
+pub const FOOTER_TEXT_LIMIT: usize = 2048;
+pub const AUTHOR_NAME_LIMIT: usize = 256;
+/// Sum of title, description, field names and values, footer text and author name
+pub const EMBED_TOTAL_LIMIT: usize = 6000;
+
+/// Reasons why DISCORD would reject built message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    ContentTooLong {
+        length: usize,
+    },
+    TitleTooLong {
+        length: usize,
+    },
+    DescriptionTooLong {
+        length: usize,
+    },
+    TooManyFields {
+        count: usize,
+    },
+    FieldNameTooLong {
+        index: usize,
+        length: usize,
+    },
+    FieldValueTooLong {
+        index: usize,
+        length: usize,
+    },
+    FooterTooLong {
+        length: usize,
+    },
+    AuthorNameTooLong {
+        length: usize,
+    },
+    EmbedTooLong {
+        length: usize,
+    },
+    /// Message has neither content nor embed
+    EmptyMessage,
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BuildError::ContentTooLong { length } => write!(
+                f,
+                "Message content is {} characters long, limit is {}",
+                length, CONTENT_LIMIT
+            ),
+            BuildError::TitleTooLong { length } => write!(
+                f,
+                "Embed title is {} characters long, limit is {}",
+                length, TITLE_LIMIT
+            ),
+            BuildError::DescriptionTooLong { length } => write!(
+                f,
+                "Embed description is {} characters long, limit is {}",
+                length, DESCRIPTION_LIMIT
+            ),
+            BuildError::TooManyFields { count } => {
+                write!(f, "Embed has {} fields, limit is {}", count, FIELDS_LIMIT)
+            }
+            BuildError::FieldNameTooLong { index, length } => write!(
+                f,
+                "Name of embed field #{} is {} characters long, limit is {}",
+                index, length, FIELD_NAME_LIMIT
+            ),
+            BuildError::FieldValueTooLong { index, length } => write!(
+                f,
+                "Value of embed field #{} is {} characters long, limit is {}",
+                index, length, FIELD_VALUE_LIMIT
+            ),
+            BuildError::FooterTooLong { length } => write!(
+                f,
+                "Embed footer is {} characters long, limit is {}",
+                length, FOOTER_TEXT_LIMIT
+            ),
+            BuildError::AuthorNameTooLong { length } => write!(
+                f,
+                "Embed author name is {} characters long, limit is {}",
+                length, AUTHOR_NAME_LIMIT
+            ),
+            BuildError::EmbedTooLong { length } => write!(
+                f,
+                "Embed is {} characters long in total, limit is {}",
+                length, EMBED_TOTAL_LIMIT
+            ),
+            BuildError::EmptyMessage => write!(f, "Message has neither content nor embed"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Fluent constructor of embeds.
+/// Nothing is checked until `build` is called.
+#[derive(Debug, Clone, Default)]
+pub struct EmbedBuilder {
+    title: Option<String>,
+    description: Option<String>,
+    url: Option<String>,
+    timestamp: Option<String>,
+    colour: Option<u32>,
+    footer: Option<EmbedFooterPacket>,
+    image: Option<String>,
+    thumbnail: Option<String>,
+    author: Option<EmbedAuthorPacket>,
+    fields: Vec<EmbedFieldPacket>,
+}
+
+impl EmbedBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_owned());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_owned());
+        self
+    }
+
+    /// ISO8601 timestamp, e.g. "2019-07-14T12:00:00.000Z"
+    pub fn timestamp(mut self, timestamp: &str) -> Self {
+        self.timestamp = Some(timestamp.to_owned());
+        self
+    }
+
+    /// RGB colour, e.g. 0xDC143C
+    pub fn colour(mut self, colour: u32) -> Self {
+        self.colour = Some(colour);
+        self
+    }
+
+    pub fn footer(mut self, text: &str, icon_url: Option<&str>) -> Self {
+        self.footer = Some(EmbedFooterPacket {
+            text: text.to_owned(),
+            icon_url: icon_url.map(str::to_owned),
+            proxy_icon_url: None,
+        });
+        self
+    }
+
+    pub fn author(mut self, name: &str, url: Option<&str>, icon_url: Option<&str>) -> Self {
+        self.author = Some(EmbedAuthorPacket {
+            name: Some(name.to_owned()),
+            url: url.map(str::to_owned),
+            icon_url: icon_url.map(str::to_owned),
+            proxy_icon_url: None,
+        });
+        self
+    }
+
+    pub fn image(mut self, url: &str) -> Self {
+        self.image = Some(url.to_owned());
+        self
+    }
+
+    pub fn thumbnail(mut self, url: &str) -> Self {
+        self.thumbnail = Some(url.to_owned());
+        self
+    }
+
+    pub fn field(mut self, name: &str, value: &str, inline: bool) -> Self {
+        self.fields.push(EmbedFieldPacket {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            inline: Some(inline),
+        });
+        self
+    }
+
+    /// Checks DISCORD limits and creates embed.
+    pub fn build(self) -> Result<EmbedPacket, BuildError> {
+        self.validate()?;
+
+        Ok(EmbedPacket {
+            title: self.title,
+            embed_type: Some("rich".to_owned()),
+            description: self.description,
+            url: self.url,
+            timestamp: self.timestamp,
+            color: self.colour.map(i64::from),
+            footer: self.footer,
+            image: self.image.map(|url| EmbedImagePacket {
+                url: Some(url),
+                proxy_url: None,
+                height: None,
+                width: None,
+            }),
+            thumbnail: self.thumbnail.map(|url| EmbedThumbnailPacket {
+                url: Some(url),
+                proxy_url: None,
+                height: None,
+                width: None,
+            }),
+            video: None,
+            provider: None,
+            author: self.author,
+            fields: if self.fields.is_empty() {
+                None
+            } else {
+                Some(self.fields)
+            },
+        })
+    }
+
+    fn validate(&self) -> Result<(), BuildError> {
+        let mut total = 0;
+
+        let length = char_count(&self.title);
+        if length > TITLE_LIMIT {
+            return Err(BuildError::TitleTooLong { length });
+        }
+        total += length;
+
+        let length = char_count(&self.description);
+        if length > DESCRIPTION_LIMIT {
+            return Err(BuildError::DescriptionTooLong { length });
+        }
+        total += length;
+
+        if self.fields.len() > FIELDS_LIMIT {
+            return Err(BuildError::TooManyFields {
+                count: self.fields.len(),
+            });
+        }
+        for (index, field) in self.fields.iter().enumerate() {
+            let length = field.name.chars().count();
+            if length > FIELD_NAME_LIMIT {
+                return Err(BuildError::FieldNameTooLong { index, length });
+            }
+            total += length;
+
+            let length = field.value.chars().count();
+            if length > FIELD_VALUE_LIMIT {
+                return Err(BuildError::FieldValueTooLong { index, length });
+            }
+            total += length;
+        }
+
+        if let Some(footer) = &self.footer {
+            let length = footer.text.chars().count();
+            if length > FOOTER_TEXT_LIMIT {
+                return Err(BuildError::FooterTooLong { length });
+            }
+            total += length;
+        }
+
+        if let Some(author) = &self.author {
+            let length = char_count(&author.name);
+            if length > AUTHOR_NAME_LIMIT {
+                return Err(BuildError::AuthorNameTooLong { length });
+            }
+            total += length;
+        }
+
+        if total > EMBED_TOTAL_LIMIT {
+            return Err(BuildError::EmbedTooLong { length: total });
+        }
+
+        Ok(())
+    }
+}
+
+/// Fluent constructor of messages to send.
+#[derive(Debug, Default)]
+pub struct MessageBuilder {
+    content: Option<String>,
+    tts: bool,
+    embed: Option<EmbedBuilder>,
+    allowed_mentions: Option<AllowedMentionsPacket>,
+}
+
+impl MessageBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn content(mut self, content: &str) -> Self {
+        self.content = Some(content.to_owned());
+        self
+    }
+
+    pub fn tts(mut self, tts: bool) -> Self {
+        self.tts = tts;
+        self
+    }
+
+    pub fn embed(mut self, embed: EmbedBuilder) -> Self {
+        self.embed = Some(embed);
+        self
+    }
+
+    /// By default nobody is pinged by built message.
+    pub fn allowed_mentions(mut self, allowed_mentions: AllowedMentionsPacket) -> Self {
+        self.allowed_mentions = Some(allowed_mentions);
+        self
+    }
+
+    /// Checks DISCORD limits and creates request packet.
+    pub fn build(self) -> Result<MessageRequestPacket, BuildError> {
+        let length = char_count(&self.content);
+        if length > CONTENT_LIMIT {
+            return Err(BuildError::ContentTooLong { length });
+        }
+
+        let embed = match self.embed {
+            Some(embed) => Some(embed.build()?),
+            None => None,
+        };
+
+        if length == 0 && embed.is_none() {
+            return Err(BuildError::EmptyMessage);
+        }
+
+        Ok(MessageRequestPacket {
+            content: self.content,
+            nonce: None,
+            tts: self.tts,
+            file: None,
+            embed,
+            payload_json: None,
+            allowed_mentions: Some(
+                self.allowed_mentions
+                    .unwrap_or_else(AllowedMentionsPacket::none),
+            ),
+        })
+    }
+}
+
+fn char_count(text: &Option<String>) -> usize {
+    text.as_ref().map(|t| t.chars().count()).unwrap_or(0)
+}
+
+/// Cuts text to fit DISCORD limit, cut text ends with "…".
+pub fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_owned();
+    }
+    if limit == 0 {
+        return String::new();
+    }
+    let mut cut: String = text.chars().take(limit.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(length: usize) -> String {
+        // Multibyte, so bytes are not counted instead of characters
+        "ж".repeat(length)
+    }
+
+    #[test]
+    fn content_limit() {
+        assert!(MessageBuilder::new().content(&text(2000)).build().is_ok());
+        assert_eq!(
+            MessageBuilder::new().content(&text(2001)).build().err(),
+            Some(BuildError::ContentTooLong { length: 2001 })
+        );
+    }
+
+    #[test]
+    fn empty_message() {
+        assert_eq!(
+            MessageBuilder::new().build().err(),
+            Some(BuildError::EmptyMessage)
+        );
+        assert_eq!(
+            MessageBuilder::new().content("").build().err(),
+            Some(BuildError::EmptyMessage)
+        );
+        assert!(MessageBuilder::new()
+            .embed(EmbedBuilder::new().title("title"))
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn title_limit() {
+        assert!(EmbedBuilder::new().title(&text(256)).build().is_ok());
+        assert_eq!(
+            EmbedBuilder::new().title(&text(257)).build().err(),
+            Some(BuildError::TitleTooLong { length: 257 })
+        );
+    }
+
+    #[test]
+    fn description_limit() {
+        assert!(EmbedBuilder::new().description(&text(4096)).build().is_ok());
+        assert_eq!(
+            EmbedBuilder::new().description(&text(4097)).build().err(),
+            Some(BuildError::DescriptionTooLong { length: 4097 })
+        );
+    }
+
+    #[test]
+    fn fields_limit() {
+        let embed = (0..25).fold(EmbedBuilder::new(), |e, _| e.field("name", "value", false));
+        assert!(embed.clone().build().is_ok());
+        assert_eq!(
+            embed.field("name", "value", false).build().err(),
+            Some(BuildError::TooManyFields { count: 26 })
+        );
+    }
+
+    #[test]
+    fn field_name_and_value_limits() {
+        let embed = EmbedBuilder::new().field("first", "value", true);
+        assert!(embed
+            .clone()
+            .field(&text(256), &text(1024), true)
+            .build()
+            .is_ok());
+        assert_eq!(
+            embed.clone().field(&text(257), "value", true).build().err(),
+            Some(BuildError::FieldNameTooLong {
+                index: 1,
+                length: 257
+            })
+        );
+        assert_eq!(
+            embed.field("name", &text(1025), true).build().err(),
+            Some(BuildError::FieldValueTooLong {
+                index: 1,
+                length: 1025
+            })
+        );
+    }
+
+    #[test]
+    fn footer_limit() {
+        assert!(EmbedBuilder::new()
+            .footer(&text(2048), None)
+            .build()
+            .is_ok());
+        assert_eq!(
+            EmbedBuilder::new().footer(&text(2049), None).build().err(),
+            Some(BuildError::FooterTooLong { length: 2049 })
+        );
+    }
+
+    #[test]
+    fn author_name_limit() {
+        assert!(EmbedBuilder::new()
+            .author(&text(256), None, None)
+            .build()
+            .is_ok());
+        assert_eq!(
+            EmbedBuilder::new()
+                .author(&text(257), None, None)
+                .build()
+                .err(),
+            Some(BuildError::AuthorNameTooLong { length: 257 })
+        );
+    }
+
+    #[test]
+    fn total_limit() {
+        // 4096 + 1024 + 256 + 256 + 256 + 112 = 6000
+        let embed = EmbedBuilder::new()
+            .description(&text(4096))
+            .field(&text(256), &text(1024), false)
+            .title(&text(256))
+            .author(&text(256), None, None);
+        assert!(embed.clone().footer(&text(112), None).build().is_ok());
+        assert_eq!(
+            embed.footer(&text(113), None).build().err(),
+            Some(BuildError::EmbedTooLong { length: 6001 })
+        );
+    }
+
+    #[test]
+    fn embed_errors_fail_message() {
+        assert_eq!(
+            MessageBuilder::new()
+                .content("text")
+                .embed(EmbedBuilder::new().title(&text(257)))
+                .build()
+                .err(),
+            Some(BuildError::TitleTooLong { length: 257 })
+        );
+    }
+
+    #[test]
+    fn truncate_keeps_short_text() {
+        assert_eq!(truncate("", 3), "");
+        assert_eq!(truncate("жжж", 3), "жжж");
+    }
+
+    #[test]
+    fn truncate_counts_characters() {
+        assert_eq!(truncate("жжжж", 3), "жж…");
+        assert_eq!(truncate("👍👍👍👍", 2), "👍…");
+        assert_eq!(truncate(&text(5000), 4096).chars().count(), 4096);
+    }
+
+    #[test]
+    fn truncate_to_nothing() {
+        assert_eq!(truncate("text", 1), "…");
+        assert_eq!(truncate("text", 0), "");
+    }
+}
@@ -0,0 +1,366 @@
+use log::{debug, warn};
+
+use crate::data::CacheConfig;
+use crate::discord::*;
+use crate::lru::LruCache;
+use crate::permissions::{channel_permissions, Permissions};
+use std::collections::HashMap;
+
+/// Everything i have learned from gateway events.
+/// Engine feeds it before handlers are called, handlers only read it.
+#[derive(Debug)]
+pub struct Cache {
+    limits: CacheConfig,
+    /// Myself, from READY and USER_UPDATE
+    myself: Option<UserPacket>,
+    /// Guilds without members and channels, they are kept separately
+    guilds: HashMap<Snowflake, GuildPacket>,
+    /// Guild and private channels
+    channels: HashMap<Snowflake, ChannelPacket>,
+    /// Guild id -> user id -> member
+    members: HashMap<Snowflake, LruCache<Snowflake, GuildMemberPacket>>,
+    /// Everyone i have seen, who is still in some cached guild or private channel
+    users: LruCache<Snowflake, UserPacket>,
+}
+
+impl Cache {
+    pub fn new(limits: &CacheConfig) -> Self {
+        Cache {
+            limits: limits.clone(),
+            myself: None,
+            guilds: HashMap::new(),
+            channels: HashMap::new(),
+            members: HashMap::new(),
+            users: LruCache::new(limits.max_users),
+        }
+    }
+
+    // Queries
+
+    pub fn myself(&self) -> Option<&UserPacket> {
+        self.myself.as_ref()
+    }
+
+    pub fn myself_id(&self) -> Option<&Snowflake> {
+        self.myself.as_ref().map(|u| &u.id)
+    }
+
+    pub fn guild(&self, guild_id: &Snowflake) -> Option<&GuildPacket> {
+        self.guilds.get(guild_id)
+    }
+
+    pub fn guilds(&self) -> impl Iterator<Item = &GuildPacket> {
+        self.guilds.values()
+    }
+
+    /// Guild or private channel.
+    pub fn channel(&self, channel_id: &Snowflake) -> Option<&ChannelPacket> {
+        self.channels.get(channel_id)
+    }
+
+    /// Name of channel, private channels do not have names.
+    pub fn channel_name(&self, channel_id: &Snowflake) -> Option<&str> {
+        self.channel(channel_id)?.name.as_deref()
+    }
+
+    /// All channels of guild, sorted by position.
+    pub fn guild_channels(&self, guild_id: &Snowflake) -> Vec<&ChannelPacket> {
+        let mut channels: Vec<&ChannelPacket> = self
+            .channels
+            .values()
+            .filter(|c| c.guild_id.as_ref() == Some(guild_id))
+            .collect();
+        channels.sort_by_key(|c| c.position.unwrap_or(0));
+        channels
+    }
+
+    pub fn member(&self, guild_id: &Snowflake, user_id: &Snowflake) -> Option<&GuildMemberPacket> {
+        self.members.get(guild_id)?.peek(user_id)
+    }
+
+    /// Role ids of member, without @everyone.
+    pub fn member_roles(&self, guild_id: &Snowflake, user_id: &Snowflake) -> Option<&[Snowflake]> {
+        self.member(guild_id, user_id).map(|m| m.roles.as_slice())
+    }
+
+    pub fn role(&self, guild_id: &Snowflake, role_id: &Snowflake) -> Option<&RolePacket> {
+        self.guild(guild_id)?
+            .roles
+            .iter()
+            .find(|r| r.id.eq(role_id))
+    }
+
+    pub fn user(&self, user_id: &Snowflake) -> Option<&UserPacket> {
+        self.users.peek(user_id)
+    }
+
+    /// Permissions of user with given roles in guild channel.
+    /// None for private and unknown channels.
+    pub fn permissions(
+        &self,
+        channel_id: &Snowflake,
+        user_id: &Snowflake,
+        roles: &[Snowflake],
+    ) -> Option<Permissions> {
+        let channel = self.channel(channel_id)?;
+        let guild = self.guild(channel.guild_id.as_ref()?)?;
+        Some(channel_permissions(guild, user_id, roles, channel))
+    }
+
+    /// Permissions of cached member in guild channel.
+    pub fn member_permissions(
+        &self,
+        channel_id: &Snowflake,
+        user_id: &Snowflake,
+    ) -> Option<Permissions> {
+        let guild_id = self.channel(channel_id)?.guild_id.as_ref()?;
+        let roles = self.member_roles(guild_id, user_id)?;
+        self.permissions(channel_id, user_id, roles)
+    }
+
+    /// "#name (id)" for logs, just id if channel is unknown.
+    pub fn describe_channel(&self, channel_id: &Snowflake) -> String {
+        match self.channel_name(channel_id) {
+            Some(name) => format!("#{} ({})", name, channel_id.0),
+            None => channel_id.0.clone(),
+        }
+    }
+
+    /// "name#discriminator (id)" for logs, just id if user is unknown.
+    pub fn describe_user(&self, user_id: &Snowflake) -> String {
+        match self.user(user_id) {
+            Some(user) => format!("{}#{} ({})", user.username, user.discriminator, user_id.0),
+            None => user_id.0.clone(),
+        }
+    }
+
+    /// "name (id)" for logs, just id if guild is unknown.
+    pub fn describe_guild(&self, guild_id: &Snowflake) -> String {
+        match self.guild(guild_id) {
+            Some(guild) => format!("{} ({})", guild.name, guild_id.0),
+            None => guild_id.0.clone(),
+        }
+    }
+
+    // Updates from gateway events
+
+    pub fn ready(&mut self, ready: &ReadyPacket) {
+        self.myself = Some(ready.user.clone());
+        self.insert_user(&ready.user);
+        for channel in &ready.private_channels {
+            self.insert_channel(channel);
+        }
+    }
+
+    /// GUILD_CREATE brings guild with all of its channels and (some) members.
+    pub fn insert_guild(&mut self, guild: &GuildPacket) {
+        debug!("Guild {} ({:?}) became available", &guild.name, &guild.id);
+        let mut guild = guild.clone();
+
+        // Guild may come back after outage, channels deleted meanwhile are gone
+        self.channels
+            .retain(|_, c| c.guild_id.as_ref() != Some(&guild.id));
+        for mut channel in guild.channels.take().unwrap_or_default() {
+            // Channels inside of guild do not have guild_id
+            channel.guild_id = Some(guild.id.clone());
+            self.insert_channel(&channel);
+        }
+        for member in guild.members.take().unwrap_or_default() {
+            self.insert_member(&guild.id, &member);
+        }
+
+        self.guilds.insert(guild.id.clone(), guild);
+    }
+
+    /// GUILD_UPDATE does not have channels and members, so they are kept.
+    /// Fields, that only GUILD_CREATE sends, are kept too.
+    pub fn update_guild(&mut self, guild: &GuildPacket) {
+        let mut guild = guild.clone();
+        guild.channels = None;
+        guild.members = None;
+        if let Some(old) = self.guilds.remove(&guild.id) {
+            guild.joined_at = guild.joined_at.or(old.joined_at);
+            guild.large = guild.large.or(old.large);
+            guild.member_count = guild.member_count.or(old.member_count);
+            guild.voice_states = guild.voice_states.or(old.voice_states);
+            guild.presences = guild.presences.or(old.presences);
+        }
+        self.guilds.insert(guild.id.clone(), guild);
+    }
+
+    /// I left the guild, or it became unavailable.
+    /// Outage is temporary, so its data is kept until GUILD_CREATE brings fresh one.
+    pub fn remove_guild(&mut self, guild: &UnavailableGuildPacket) {
+        if guild.unavailable == Some(true) {
+            debug!("Guild {:?} is unavailable", &guild.id);
+            if let Some(cached) = self.guilds.get_mut(&guild.id) {
+                cached.unavailable = Some(true);
+            }
+            return;
+        }
+
+        debug!("Guild {:?} is gone", &guild.id);
+        self.guilds.remove(&guild.id);
+        self.channels
+            .retain(|_, c| c.guild_id.as_ref() != Some(&guild.id));
+        if let Some(members) = self.members.remove(&guild.id) {
+            for user_id in members.keys() {
+                self.forget_stranger(user_id);
+            }
+        }
+    }
+
+    pub fn insert_channel(&mut self, channel: &ChannelPacket) {
+        for user in channel.recipients.iter().flatten() {
+            self.insert_user(user);
+        }
+        self.channels.insert(channel.id.clone(), channel.clone());
+    }
+
+    pub fn remove_channel(&mut self, channel: &ChannelPacket) {
+        self.channels.remove(&channel.id);
+    }
+
+    pub fn add_member(&mut self, add: &GuildMemberAddPacket) {
+        if let Some(guild) = self.guilds.get_mut(&add.guild_id) {
+            guild.member_count = guild.member_count.map(|c| c + 1);
+        }
+        self.insert_member(&add.guild_id, &add.member);
+    }
+
+    fn insert_member(&mut self, guild_id: &Snowflake, member: &GuildMemberPacket) {
+        let user = match &member.user {
+            Some(user) => user,
+            None => {
+                warn!("Member without user in guild {:?}. Not cached", guild_id);
+                return;
+            }
+        };
+        self.insert_user(user);
+
+        let limit = self.limits.max_members_per_guild;
+        let myself_id = self.myself.as_ref().map(|u| u.id.clone());
+        let members = self
+            .members
+            .entry(guild_id.clone())
+            .or_insert_with(|| LruCache::new(limit));
+        keep_myself(members, myself_id.as_ref());
+        members.insert(user.id.clone(), member.clone());
+    }
+
+    pub fn update_member(&mut self, update: &GuildMemberUpdatePacket) {
+        self.insert_user(&update.user);
+        let member = self
+            .members
+            .get_mut(&update.guild_id)
+            .and_then(|members| members.get_mut(&update.user.id));
+        match member {
+            Some(member) => {
+                member.user = Some(update.user.clone());
+                member.roles = update.roles.clone();
+                member.nick = update.nick.clone();
+                member.premium_since = update.premium_since.clone();
+            }
+            None => debug!(
+                "Member {:?} of guild {:?} is not cached, update is ignored",
+                &update.user.id, &update.guild_id
+            ),
+        }
+    }
+
+    pub fn remove_member(&mut self, remove: &GuildMemberRemovePacket) {
+        if let Some(guild) = self.guilds.get_mut(&remove.guild_id) {
+            guild.member_count = guild.member_count.map(|c| c - 1);
+        }
+        if let Some(members) = self.members.get_mut(&remove.guild_id) {
+            members.remove(&remove.user.id);
+        }
+        self.forget_stranger(&remove.user.id);
+    }
+
+    /// Role was created or updated.
+    pub fn insert_role(&mut self, role: &GuildRolePacket) {
+        if let Some(guild) = self.guilds.get_mut(&role.guild_id) {
+            guild.roles.retain(|r| r.id.ne(&role.role.id));
+            guild.roles.push(role.role.clone());
+        }
+    }
+
+    pub fn remove_role(&mut self, role: &GuildRoleDeletePacket) {
+        if let Some(guild) = self.guilds.get_mut(&role.guild_id) {
+            guild.roles.retain(|r| r.id.ne(&role.role_id));
+        }
+        if let Some(members) = self.members.get_mut(&role.guild_id) {
+            for member in members.values_mut() {
+                member.roles.retain(|r| r.ne(&role.role_id));
+            }
+        }
+    }
+
+    /// Presence may bring new username, roles and nickname.
+    pub fn update_presence(&mut self, presence: &PresenceUpdatePacket) {
+        let partial = &presence.user;
+        if let Some(user) = self.users.get_mut(&partial.id) {
+            if let Some(username) = &partial.username {
+                user.username = username.clone();
+            }
+            if let Some(discriminator) = &partial.discriminator {
+                user.discriminator = discriminator.clone();
+            }
+            if partial.avatar.is_some() {
+                user.avatar = partial.avatar.clone();
+            }
+        }
+
+        let member = presence
+            .guild_id
+            .as_ref()
+            .and_then(|guild_id| self.members.get_mut(guild_id))
+            .and_then(|members| members.get_mut(&partial.id));
+        if let Some(member) = member {
+            if let Some(roles) = &presence.roles {
+                member.roles = roles.clone();
+            }
+            if presence.nick.is_some() {
+                member.nick = presence.nick.clone();
+            }
+        }
+    }
+
+    /// USER_UPDATE is only about myself.
+    pub fn update_user(&mut self, user: &UserPacket) {
+        self.myself = Some(user.clone());
+        self.insert_user(user);
+    }
+
+    fn insert_user(&mut self, user: &UserPacket) {
+        let myself_id = self.myself.as_ref().map(|u| u.id.clone());
+        keep_myself(&mut self.users, myself_id.as_ref());
+        self.users.insert(user.id.clone(), user.clone());
+    }
+
+    /// Forgets user, that is not in any cached guild or private channel anymore.
+    fn forget_stranger(&mut self, user_id: &Snowflake) {
+        if self.myself_id() == Some(user_id) {
+            return;
+        }
+        let is_member = self.members.values().any(|m| m.contains_key(user_id));
+        let is_recipient = self
+            .channels
+            .values()
+            .flat_map(|c| c.recipients.iter().flatten())
+            .any(|u| u.id.eq(user_id));
+        if !is_member && !is_recipient {
+            debug!("User {:?} is not known anymore", user_id);
+            self.users.remove(user_id);
+        }
+    }
+}
+
+/// I am always cached, my permissions depend on it.
+/// Being the most recently used, i am not the one, who is forgotten to make space.
+fn keep_myself<V>(cache: &mut LruCache<Snowflake, V>, myself_id: Option<&Snowflake>) {
+    if let Some(myself_id) = myself_id {
+        cache.get_mut(myself_id);
+    }
+}
@@ -0,0 +1,153 @@
+use log::debug;
+
+use crate::discord::*;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use futures::sync::oneshot;
+use futures::Future;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Why nothing was collected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CollectError {
+    /// Nothing matched in time
+    Timeout,
+}
+
+impl std::fmt::Display for CollectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CollectError::Timeout => write!(f, "Nobody answered in time"),
+        }
+    }
+}
+
+impl std::error::Error for CollectError {}
+
+pub type CollectFuture<T> = Box<dyn Future<Item = T, Error = CollectError>>;
+
+/// Somebody, who waits for event.
+struct Wait<T> {
+    filter: Box<dyn Fn(&T) -> bool>,
+    sender: oneshot::Sender<T>,
+    until: Instant,
+}
+
+impl<T> Wait<T> {
+    fn is_over(&self, now: Instant) -> bool {
+        self.until <= now || self.sender.is_canceled()
+    }
+}
+
+/// Gives event to the first wait, that accepts it.
+fn deliver<T: Clone>(waits: &mut Vec<Wait<T>>, event: &T) {
+    if let Some(i) = waits.iter().position(|w| (w.filter)(event)) {
+        let wait = waits.remove(i);
+        // Receiver may be gone already, nobody cares then
+        let _ = wait.sender.send(event.clone());
+    }
+}
+
+#[derive(Default)]
+struct Waits {
+    messages: Vec<Wait<MessagePacket>>,
+    reactions: Vec<Wait<MessageReactionPacket>>,
+}
+
+/// Lets commands wait for the next message or reaction, e.g. an answer to their question.
+/// Collected events still go to all other handlers as usual.
+/// Cheap to clone, all clones collect from the same events.
+#[derive(Clone, Default)]
+pub struct Collector {
+    waits: Rc<RefCell<Waits>>,
+}
+
+impl Collector {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The next message, that passes filter within timeout.
+    pub fn next_message<F>(&self, timeout: Duration, filter: F) -> CollectFuture<MessagePacket>
+    where
+        F: Fn(&MessagePacket) -> bool + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.waits.borrow_mut().messages.push(Wait {
+            filter: Box::new(filter),
+            sender,
+            until: Instant::now() + timeout,
+        });
+        Box::new(receiver.map_err(|_| CollectError::Timeout))
+    }
+
+    /// The next reaction, that passes filter within timeout.
+    pub fn next_reaction<F>(
+        &self,
+        timeout: Duration,
+        filter: F,
+    ) -> CollectFuture<MessageReactionPacket>
+    where
+        F: Fn(&MessageReactionPacket) -> bool + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.waits.borrow_mut().reactions.push(Wait {
+            filter: Box::new(filter),
+            sender,
+            until: Instant::now() + timeout,
+        });
+        Box::new(receiver.map_err(|_| CollectError::Timeout))
+    }
+
+    /// The next message of the same author in the same channel.
+    pub fn reply_to(
+        &self,
+        message: &MessagePacket,
+        timeout: Duration,
+    ) -> CollectFuture<MessagePacket> {
+        let author_id = message.author.id.clone();
+        let channel_id = message.channel_id.clone();
+        self.next_message(timeout, move |m| {
+            m.author.id.eq(&author_id) && m.channel_id.eq(&channel_id)
+        })
+    }
+}
+
+impl EventHandler for Collector {
+    fn name(&self) -> &'static str {
+        "collector"
+    }
+
+    fn on_message(&mut self, ctx: &EventContext, message: &MessagePacket) -> HandlerResult {
+        if message.author.id.ne(ctx.myself_id) {
+            deliver(&mut self.waits.borrow_mut().messages, message);
+        }
+        Ok(())
+    }
+
+    fn on_reaction_add(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        if reaction.user_id.ne(ctx.myself_id) {
+            deliver(&mut self.waits.borrow_mut().reactions, reaction);
+        }
+        Ok(())
+    }
+
+    /// Dropped senders end waits with timeout.
+    fn on_tick(&mut self, _ctx: &EventContext) -> HandlerResult {
+        let now = Instant::now();
+        let mut waits = self.waits.borrow_mut();
+        let count = waits.messages.len() + waits.reactions.len();
+        waits.messages.retain(|w| !w.is_over(now));
+        waits.reactions.retain(|w| !w.is_over(now));
+        let expired = count - waits.messages.len() - waits.reactions.len();
+        if expired > 0 {
+            debug!("{} waits are over", expired);
+        }
+        Ok(())
+    }
+}
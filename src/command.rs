@@ -0,0 +1,1161 @@
+use log::{debug, error, warn};
+
+use crate::builder::MessageBuilder;
+use crate::cache::Cache;
+use crate::data::Config;
+use crate::discord::*;
+use crate::guard::*;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::i18n::{self, Language, MessageArgs};
+use crate::parser::{join, tokenize, Token};
+use crate::permissions::Permissions;
+use crate::rest::{self, Rest, RestError};
+use crate::settings::{Key, Scope, Settings, SettingsError};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Prefixes chosen by admins of guild
+pub const PREFIXES: Key<Vec<String>> = Key::new("prefixes");
+/// Whether message, that starts with my mention, is a command in guild
+pub const MENTION_PREFIX: Key<bool> = Key::new("mention_prefix");
+/// Prefixes one guild may have
+const MAX_PREFIXES: usize = 5;
+/// Longer prefixes are typos rather than prefixes
+const MAX_PREFIX_LENGTH: usize = 10;
+/// Longer durations are typos too, and they would overflow timestamps
+const MAX_DURATION: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Kind of command argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgumentType {
+    /// Signed number
+    Integer,
+    /// Single word or "quoted text"
+    Text,
+    /// Everything till the end of message
+    Rest,
+    /// User mention or id
+    User,
+    /// Role mention or id
+    Role,
+    /// Channel mention or id
+    Channel,
+    /// Duration like 90s, 15m, 1h30m, 2d or 1w
+    Duration,
+}
+
+impl ArgumentType {
+    fn name(&self) -> &'static str {
+        match self {
+            ArgumentType::Integer => "number",
+            ArgumentType::Text => "text",
+            ArgumentType::Rest => "text...",
+            ArgumentType::User => "@user",
+            ArgumentType::Role => "@role",
+            ArgumentType::Channel => "#channel",
+            ArgumentType::Duration => "duration",
+        }
+    }
+}
+
+/// Declared argument of command.
+#[derive(Debug, Clone)]
+pub struct Argument {
+    pub name: &'static str,
+    pub kind: ArgumentType,
+    pub optional: bool,
+}
+
+/// Parsed argument value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Text(String),
+    User(Snowflake),
+    Role(Snowflake),
+    Channel(Snowflake),
+    Duration(Duration),
+}
+
+/// Parsed arguments of command, accessed by argument name.
+#[derive(Debug, Default)]
+pub struct Args {
+    values: HashMap<&'static str, Value>,
+}
+
+impl Args {
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        match self.values.get(name) {
+            Some(Value::Integer(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(Value::Text(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn user(&self, name: &str) -> Option<&Snowflake> {
+        match self.values.get(name) {
+            Some(Value::User(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn role(&self, name: &str) -> Option<&Snowflake> {
+        match self.values.get(name) {
+            Some(Value::Role(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&Snowflake> {
+        match self.values.get(name) {
+            Some(Value::Channel(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn duration(&self, name: &str) -> Option<Duration> {
+        match self.values.get(name) {
+            Some(Value::Duration(v)) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+/// Everything command handler may need.
+pub struct CommandContext<'a> {
+    /// Message that triggered command
+    pub message: &'a MessagePacket,
+    /// Bot identifier
+    pub myself_id: &'a Snowflake,
+    /// Everything engine knows about DISCORD
+    pub cache: &'a Cache,
+    /// Persistent settings of guilds, channels and users
+    pub settings: &'a Settings,
+    /// All known commands
+    pub commands: &'a CommandRegistry,
+    /// Handle to DISCORD REST API
+    pub rest: Rest,
+    /// Language of answers
+    pub language: Language,
+}
+
+impl<'a> CommandContext<'a> {
+    /// Guild of message, if it is known.
+    pub fn guild(&self) -> Option<&'a GuildPacket> {
+        self.cache.guild(self.message.guild_id.as_ref()?)
+    }
+
+    /// Permissions of message author in the channel of message.
+    /// None for direct messages and unknown guilds.
+    pub fn author_permissions(&self) -> Option<Permissions> {
+        let member = self.message.member.as_ref()?;
+        self.cache.permissions(
+            &self.message.channel_id,
+            &self.message.author.id,
+            &member.roles,
+        )
+    }
+
+    /// My permissions in the channel of message.
+    /// None for direct messages and unknown guilds.
+    pub fn bot_permissions(&self) -> Option<Permissions> {
+        self.cache
+            .member_permissions(&self.message.channel_id, self.myself_id)
+    }
+
+    /// Prefix to show in usage examples.
+    /// Without prefixes my mention is the only way to run commands.
+    pub fn prefix(&self) -> String {
+        let guild_id = self.message.guild_id.as_ref();
+        match self
+            .commands
+            .prefixes(self.settings, guild_id)
+            .into_iter()
+            .next()
+        {
+            Some(prefix) => prefix,
+            None => format!("<@{}> ", self.myself_id.0),
+        }
+    }
+
+    /// Message from catalog in language of answers.
+    pub fn text(&self, key: &str, args: &MessageArgs) -> String {
+        i18n::text(self.language, key, args)
+    }
+
+    /// Plural message from catalog in language of answers.
+    pub fn plural(&self, key: &str, count: i64, args: &MessageArgs) -> String {
+        i18n::plural(self.language, key, count, args)
+    }
+
+    /// Sends plain text to the channel of command.
+    pub fn reply(&self, text: &str) {
+        self.rest.say(
+            &self.message.channel_id,
+            &MessageRequestPacket::simple_text(text),
+        );
+    }
+
+    /// Sends built message to the channel of command.
+    pub fn send(&self, message: MessageBuilder) -> Result<(), CommandError> {
+        let packet = message
+            .build()
+            .map_err(|e| CommandError::Failed(RestError::Build(e).to_string()))?;
+        self.rest.say(&self.message.channel_id, &packet);
+        Ok(())
+    }
+}
+
+/// Why command was not executed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    /// Arguments do not match command signature
+    Usage(String),
+    /// Command failed on its own
+    Failed(String),
+}
+
+pub type CommandResult = Result<(), CommandError>;
+
+pub type CommandHandler = Box<dyn Fn(&CommandContext, &Args) -> CommandResult>;
+
+/// Named command with its signature and handler.
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: Vec<&'static str>,
+    pub description: &'static str,
+    pub arguments: Vec<Argument>,
+    /// Conditions to be met before execution
+    pub guards: Vec<Guard>,
+    /// How often command can be used
+    pub cooldown: Option<Cooldown>,
+    handler: CommandHandler,
+}
+
+impl Command {
+    pub fn new<F>(name: &'static str, description: &'static str, handler: F) -> Self
+    where
+        F: Fn(&CommandContext, &Args) -> CommandResult + 'static,
+    {
+        Command {
+            name,
+            aliases: vec![],
+            description,
+            arguments: vec![],
+            guards: vec![],
+            cooldown: None,
+            handler: Box::new(handler),
+        }
+    }
+
+    pub fn alias(mut self, alias: &'static str) -> Self {
+        self.aliases.push(alias);
+        self
+    }
+
+    pub fn argument(mut self, name: &'static str, kind: ArgumentType) -> Self {
+        self.arguments.push(Argument {
+            name,
+            kind,
+            optional: false,
+        });
+        self
+    }
+
+    pub fn optional_argument(mut self, name: &'static str, kind: ArgumentType) -> Self {
+        self.arguments.push(Argument {
+            name,
+            kind,
+            optional: true,
+        });
+        self
+    }
+
+    pub fn guard(mut self, guard: Guard) -> Self {
+        self.guards.push(guard);
+        self
+    }
+
+    /// Permissions author must have to use command.
+    pub fn permissions(self, permissions: Permissions) -> Self {
+        self.guard(Guard::UserPermissions(permissions))
+    }
+
+    /// Permissions i must have to execute command.
+    pub fn bot_permissions(self, permissions: Permissions) -> Self {
+        self.guard(Guard::BotPermissions(permissions))
+    }
+
+    pub fn cooldown(mut self, bucket: Bucket, uses: usize, per: Duration) -> Self {
+        self.cooldown = Some(Cooldown { bucket, uses, per });
+        self
+    }
+
+    /// Whether author of message may use this command.
+    pub fn is_allowed(&self, ctx: &CommandContext) -> bool {
+        self.guards
+            .iter()
+            .filter(|g| g.is_about_author())
+            .all(|g| g.check(ctx).is_ok())
+    }
+
+    /// Signature of arguments, e.g. "<user: @user> [reason: text...]".
+    pub fn signature(&self) -> String {
+        self.arguments
+            .iter()
+            .map(|a| {
+                if a.optional {
+                    format!("[{}: {}]", a.name, a.kind.name())
+                } else {
+                    format!("<{}: {}>", a.name, a.kind.name())
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+    }
+
+    /// Tells author why command did not work.
+    fn report(&self, ctx: &CommandContext, error: CommandError) {
+        match error {
+            CommandError::Usage(reason) => {
+                let usage = format!("{}{} {}", ctx.prefix(), self.name, self.signature());
+                ctx.reply(&ctx.text(
+                    "command-usage",
+                    &[("reason", &reason), ("usage", &usage.trim_end())],
+                ))
+            }
+            CommandError::Failed(reason) => {
+                warn!("Command {} failed: {}", self.name, reason);
+                ctx.reply(&ctx.text("command-failed", &[("reason", &reason)]));
+            }
+        }
+    }
+
+    /// Parses arguments according to signature.
+    fn parse(&self, ctx: &CommandContext, mut input: &str) -> Result<Args, CommandError> {
+        let mut args = Args::default();
+
+        for argument in &self.arguments {
+            let raw = if argument.kind == ArgumentType::Rest {
+                let rest = input.trim();
+                input = "";
+                if rest.is_empty() {
+                    None
+                } else {
+                    Some(rest.to_owned())
+                }
+            } else {
+                next_word(&mut input)
+            };
+
+            let raw = match raw {
+                Some(raw) => raw,
+                None if argument.optional => continue,
+                None => {
+                    return Err(CommandError::Usage(
+                        ctx.text("argument-missing", &[("argument", &argument.name)]),
+                    ))
+                }
+            };
+
+            let value = parse_value(&argument.kind, &raw).ok_or_else(|| {
+                CommandError::Usage(ctx.text(
+                    "argument-invalid",
+                    &[
+                        ("value", &raw),
+                        ("kind", &argument.kind.name()),
+                        ("argument", &argument.name),
+                    ],
+                ))
+            })?;
+            args.values.insert(argument.name, value);
+        }
+
+        if !input.trim().is_empty() {
+            return Err(CommandError::Usage(
+                ctx.text("argument-unexpected", &[("arguments", &input.trim())]),
+            ));
+        }
+
+        Ok(args)
+    }
+}
+
+/// All known commands and the way they are triggered.
+pub struct CommandRegistry {
+    prefix: String,
+    mention_prefix: bool,
+    owners: Vec<Snowflake>,
+    commands: Vec<Command>,
+    cooldowns: CooldownTracker,
+}
+
+impl std::fmt::Debug for CommandRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CommandRegistry")
+            .field("prefix", &self.prefix)
+            .field("mention_prefix", &self.mention_prefix)
+            .field(
+                "commands",
+                &self.commands.iter().map(|c| c.name).collect::<Vec<&str>>(),
+            )
+            .finish()
+    }
+}
+
+impl CommandRegistry {
+    pub fn new(config: &Config) -> Self {
+        CommandRegistry {
+            prefix: config.prefix.clone(),
+            mention_prefix: config.mention_prefix,
+            owners: config.owners.clone(),
+            commands: vec![],
+            cooldowns: CooldownTracker::default(),
+        }
+    }
+
+    pub fn register(&mut self, command: Command) {
+        if let Some(clash) = self
+            .commands
+            .iter()
+            .find(|c| c.matches(command.name) || command.aliases.iter().any(|a| c.matches(a)))
+        {
+            warn!(
+                "Command {} clashes with already registered command {}",
+                command.name, clash.name
+            );
+        }
+        self.commands.push(command);
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Command> {
+        self.commands.iter().find(|c| c.matches(name))
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Prefixes of guild, or default prefix from config. Longer ones go first.
+    pub fn prefixes(&self, settings: &Settings, guild_id: Option<&Snowflake>) -> Vec<String> {
+        let mut prefixes = guild_id
+            .and_then(|id| guild_setting(settings, &PREFIXES, id))
+            .unwrap_or_else(|| vec![self.prefix.clone()]);
+        prefixes.retain(|p| !p.is_empty());
+        // "!!" would be taken for "!" otherwise
+        prefixes.sort_by(|a, b| b.len().cmp(&a.len()));
+        prefixes
+    }
+
+    /// Whether my mention works as prefix in guild.
+    pub fn mention_prefix(&self, settings: &Settings, guild_id: Option<&Snowflake>) -> bool {
+        guild_id
+            .and_then(|id| guild_setting(settings, &MENTION_PREFIX, id))
+            .unwrap_or(self.mention_prefix)
+    }
+
+    pub fn owners(&self) -> &[Snowflake] {
+        &self.owners
+    }
+
+    /// Finds how message was addressed to me and returns the rest of it.
+    /// Mention counts only at the start, so talking about me is not a command.
+    /// In direct messages nothing special is needed.
+    pub fn strip_trigger(
+        &self,
+        message: &MessagePacket,
+        myself_id: &Snowflake,
+        settings: &Settings,
+    ) -> Option<Trigger> {
+        let content = message.content.trim_start();
+        let guild_id = message.guild_id.as_ref();
+
+        let prefixes = self.prefixes(settings, guild_id);
+        if let Some(prefix) = prefixes.iter().find(|p| content.starts_with(p.as_str())) {
+            return Some(Trigger::Prefix(content[prefix.len()..].to_owned()));
+        }
+
+        if self.mention_prefix(settings, guild_id) {
+            let tokens = tokenize(content);
+            if let Some((first, rest)) = tokens.split_first() {
+                if first.is_user_mention(myself_id) {
+                    return Some(Trigger::Mention(join(rest).trim().to_owned()));
+                }
+            }
+        }
+
+        if message.is_direct() {
+            return Some(Trigger::Direct(content.to_owned()));
+        }
+
+        None
+    }
+
+    /// Runs command written in text.
+    /// Returns false if there is no such command.
+    pub fn execute(&self, ctx: &CommandContext, text: &str) -> bool {
+        let mut input = text.trim_start();
+        let name = match next_word(&mut input) {
+            Some(name) => name,
+            None => return false,
+        };
+
+        let command = match self.find(&name) {
+            Some(command) => command,
+            None => {
+                debug!("There is no command {}", name);
+                return false;
+            }
+        };
+
+        if let Some(reason) = command.guards.iter().find_map(|g| g.check(ctx).err()) {
+            debug!("Command {} was not allowed: {}", command.name, reason);
+            ctx.reply(&ctx.text(
+                "command-not-allowed",
+                &[("reason", &reason), ("command", &command.name)],
+            ));
+            return true;
+        }
+
+        debug!(
+            "Executing command {} with arguments {:?}",
+            command.name, input
+        );
+        let args = match command.parse(ctx, input) {
+            Ok(args) => args,
+            Err(e) => {
+                command.report(ctx, e);
+                return true;
+            }
+        };
+
+        // Typo in arguments does not use up the cooldown
+        if let Some(cooldown) = &command.cooldown {
+            if let Err(wait) = self.cooldowns.hit(command.name, cooldown, ctx) {
+                debug!("Command {} is on cooldown", command.name);
+                ctx.reply(&ctx.plural(
+                    "command-cooldown",
+                    wait.as_secs() as i64 + 1,
+                    &[("command", &command.name)],
+                ));
+                return true;
+            }
+        }
+
+        if let Err(e) = (command.handler)(ctx, &args) {
+            command.report(ctx, e);
+        }
+
+        true
+    }
+}
+
+/// Commands are just one more handler of messages.
+impl EventHandler for CommandRegistry {
+    fn name(&self) -> &'static str {
+        "commands"
+    }
+
+    fn on_message(&mut self, ctx: &EventContext, message: &MessagePacket) -> HandlerResult {
+        if message.author.id.eq(ctx.myself_id) {
+            debug!("My own message. Ignoring packet");
+            return Ok(());
+        }
+
+        if message.author.bot.unwrap_or(false) {
+            debug!("Message from other bot. Ignoring packet");
+            return Ok(());
+        }
+
+        let trigger = match self.strip_trigger(message, ctx.myself_id, ctx.settings) {
+            Some(trigger) => trigger,
+            None => {
+                debug!("Message was not addressed to me. Ignoring packet");
+                return Ok(());
+            }
+        };
+        debug!("Message was addressed to me: {:?}", &trigger);
+
+        let command_ctx = CommandContext {
+            message,
+            myself_id: ctx.myself_id,
+            cache: ctx.cache,
+            settings: ctx.settings,
+            commands: self,
+            rest: ctx.rest.clone(),
+            language: i18n::language(ctx.settings, message.guild_id.as_ref(), &message.author.id),
+        };
+
+        match trigger {
+            Trigger::Prefix(text) => {
+                self.execute(&command_ctx, &text);
+            }
+            // People talk to me, if it is not a command, lets pong
+            Trigger::Mention(text) | Trigger::Direct(text) => {
+                if !self.execute(&command_ctx, &text) {
+                    echo(&command_ctx, &text);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The way message was addressed to me, with the rest of message.
+#[derive(Debug, PartialEq)]
+pub enum Trigger {
+    Prefix(String),
+    Mention(String),
+    Direct(String),
+}
+
+/// Takes single word or "quoted text" from the input.
+/// Quotes can be escaped with backslash.
+pub fn next_word(input: &mut &str) -> Option<String> {
+    let trimmed = input.trim_start();
+    if trimmed.is_empty() {
+        *input = trimmed;
+        return None;
+    }
+
+    let mut word = String::new();
+    let mut chars = trimmed.char_indices();
+    if trimmed.starts_with('"') {
+        chars.next();
+        let mut escaped = false;
+        for (i, c) in chars {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => {
+                    *input = &trimmed[i + 1..];
+                    return Some(word);
+                }
+                _ => {
+                    escaped = false;
+                    word.push(c);
+                }
+            }
+        }
+        // No closing quote, lets take everything
+        *input = "";
+        return Some(word);
+    }
+
+    let end = trimmed
+        .find(char::is_whitespace)
+        .unwrap_or_else(|| trimmed.len());
+    word.push_str(&trimmed[..end]);
+    *input = &trimmed[end..];
+    Some(word)
+}
+
+/// Parses one argument of given type.
+pub fn parse_value(kind: &ArgumentType, raw: &str) -> Option<Value> {
+    match kind {
+        ArgumentType::Integer => raw.parse().ok().map(Value::Integer),
+        ArgumentType::Text | ArgumentType::Rest => Some(Value::Text(raw.to_owned())),
+        ArgumentType::User => parse_mention(raw, |t| match t {
+            Token::UserMention { id, .. } => Some(id),
+            _ => None,
+        })
+        .map(Value::User),
+        ArgumentType::Role => parse_mention(raw, |t| match t {
+            Token::RoleMention(id) => Some(id),
+            _ => None,
+        })
+        .map(Value::Role),
+        ArgumentType::Channel => parse_mention(raw, |t| match t {
+            Token::ChannelMention(id) => Some(id),
+            _ => None,
+        })
+        .map(Value::Channel),
+        ArgumentType::Duration => parse_duration(raw).map(Value::Duration),
+    }
+}
+
+/// Mention of exactly one entity, or raw id.
+fn parse_mention<F>(raw: &str, extract: F) -> Option<Snowflake>
+where
+    F: Fn(Token) -> Option<Snowflake>,
+{
+    if !raw.is_empty() && raw.chars().all(|c| c.is_ascii_digit()) {
+        return Some(Snowflake(raw.to_owned()));
+    }
+
+    let mut tokens = tokenize(raw);
+    if tokens.len() != 1 {
+        return None;
+    }
+    extract(tokens.remove(0))
+}
+
+/// Custom emoji becomes "name:id", unicode emoji stays as is.
+pub fn parse_emoji(raw: &str) -> Option<String> {
+    match tokenize(raw).as_slice() {
+        [Token::Emoji { name, id, .. }] => Some(format!("{}:{}", name, id.0)),
+        [Token::Text(t)] if is_unicode_emoji(t) => Some(t.to_string()),
+        _ => None,
+    }
+}
+
+/// Role, that bot may give members by command author's choice.
+/// It can not be above the author, otherwise moderators would give themselves any role.
+pub fn check_assignable_role(
+    ctx: &CommandContext,
+    guild_id: &Snowflake,
+    role_id: &Snowflake,
+) -> CommandResult {
+    let role = ctx
+        .cache
+        .role(guild_id, role_id)
+        .ok_or_else(|| CommandError::Usage(ctx.text("role-not-found", &[])))?;
+    // @everyone has id of guild
+    if role.managed || role.id.eq(guild_id) {
+        return Err(CommandError::Usage(ctx.text("role-not-assignable", &[])));
+    }
+
+    let is_owner = ctx
+        .cache
+        .guild(guild_id)
+        .map(|g| g.owner_id.eq(&ctx.message.author.id))
+        .unwrap_or(false);
+    let author_roles = ctx
+        .message
+        .member
+        .as_ref()
+        .map(|m| m.roles.as_slice())
+        .unwrap_or(&[]);
+    if !is_owner && role.position >= highest_position(ctx, guild_id, author_roles) {
+        return Err(CommandError::Usage(ctx.text("role-above-you", &[])));
+    }
+
+    let my_roles = ctx
+        .cache
+        .member_roles(guild_id, ctx.myself_id)
+        .unwrap_or(&[]);
+    if role.position >= highest_position(ctx, guild_id, my_roles) {
+        return Err(CommandError::Usage(ctx.text("role-above-me", &[])));
+    }
+    Ok(())
+}
+
+/// Position of the highest of roles, @everyone is the lowest one.
+fn highest_position(ctx: &CommandContext, guild_id: &Snowflake, roles: &[Snowflake]) -> i64 {
+    roles
+        .iter()
+        .filter_map(|r| ctx.cache.role(guild_id, r))
+        .map(|r| r.position)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Parses durations like 90s, 15m, 1h30m, 2d or 1w.
+pub fn parse_duration(raw: &str) -> Option<Duration> {
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+
+    for c in raw.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let multiplier = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        let value: u64 = number.parse().ok()?;
+        seconds = seconds.checked_add(value.checked_mul(multiplier)?)?;
+        number.clear();
+    }
+
+    let duration = Duration::from_secs(seconds);
+    if !number.is_empty() || seconds == 0 || duration > MAX_DURATION {
+        return None;
+    }
+
+    Some(duration)
+}
+
+/// Setting of guild, broken settings are logged and treated as absent.
+fn guild_setting<T: serde::de::DeserializeOwned>(
+    settings: &Settings,
+    key: &Key<T>,
+    guild_id: &Snowflake,
+) -> Option<T> {
+    settings
+        .get(key, Scope::Guild, guild_id)
+        .unwrap_or_else(|e| {
+            error!("Failed to get {} of {:?}: {}", key.name, guild_id, e);
+            None
+        })
+}
+
+/// Commands that are always available.
+pub fn register_builtin(registry: &mut CommandRegistry) {
+    registry.register(
+        Command::new("echo", "Repeats your text", |ctx, args| {
+            echo(ctx, args.text("text").unwrap_or(""));
+            Ok(())
+        })
+        .alias("say")
+        .argument("text", ArgumentType::Rest)
+        .cooldown(Bucket::User, 3, Duration::from_secs(10)),
+    );
+
+    registry.register(
+        Command::new("prefix", "Shows prefixes of commands here", |ctx, _args| {
+            show_prefixes(ctx);
+            Ok(())
+        })
+        .alias("prefixes"),
+    );
+
+    registry.register(
+        Command::new(
+            "setprefix",
+            "Replaces prefixes of this guild, e.g. `setprefix ! \"bot, \"`",
+            set_prefixes,
+        )
+        .argument("prefixes", ArgumentType::Rest)
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+
+    registry.register(
+        Command::new(
+            "resetprefix",
+            "Returns default prefix to this guild",
+            |ctx, _args| {
+                let guild_id = ctx.message.guild_id.as_ref().unwrap();
+                ctx.settings
+                    .remove(&PREFIXES, Scope::Guild, guild_id)
+                    .map_err(|e| setting_failed(ctx, &PREFIXES, guild_id, e))?;
+                show_prefixes(ctx);
+                Ok(())
+            },
+        )
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+
+    registry.register(
+        Command::new(
+            "mentionprefix",
+            "Turns on or off commands, that start with my mention",
+            |ctx, args| {
+                let enabled = match args.text("state").unwrap().to_lowercase().as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(CommandError::Usage(ctx.text("mention-prefix-state", &[]))),
+                };
+                let guild_id = ctx.message.guild_id.as_ref().unwrap();
+                ctx.settings
+                    .set(&MENTION_PREFIX, Scope::Guild, guild_id, &enabled)
+                    .map_err(|e| setting_failed(ctx, &MENTION_PREFIX, guild_id, e))?;
+                show_prefixes(ctx);
+                Ok(())
+            },
+        )
+        .argument("state", ArgumentType::Text)
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+}
+
+fn show_prefixes(ctx: &CommandContext) {
+    let guild_id = ctx.message.guild_id.as_ref();
+    let prefixes = ctx
+        .commands
+        .prefixes(ctx.settings, guild_id)
+        .iter()
+        .map(|p| format!("`{}`", p.replace('`', "'")))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let prefixes = if prefixes.is_empty() {
+        ctx.text("prefixes-none", &[])
+    } else {
+        prefixes
+    };
+    let key = if ctx.commands.mention_prefix(ctx.settings, guild_id) {
+        "prefixes-mention-on"
+    } else {
+        "prefixes-mention-off"
+    };
+    ctx.reply(&ctx.text(key, &[("prefixes", &prefixes)]));
+}
+
+fn set_prefixes(ctx: &CommandContext, args: &Args) -> CommandResult {
+    let mut input = args.text("prefixes").unwrap();
+    let mut prefixes = vec![];
+    while let Some(prefix) = next_word(&mut input) {
+        if prefix.trim().is_empty() || prefix.chars().count() > MAX_PREFIX_LENGTH {
+            return Err(CommandError::Usage(ctx.plural(
+                "prefix-length",
+                MAX_PREFIX_LENGTH as i64,
+                &[],
+            )));
+        }
+        if !prefixes.contains(&prefix) {
+            prefixes.push(prefix);
+        }
+    }
+    if prefixes.len() > MAX_PREFIXES {
+        return Err(CommandError::Usage(ctx.plural(
+            "prefixes-too-many",
+            MAX_PREFIXES as i64,
+            &[],
+        )));
+    }
+
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    ctx.settings
+        .set(&PREFIXES, Scope::Guild, guild_id, &prefixes)
+        .map_err(|e| setting_failed(ctx, &PREFIXES, guild_id, e))?;
+    show_prefixes(ctx);
+    Ok(())
+}
+
+fn setting_failed<T>(
+    ctx: &CommandContext,
+    key: &Key<T>,
+    guild_id: &Snowflake,
+    e: SettingsError,
+) -> CommandError {
+    error!("Failed to save {} of {:?}: {}", key.name, guild_id, e);
+    CommandError::Failed(ctx.text("setting-failed", &[]))
+}
+
+/// Respond with same text.
+/// Only author is allowed to be pinged, whatever was written in message.
+pub fn echo(ctx: &CommandContext, text: &str) {
+    let author = &ctx.message.author.id;
+    let mut request_packet = MessageRequestPacket::simple_text(&format!(
+        "<@{}> {}",
+        author.0,
+        crate::sanitize::escape_mentions(text)
+    ));
+    request_packet.allowed_mentions = Some(AllowedMentionsPacket::users(vec![author.clone()]));
+    rest::spawn(
+        ctx.rest
+            .send_message(&ctx.message.channel_id, &request_packet),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn words(mut input: &str) -> Vec<String> {
+        let mut words = vec![];
+        while let Some(word) = next_word(&mut input) {
+            words.push(word);
+        }
+        words
+    }
+
+    #[test]
+    fn words_are_split_by_whitespace() {
+        assert_eq!(words("  one two\tthree\n"), vec!["one", "two", "three"]);
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn quoted_text_is_one_word() {
+        assert_eq!(
+            words(r#"say "hello world" now"#),
+            vec!["say", "hello world", "now"]
+        );
+        assert_eq!(words(r#""""#), vec![""]);
+    }
+
+    #[test]
+    fn quotes_are_escaped_with_backslash() {
+        assert_eq!(
+            words(r#""she said \"hi\"" "back\\slash""#),
+            vec![r#"she said "hi""#, r"back\slash"]
+        );
+    }
+
+    #[test]
+    fn unterminated_quote_takes_everything() {
+        let mut input = r#"one "two three"#;
+        assert_eq!(next_word(&mut input), Some("one".to_owned()));
+        assert_eq!(next_word(&mut input), Some("two three".to_owned()));
+        assert_eq!(input, "");
+        assert_eq!(next_word(&mut input), None);
+    }
+
+    #[test]
+    fn rest_of_input_is_left() {
+        let mut input = "  name   the rest";
+        assert_eq!(next_word(&mut input), Some("name".to_owned()));
+        assert_eq!(input, "   the rest");
+    }
+
+    #[test]
+    fn multibyte_words() {
+        assert_eq!(words("привет «мир» 👍"), vec!["привет", "«мир»", "👍"]);
+    }
+
+    #[test]
+    fn duration_units() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Some(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(90 * 60)));
+        assert_eq!(
+            parse_duration("2D"),
+            Some(Duration::from_secs(2 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_duration("1w"),
+            Some(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn bad_durations() {
+        for raw in &["", "90", "h", "0s", "1x", "1h30", "-1h", "1.5h", "1 h"] {
+            assert_eq!(parse_duration(raw), None, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn duration_limit() {
+        assert_eq!(
+            parse_duration("5200w"),
+            Some(Duration::from_secs(5200 * 7 * 24 * 60 * 60))
+        );
+        assert_eq!(parse_duration("36501d"), None);
+    }
+
+    #[test]
+    fn duration_overflow() {
+        assert_eq!(parse_duration("99999999999999999999s"), None);
+        assert_eq!(parse_duration("31000000000000w"), None);
+        assert_eq!(parse_duration("18446744073709551615s1s"), None);
+    }
+
+    fn message(content: &str, guild: bool) -> MessagePacket {
+        let mut message = json!({
+            "id": "100",
+            "channel_id": "200",
+            "author": {"id": "300", "username": "user", "discriminator": "0001"},
+            "content": content,
+            "timestamp": "2020-01-01T00:00:00+00:00",
+            "tts": false,
+            "mention_everyone": false,
+            "mentions": [],
+            "mention_roles": [],
+            "attachments": [],
+            "embeds": [],
+            "pinned": false,
+            "type": 0,
+        });
+        if guild {
+            message["guild_id"] = json!("400");
+        }
+        serde_json::from_value(message).unwrap()
+    }
+
+    fn trigger(
+        registry: &CommandRegistry,
+        settings: &Settings,
+        content: &str,
+        guild: bool,
+    ) -> Option<Trigger> {
+        registry.strip_trigger(
+            &message(content, guild),
+            &Snowflake("1".to_owned()),
+            settings,
+        )
+    }
+
+    #[test]
+    fn triggers() {
+        let registry = CommandRegistry::new(&Config::default());
+        let settings = Settings::open(":memory:").unwrap();
+        let strip = |content, guild| trigger(&registry, &settings, content, guild);
+
+        assert_eq!(
+            strip("!help me", true),
+            Some(Trigger::Prefix("help me".to_owned()))
+        );
+        assert_eq!(
+            strip("  !help", true),
+            Some(Trigger::Prefix("help".to_owned()))
+        );
+        assert_eq!(
+            strip("<@1> help", true),
+            Some(Trigger::Mention("help".to_owned()))
+        );
+        assert_eq!(
+            strip("<@!1>help", true),
+            Some(Trigger::Mention("help".to_owned()))
+        );
+        // Talking about me, or to someone else
+        assert_eq!(strip("thanks <@1>", true), None);
+        assert_eq!(strip("<@2> help", true), None);
+        assert_eq!(strip("help", true), None);
+        assert_eq!(
+            strip("help", false),
+            Some(Trigger::Direct("help".to_owned()))
+        );
+        assert_eq!(
+            strip("!help", false),
+            Some(Trigger::Prefix("help".to_owned()))
+        );
+    }
+
+    #[test]
+    fn guild_prefixes() {
+        let registry = CommandRegistry::new(&Config::default());
+        let settings = Settings::open(":memory:").unwrap();
+        let guild_id = Snowflake("400".to_owned());
+        settings
+            .set(
+                &PREFIXES,
+                Scope::Guild,
+                &guild_id,
+                &vec!["?".to_owned(), "??".to_owned()],
+            )
+            .unwrap();
+        settings
+            .set(&MENTION_PREFIX, Scope::Guild, &guild_id, &false)
+            .unwrap();
+        let strip = |content, guild| trigger(&registry, &settings, content, guild);
+
+        // Longer prefix goes first
+        assert_eq!(
+            strip("??help", true),
+            Some(Trigger::Prefix("help".to_owned()))
+        );
+        assert_eq!(
+            strip("?help", true),
+            Some(Trigger::Prefix("help".to_owned()))
+        );
+        assert_eq!(strip("!help", true), None);
+        assert_eq!(strip("<@1> help", true), None);
+        // Direct messages do not have guild settings
+        assert_eq!(
+            strip("!help", false),
+            Some(Trigger::Prefix("help".to_owned()))
+        );
+    }
+}
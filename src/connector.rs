@@ -4,9 +4,16 @@ use crate::discord::WrapperPacket;
 use crate::engine::*;
 use actix::*;
 use actix_web::client;
+use actix_web::http::{Method, StatusCode};
 use actix_web::ws::{ClientWriter, Message, ProtocolError};
 use actix_web::HttpMessage;
 use futures::Future;
+use std::time::Duration;
+
+/// How often engine checks timed actions.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// Responses with guild members or message history easily exceed default 256KB limit
+const RESPONSE_LIMIT: usize = 8 * 1024 * 1024;
 
 /// Internal engine that handles DISCORD messages.
 pub struct WssConnector {
@@ -28,8 +35,10 @@ impl Default for WssConnector {
 impl Actor for WssConnector {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Context<Self>) {
+    fn started(&mut self, ctx: &mut Context<Self>) {
         debug!("Started MyLittleConnection");
+        // Timed actions of handlers
+        ctx.run_interval(TICK_INTERVAL, |act, _ctx| act.engine.on_tick());
     }
 
     fn stopped(&mut self, _ctx: &mut Context<Self>) {
@@ -106,8 +115,30 @@ impl Actor for RequestConnector {
 impl actix::Supervised for RequestConnector {}
 impl SystemService for RequestConnector {}
 
+/// Why request to DISCORD REST API failed.
+#[derive(Debug)]
+pub enum RequestError {
+    /// Request could not be sent, or response could not be read
+    Http(actix_web::Error),
+    /// DISCORD refused request, body tells why
+    Status { status: StatusCode, body: String },
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RequestError::Http(e) => write!(f, "{}", e),
+            RequestError::Status { status, body } => {
+                write!(f, "DISCORD responded with {}: {}", status, body)
+            }
+        }
+    }
+}
+
+type ResponseFuture = Box<dyn Future<Item = serde_json::Value, Error = RequestError>>;
+
 impl Handler<RequestMessage> for RequestConnector {
-    type Result = Box<dyn Future<Item = serde_json::Value, Error = actix_web::error::Error>>;
+    type Result = ResponseFuture;
 
     fn handle(&mut self, msg: RequestMessage, _ctx: &mut Context<Self>) -> Self::Result {
         let url = &format!("https://discordapp.com/api/v6{}", &msg.url);
@@ -116,6 +147,12 @@ impl Handler<RequestMessage> for RequestConnector {
             HttpMethod::POST => client::post(url),
             HttpMethod::PUT => client::put(url),
             HttpMethod::DELETE => client::delete(url),
+            // There is no shortcut for PATCH
+            HttpMethod::PATCH => {
+                let mut req = client::ClientRequest::build();
+                req.method(Method::PATCH).uri(url);
+                req
+            }
         };
         let req = req.header(
             actix_web::http::header::AUTHORIZATION,
@@ -131,17 +168,40 @@ impl Handler<RequestMessage> for RequestConnector {
         let res = req.unwrap().send();
 
         debug!("Handled msg {:?}", &msg);
-        let res = res.map_err(actix_web::error::Error::from).and_then(|resp| {
-            resp.json()
-                .from_err()
-                // How do i do it better?
-                .then(|item| item)
-        });
-
+        let res = res
+            .map_err(|e| RequestError::Http(e.into()))
+            .and_then(read_response);
         Box::new(res)
     }
 }
 
+/// Body of successful response.
+/// Error responses have JSON body too, it must not pass for response.
+fn read_response(resp: client::ClientResponse) -> ResponseFuture {
+    let status = resp.status();
+    // Deletes and role changes respond without any body
+    if status == StatusCode::NO_CONTENT {
+        return Box::new(futures::future::ok(serde_json::Value::Null));
+    }
+    if !status.is_success() {
+        let res = resp
+            .body()
+            .map_err(|e| RequestError::Http(e.into()))
+            .and_then(move |body| {
+                Err(RequestError::Status {
+                    status,
+                    body: String::from_utf8_lossy(&body).into_owned(),
+                })
+            });
+        return Box::new(res);
+    }
+    Box::new(
+        resp.json()
+            .limit(RESPONSE_LIMIT)
+            .map_err(|e| RequestError::Http(e.into())),
+    )
+}
+
 /// Message to get some information from DISCORD REST API.
 #[derive(Debug)]
 pub struct RequestMessage {
@@ -156,7 +216,7 @@ impl actix::Message for RequestMessage {
     // Actix executes futures inside its core
     // So i dont have to worry about it
     // Pretty good
-    type Result = std::result::Result<serde_json::Value, actix_web::Error>;
+    type Result = std::result::Result<serde_json::Value, RequestError>;
 }
 
 /// Message to get some information from DISCORD REST API.
@@ -207,10 +267,11 @@ impl Handler<ClientMessage> for WssConnector {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum HttpMethod {
     GET,
     POST,
     PUT,
+    PATCH,
     DELETE,
 }
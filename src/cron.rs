@@ -0,0 +1,282 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+
+/// How far next time is searched, schedules like "30 February" never happen
+const SEARCH_LIMIT_DAYS: i64 = 366 * 5;
+
+/// Schedule in cron format: minute, hour, day of month, month and day of week (0 is Sunday).
+/// Fields may be "*", numbers, ranges "1-5", lists "1,15" and steps "*/10" or "0-30/5".
+/// Time is UTC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+    /// Both day fields are restricted, then either of them is enough
+    any_day: bool,
+}
+
+impl Cron {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "Cron has 5 fields: minute hour day month weekday, got {}",
+                fields.len()
+            ));
+        }
+
+        let restricted = |f: &str| f != "*";
+        // 7 is Sunday as well
+        let mut weekdays: Vec<u32> = parse_field(fields[4], 0, 7, "weekday")?
+            .into_iter()
+            .map(|d| d % 7)
+            .collect();
+        weekdays.sort_unstable();
+        weekdays.dedup();
+        Ok(Cron {
+            minutes: parse_field(fields[0], 0, 59, "minute")?,
+            hours: parse_field(fields[1], 0, 23, "hour")?,
+            days: parse_field(fields[2], 1, 31, "day")?,
+            months: parse_field(fields[3], 1, 12, "month")?,
+            weekdays,
+            any_day: restricted(fields[2]) && restricted(fields[4]),
+        })
+    }
+
+    fn day_matches(&self, time: &DateTime<Utc>) -> bool {
+        let day = self.days.contains(&time.day());
+        let weekday = self
+            .weekdays
+            .contains(&time.weekday().num_days_from_sunday());
+        if self.any_day {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// The first matching minute strictly after given time.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start + Duration::days(SEARCH_LIMIT_DAYS);
+        let mut time = start;
+
+        while time < limit {
+            if !self.months.contains(&time.month()) {
+                // First minute of the next month
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                time = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+            } else if !self.day_matches(&time) {
+                time = start_of(&time, 0)? + Duration::days(1);
+            } else if !self.hours.contains(&time.hour()) {
+                time = start_of(&time, time.hour())? + Duration::hours(1);
+            } else if !self.minutes.contains(&time.minute()) {
+                time = time + Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+}
+
+/// Beginning of given hour of the same day.
+fn start_of(time: &DateTime<Utc>, hour: u32) -> Option<DateTime<Utc>> {
+    Utc.with_ymd_and_hms(time.year(), time.month(), time.day(), hour, 0, 0)
+        .single()
+}
+
+/// Values of one field, sorted.
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<Vec<u32>, String> {
+    let bad = || format!("Bad {} field `{}`", name, field);
+    let mut values = Vec::new();
+
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(i) => (&part[..i], part[i + 1..].parse::<u32>().map_err(|_| bad())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(bad());
+        }
+
+        let (from, to) = if range == "*" {
+            (min, max)
+        } else if let Some(i) = range.find('-') {
+            (
+                range[..i].parse().map_err(|_| bad())?,
+                range[i + 1..].parse().map_err(|_| bad())?,
+            )
+        } else {
+            let value = range.parse().map_err(|_| bad())?;
+            // "5/15" means from 5 till the end with step
+            if step > 1 {
+                (value, max)
+            } else {
+                (value, value)
+            }
+        };
+        if from < min || to > max || from > to {
+            return Err(format!(
+                "{} must be between {} and {} in `{}`",
+                name, min, max, field
+            ));
+        }
+
+        values.extend((from..=to).step_by(step as usize));
+    }
+
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn next(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        Cron::parse(expression).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn field_count() {
+        assert!(Cron::parse("* * * *").is_err());
+        assert!(Cron::parse("* * * * * *").is_err());
+        assert!(Cron::parse("").is_err());
+    }
+
+    #[test]
+    fn field_ranges() {
+        assert!(Cron::parse("59 23 31 12 7").is_ok());
+        assert!(Cron::parse("0 0 1 1 0").is_ok());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("* 24 * * *").is_err());
+        assert!(Cron::parse("* * 0 * *").is_err());
+        assert!(Cron::parse("* * 32 * *").is_err());
+        assert!(Cron::parse("* * * 0 *").is_err());
+        assert!(Cron::parse("* * * 13 *").is_err());
+        assert!(Cron::parse("* * * * 8").is_err());
+        assert!(Cron::parse("5-1 * * * *").is_err());
+        assert!(Cron::parse("a * * * *").is_err());
+        assert!(Cron::parse("-1 * * * *").is_err());
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(
+            Cron::parse("*/15 * * * *").unwrap().minutes,
+            vec![0, 15, 30, 45]
+        );
+        assert_eq!(
+            Cron::parse("0-30/10 * * * *").unwrap().minutes,
+            vec![0, 10, 20, 30]
+        );
+        assert_eq!(
+            Cron::parse("5/20 * * * *").unwrap().minutes,
+            vec![5, 25, 45]
+        );
+        assert_eq!(Cron::parse("* */8 * * *").unwrap().hours, vec![0, 8, 16]);
+        assert!(Cron::parse("*/0 * * * *").is_err());
+        assert!(Cron::parse("*/x * * * *").is_err());
+    }
+
+    #[test]
+    fn lists() {
+        let cron = Cron::parse("30,0,30 9-10,17 * * 1-5,7").unwrap();
+        assert_eq!(cron.minutes, vec![0, 30]);
+        assert_eq!(cron.hours, vec![9, 10, 17]);
+        // 7 is Sunday too
+        assert_eq!(cron.weekdays, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(Cron::parse("* * * * 0,7").unwrap().weekdays, vec![0]);
+        assert!(Cron::parse("1,,2 * * * *").is_err());
+    }
+
+    #[test]
+    fn next_minute() {
+        let after = Utc.with_ymd_and_hms(2024, 1, 1, 10, 20, 30).unwrap();
+        assert_eq!(next("* * * * *", after), Some(at(2024, 1, 1, 10, 21)));
+        // Strictly after
+        assert_eq!(
+            next("20 10 * * *", at(2024, 1, 1, 10, 20)),
+            Some(at(2024, 1, 2, 10, 20))
+        );
+        assert_eq!(
+            next("*/15 9-17 * * *", at(2024, 1, 1, 17, 45)),
+            Some(at(2024, 1, 2, 9, 0))
+        );
+    }
+
+    #[test]
+    fn either_day_is_enough() {
+        // 1 January 2024 is Monday. 13th or Friday
+        let cron = "0 12 13 * 5";
+        assert_eq!(
+            next(cron, at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 1, 5, 12, 0))
+        );
+        assert_eq!(
+            next(cron, at(2024, 1, 12, 12, 0)),
+            Some(at(2024, 1, 13, 12, 0))
+        );
+        assert_eq!(
+            next(cron, at(2024, 1, 13, 12, 0)),
+            Some(at(2024, 1, 19, 12, 0))
+        );
+    }
+
+    #[test]
+    fn one_day_field() {
+        assert_eq!(
+            next("0 12 * * 5", at(2024, 1, 6, 0, 0)),
+            Some(at(2024, 1, 12, 12, 0))
+        );
+        assert_eq!(
+            next("0 12 13 * *", at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 1, 13, 12, 0))
+        );
+        // Weekday restricts month days, when day of month is "*"
+        assert_eq!(
+            next("0 0 * 2 0", at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 2, 4, 0, 0))
+        );
+    }
+
+    #[test]
+    fn rollover() {
+        assert_eq!(
+            next("0 * * * *", at(2024, 12, 31, 23, 0)),
+            Some(at(2025, 1, 1, 0, 0))
+        );
+        assert_eq!(
+            next("0 0 1 1 *", at(2024, 6, 15, 8, 0)),
+            Some(at(2025, 1, 1, 0, 0))
+        );
+        // February has no 31st
+        assert_eq!(
+            next("30 23 31 * *", at(2024, 1, 31, 23, 30)),
+            Some(at(2024, 3, 31, 23, 30))
+        );
+        assert_eq!(
+            next("0 0 29 2 *", at(2024, 3, 1, 0, 0)),
+            Some(at(2028, 2, 29, 0, 0))
+        );
+    }
+
+    #[test]
+    fn never() {
+        assert_eq!(next("0 0 30 2 *", at(2024, 1, 1, 0, 0)), None);
+        assert_eq!(next("0 0 31 4,6,9,11 *", at(2024, 1, 1, 0, 0)), None);
+    }
+}
@@ -1,8 +1,16 @@
+use crate::archive::ArchiveConfig;
+use crate::automod::AutomodConfig;
+use crate::discord::Snowflake;
+use crate::starboard::StarboardConfig;
+use crate::welcome::WelcomeConfig;
 use lazy_static::lazy_static;
-use log::info;
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
 
 //const WSS_ADDRESS_LINK: &'static str = "https://discordapp.com/api/v6/gateway";
 const EXPECTED_ARGUMENTS: usize = 2;
+const CONFIG_PATH: &str = "conf/bot.yaml";
 
 lazy_static! {
     pub static ref POOL: Pool = Pool::new();
@@ -13,13 +21,19 @@ lazy_static! {
 pub struct Pool {
     pub wss_ref: String,
     pub key: String,
+    pub config: Config,
 }
 
 impl Pool {
     fn new() -> Self {
         let wss_ref = Pool::get_wss_ref();
         let key = Pool::get_secure_key();
-        Pool { key, wss_ref }
+        let config = Config::load();
+        Pool {
+            key,
+            wss_ref,
+            config,
+        }
     }
 
     fn get_wss_ref() -> String {
@@ -31,9 +45,9 @@ impl Pool {
     fn get_secure_key() -> String {
         let args: Vec<String> = std::env::args().collect();
 
-        if args.len() != EXPECTED_ARGUMENTS {
+        if args.len() < EXPECTED_ARGUMENTS {
             panic!(
-                "Wrong arguments length. Expected: {}, Got: {}",
+                "Wrong arguments length. Expected at least: {}, Got: {}",
                 EXPECTED_ARGUMENTS,
                 args.len()
             );
@@ -44,3 +58,87 @@ impl Pool {
         key.to_string()
     }
 }
+
+/// Bot configuration from conf/bot.yaml.
+/// Every value has a default, so file may be absent or partial.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Prefix of commands, e.g. "!" for "!help"
+    pub prefix: String,
+    /// Whether mention of bot can be used instead of prefix
+    pub mention_prefix: bool,
+    /// Users, that can use owner-only commands
+    pub owners: Vec<Snowflake>,
+    /// Size limits of cache
+    pub cache: CacheConfig,
+    /// Guild id -> channel for moderation log
+    pub log_channels: HashMap<Snowflake, Snowflake>,
+    /// Auto moderation rules
+    pub automod: AutomodConfig,
+    /// Guild id -> welcome and goodbye messages, unless admins chose them in settings
+    pub welcome: HashMap<Snowflake, WelcomeConfig>,
+    /// Guild id -> starboard, unless admins chose it in settings
+    pub starboard: HashMap<Snowflake, StarboardConfig>,
+    /// Where attachments are saved
+    pub archive: ArchiveConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            prefix: "!".to_owned(),
+            mention_prefix: true,
+            owners: vec![],
+            cache: CacheConfig::default(),
+            log_channels: HashMap::new(),
+            automod: AutomodConfig::default(),
+            welcome: HashMap::new(),
+            starboard: HashMap::new(),
+            archive: ArchiveConfig::default(),
+        }
+    }
+}
+
+/// How much of DISCORD is remembered.
+/// When limit is reached, entries, that were not updated for the longest time, are forgotten.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Members of one guild
+    pub max_members_per_guild: usize,
+    /// Users from all guilds and private channels
+    pub max_users: usize,
+    /// Recent messages, that are kept for moderation log
+    pub max_messages: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            max_members_per_guild: 10_000,
+            max_users: 100_000,
+            max_messages: 1_000,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let file = match std::fs::File::open(CONFIG_PATH) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!(
+                    "Could not open {}: {}. Using default config",
+                    CONFIG_PATH, e
+                );
+                return Config::default();
+            }
+        };
+
+        let config = serde_yaml::from_reader(file)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {}", CONFIG_PATH, e));
+        info!("Config is {:?}", &config);
+        config
+    }
+}
@@ -1,3 +1,4 @@
+use crate::permissions::Permissions;
 use serde::de::Visitor;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
@@ -87,7 +88,7 @@ macro_rules! simple_serde_enum_to_str {
 }
 
 /// Snowflake is String in JSON representation, but it always has to be 64bit integer.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Snowflake(pub String);
 
 impl Into<u64> for &Snowflake {
@@ -96,8 +97,21 @@ impl Into<u64> for &Snowflake {
     }
 }
 
+/// DISCORD epoch, the first second of 2015, in unix milliseconds
+pub const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+impl Snowflake {
+    /// Unix time in milliseconds, when the entity was created. Ids keep it in their upper bits.
+    pub fn created_at(&self) -> Option<u64> {
+        self.0
+            .parse::<u64>()
+            .ok()
+            .map(|id| (id >> 22) + DISCORD_EPOCH)
+    }
+}
+
 /// General response from DISCORD.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WrapperPacket {
     /// OpCode as u8
     pub op: OpCode,
@@ -110,7 +124,7 @@ pub struct WrapperPacket {
 }
 
 /// Event for payload in Dispatch packets.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Event {
     /// Defines the heartbeat interval
     Hello,
@@ -185,7 +199,7 @@ pub enum Event {
 }
 
 /// First response from Discord WebSocket.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HelloPacket {
     /// Heartbeat interval in milliseconds
     pub heartbeat_interval: u64,
@@ -194,7 +208,7 @@ pub struct HelloPacket {
 }
 
 /// Packet to identify myself to DISCORD.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IdentityPacket {
     /// My secret
     pub token: String,
@@ -214,7 +228,7 @@ pub struct IdentityPacket {
     pub presence: Option<UpdateStatusPacket>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IdentityPropertiesPacket {
     /// My operating system
     #[serde(alias = "$os")]
@@ -227,7 +241,7 @@ pub struct IdentityPropertiesPacket {
     pub device: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateStatusPacket {
     /// Unix time in milliseconds of when the client went idle, or null if the client is not idle
     pub since: Option<u64>,
@@ -240,7 +254,7 @@ pub struct UpdateStatusPacket {
 }
 
 /// User status object
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Status {
     Online,
     Dnd,
@@ -291,7 +305,7 @@ impl TryFrom<&str> for Status {
 simple_serde_enum_to_str!(Status, "Status name from DISCORD");
 
 /// Opcodes of DISCORD protocol.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum OpCode {
     Dispatch,
     Heartbeat,
@@ -499,14 +513,14 @@ simple_serde_enum_to_str!(Event, "Event name from DISCORD");
 
 /// Ready packet.
 /// Received when identification is completed.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadyPacket {
     /// Protocol version
     pub v: u8,
     /// User object
     pub user: UserPacket,
-    /// Empty array (legacy support?)
-    pub private_channels: Vec<String>,
+    /// Direct message channels
+    pub private_channels: Vec<ChannelPacket>,
     /// Unavailable guilds
     pub guilds: Vec<UnavailableGuildPacket>,
     /// Session id for resuming connection
@@ -519,16 +533,16 @@ pub struct ReadyPacket {
 }
 
 /// Unavailable guilds.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnavailableGuildPacket {
     /// Guild id
     pub id: Snowflake,
-    /// Flag ?
-    pub unavailable: bool,
+    /// Set if guild is down, missing if i was removed from guild
+    pub unavailable: Option<bool>,
 }
 
 /// User object
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserPacket {
     /// Id
     pub id: Snowflake,
@@ -554,19 +568,31 @@ pub struct UserPacket {
     pub premium_type: Option<i64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl UserPacket {
+    /// Link to avatar image, if user has one.
+    pub fn avatar_url(&self) -> Option<String> {
+        self.avatar.as_ref().map(|avatar| {
+            format!(
+                "https://cdn.discordapp.com/avatars/{}/{}.png",
+                self.id.0, avatar
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Message object
 pub struct MessagePacket {
     /// Id
     pub id: Snowflake,
     /// Id of the channel the message was sent in
     pub channel_id: Snowflake,
-    /// Id of the guild the message was sent in
-    pub guild_id: Snowflake,
+    /// Id of the guild the message was sent in (absent for direct messages)
+    pub guild_id: Option<Snowflake>,
     /// The author of this message
     pub author: UserPacket,
-    /// Member properties for this message's author
-    pub member: GuildMemberPacket,
+    /// Member properties for this message's author (absent for direct messages)
+    pub member: Option<GuildMemberPacket>,
     /// Contents of the messages
     pub content: String,
     /// When this message was sent
@@ -579,8 +605,8 @@ pub struct MessagePacket {
     pub mention_everyone: bool,
     /// Users specigically nentioned in this message
     pub mentions: Vec<UserPacket>, // TODO add member field in UserPacket
-    /// Roles specigically mentioned in this message
-    pub mention_roles: Vec<RolePacket>,
+    /// Ids of roles specigically mentioned in this message
+    pub mention_roles: Vec<Snowflake>,
     /// Any attached files
     pub attachments: Vec<AttachmentPacket>,
     /// Any embedded content
@@ -602,8 +628,15 @@ pub struct MessagePacket {
     pub application: Option<MessageApplicationPacket>,
 }
 
+impl MessagePacket {
+    /// Whether this message was sent outside of any guild.
+    pub fn is_direct(&self) -> bool {
+        self.guild_id.is_none()
+    }
+}
+
 /// Information about guild members
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GuildMemberPacket {
     /// The user this guild member represents
     pub user: Option<UserPacket>,
@@ -617,10 +650,12 @@ pub struct GuildMemberPacket {
     pub deaf: bool,
     /// Whether the user is muted in voice channels
     pub mute: bool,
+    /// When the user used their Nitro boost on the guild
+    pub premium_since: Option<String>, // TODO this not a string
 }
 
 /// Role packet
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RolePacket {
     /// Id
     pub id: Snowflake,
@@ -633,7 +668,7 @@ pub struct RolePacket {
     /// Position of this role
     pub position: i64,
     /// Permission bit set
-    pub permissions: i64,
+    pub permissions: Permissions,
     /// Whether this role is managed by an integration
     pub managed: bool,
     /// Whether this role is mentionable
@@ -641,7 +676,7 @@ pub struct RolePacket {
 }
 
 /// Attachment to message
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttachmentPacket {
     /// Id
     pub id: Snowflake,
@@ -660,39 +695,51 @@ pub struct AttachmentPacket {
 }
 
 /// Embed object
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbedPacket {
     /// Title of embed
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
     /// Type of embed (always "rich" for webhook embeds)
-    #[serde(alias = "type")]
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
     pub embed_type: Option<String>,
     /// Description of embed
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     ///Url of embed
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub url: Option<String>,
     /// Timestamp of embed content
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub timestamp: Option<String>, //TODO not a string
     /// Color code of the embed
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub color: Option<i64>,
     /// Footer information
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub footer: Option<EmbedFooterPacket>,
     /// Image information
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub image: Option<EmbedImagePacket>,
     /// Thumbnail information
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub thumbnail: Option<EmbedThumbnailPacket>,
     /// Video information
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub video: Option<EmbedVideoPacket>,
     /// Provider information
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub provider: Option<EmbedProviderPacket>,
     /// Author information
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub author: Option<EmbedAuthorPacket>,
     /// Fields information
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub fields: Option<Vec<EmbedFieldPacket>>,
 }
 
 /// Embed footer
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbedFooterPacket {
     /// Footer text
     pub text: String,
@@ -703,7 +750,7 @@ pub struct EmbedFooterPacket {
 }
 
 /// Embed image
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbedImagePacket {
     /// Source url of image (only supports http(s) and attachments)
     pub url: Option<String>,
@@ -716,7 +763,7 @@ pub struct EmbedImagePacket {
 }
 
 /// Embed thumbnail
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbedThumbnailPacket {
     /// Source url of thumbnail (only supports http(s) and attachments)
     pub url: Option<String>,
@@ -729,7 +776,7 @@ pub struct EmbedThumbnailPacket {
 }
 
 /// Embed video
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbedVideoPacket {
     /// Source url of video
     pub url: Option<String>,
@@ -740,7 +787,7 @@ pub struct EmbedVideoPacket {
 }
 
 /// Embed provider
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbedProviderPacket {
     /// Name of provider
     pub name: Option<String>,
@@ -749,7 +796,7 @@ pub struct EmbedProviderPacket {
 }
 
 /// Embed author
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbedAuthorPacket {
     /// Name of author
     pub name: Option<String>,
@@ -762,7 +809,7 @@ pub struct EmbedAuthorPacket {
 }
 
 /// Embed field
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbedFieldPacket {
     /// Name of field
     pub name: String,
@@ -773,7 +820,7 @@ pub struct EmbedFieldPacket {
 }
 
 /// Reaction object
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReactionPacket {
     /// Times this emoji has been used to react
     pub count: i64,
@@ -784,12 +831,12 @@ pub struct ReactionPacket {
 }
 
 /// Emoji object
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmojiPacket {
-    /// Emoji id
-    pub id: Snowflake,
-    /// Emoji name
-    pub name: String,
+    /// Emoji id, null for unicode emoji
+    pub id: Option<Snowflake>,
+    /// Emoji name, null for deleted custom emoji in reactions
+    pub name: Option<String>,
     /// Roles this emoji is ehitelisted to
     pub roles: Option<Vec<Snowflake>>,
     /// User that created this emoji
@@ -802,8 +849,52 @@ pub struct EmojiPacket {
     pub animated: Option<bool>,
 }
 
+impl EmojiPacket {
+    /// Whether this is the emoji written as unicode or "name:id" of custom emoji.
+    /// Custom emoji are compared by id, their names may change.
+    pub fn matches(&self, emoji: &str) -> bool {
+        match (&self.id, &self.name) {
+            (Some(id), _) => emoji.rsplit(':').next() == Some(id.0.as_str()),
+            (None, Some(name)) => emoji.eq(name),
+            (None, None) => false,
+        }
+    }
+}
+
+/// Whether text is one unicode emoji, with its modifiers and joiners.
+/// There is no list of all emoji here, so blocks of emoji are checked instead.
+pub fn is_unicode_emoji(text: &str) -> bool {
+    let pictograph =
+        |c: char| matches!(c as u32, 0xA9 | 0xAE | 0x203C..=0x3299 | 0x1F000..=0x1FAFF);
+    // Variation selectors, joiner, keycap and tags of subdivision flags
+    let modifier = |c: char| {
+        matches!(
+            c as u32,
+            0x200D | 0x20E3 | 0xFE0E | 0xFE0F | 0xE0020..=0xE007F
+        )
+    };
+    let mut chars = text.chars();
+    match chars.next() {
+        // Keycap is digit, # or * with enclosing keycap
+        Some(c) if c.is_ascii_digit() || c == '#' || c == '*' => {
+            chars.as_str().trim_start_matches('\u{FE0F}') == "\u{20E3}"
+        }
+        Some(c) if pictograph(c) => chars.all(|c| pictograph(c) || modifier(c)),
+        _ => false,
+    }
+}
+
+/// Emoji as it is written in messages, from unicode emoji or "name:id" of custom emoji.
+pub fn display_emoji(emoji: &str) -> String {
+    if emoji.contains(':') {
+        format!("<:{}>", emoji)
+    } else {
+        emoji.to_owned()
+    }
+}
+
 /// Message Activity object
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageActivityPacket {
     #[serde(alias = "type")]
     /// Type of message activity
@@ -813,7 +904,7 @@ pub struct MessageActivityPacket {
 }
 
 /// Message application object
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageApplicationPacket {
     /// Id of application
     pub id: Snowflake,
@@ -827,7 +918,7 @@ pub struct MessageApplicationPacket {
     pub name: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MessageType {
     Default,
     RecipientAdd,
@@ -890,7 +981,7 @@ impl TryFrom<u8> for MessageType {
 
 simple_serde_enum_to_u8!(MessageType, "A number from 0 up to 7");
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MessageActivityType {
     Join,
     Spectate,
@@ -936,7 +1027,709 @@ impl TryFrom<u8> for MessageActivityType {
 }
 
 simple_serde_enum_to_u8!(MessageActivityType, "A number 1, 2, 3 or 5");
-// TODO have to write structures for different events
+
+/// Channel object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelPacket {
+    /// Id
+    pub id: Snowflake,
+    /// Type of channel
+    #[serde(rename = "type")]
+    pub channel_type: ChannelType,
+    /// Id of the guild (absent for direct messages)
+    pub guild_id: Option<Snowflake>,
+    /// Sorting position of the channel
+    pub position: Option<i64>,
+    /// Explicit permission overwrites for members and roles
+    pub permission_overwrites: Option<Vec<PermissionOverwritePacket>>,
+    /// Name of the channel (2-100 characters)
+    pub name: Option<String>,
+    /// Channel topic (0-1024 characters)
+    pub topic: Option<String>,
+    /// Whether the channel is nsfw
+    pub nsfw: Option<bool>,
+    /// Id of the last message sent in this channel (may not point to an existing or valid message)
+    pub last_message_id: Option<Snowflake>,
+    /// Bitrate (in bits) of the voice channel
+    pub bitrate: Option<i64>,
+    /// User limit of the voice channel
+    pub user_limit: Option<i64>,
+    /// Amount of seconds a user has to wait before sending another message (0-21600)
+    pub rate_limit_per_user: Option<i64>,
+    /// Recipients of the DM
+    pub recipients: Option<Vec<UserPacket>>,
+    /// Icon hash
+    pub icon: Option<String>,
+    /// Id of the DM creator
+    pub owner_id: Option<Snowflake>,
+    /// Application id of the group DM creator if it is bot-created
+    pub application_id: Option<Snowflake>,
+    /// Id of the parent category for a channel
+    pub parent_id: Option<Snowflake>,
+    /// When the last pinned message was pinned
+    pub last_pin_timestamp: Option<String>, // TODO it is not string
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChannelType {
+    GuildText,
+    Dm,
+    GuildVoice,
+    GroupDm,
+    GuildCategory,
+    GuildNews,
+    GuildStore,
+    /// Threads, stages, forums and whatever DISCORD adds later
+    Unknown(u8),
+}
+
+// Some converters for ChannelType
+impl Into<u8> for ChannelType {
+    fn into(self) -> u8 {
+        match self {
+            ChannelType::GuildText => 0,
+            ChannelType::Dm => 1,
+            ChannelType::GuildVoice => 2,
+            ChannelType::GroupDm => 3,
+            ChannelType::GuildCategory => 4,
+            ChannelType::GuildNews => 5,
+            ChannelType::GuildStore => 6,
+            ChannelType::Unknown(value) => value,
+        }
+    }
+}
+
+impl Into<u8> for &ChannelType {
+    fn into(self) -> u8 {
+        match self {
+            ChannelType::GuildText => 0,
+            ChannelType::Dm => 1,
+            ChannelType::GuildVoice => 2,
+            ChannelType::GroupDm => 3,
+            ChannelType::GuildCategory => 4,
+            ChannelType::GuildNews => 5,
+            ChannelType::GuildStore => 6,
+            ChannelType::Unknown(value) => *value,
+        }
+    }
+}
+
+impl TryFrom<u8> for ChannelType {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ChannelType::GuildText),
+            1 => Ok(ChannelType::Dm),
+            2 => Ok(ChannelType::GuildVoice),
+            3 => Ok(ChannelType::GroupDm),
+            4 => Ok(ChannelType::GuildCategory),
+            5 => Ok(ChannelType::GuildNews),
+            6 => Ok(ChannelType::GuildStore),
+            _ => Ok(ChannelType::Unknown(value)),
+        }
+    }
+}
+
+simple_serde_enum_to_u8!(ChannelType, "A number of channel type");
+
+/// Permission overwrite object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionOverwritePacket {
+    /// Role or user id
+    pub id: Snowflake,
+    /// Either "role" or "member"
+    #[serde(rename = "type")]
+    pub overwrite_type: OverwriteType,
+    /// Permission bit set
+    pub allow: Permissions,
+    /// Permission bit set
+    pub deny: Permissions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverwriteType {
+    Role,
+    Member,
+}
+
+impl Into<String> for OverwriteType {
+    fn into(self) -> String {
+        match self {
+            OverwriteType::Role => "role".to_owned(),
+            OverwriteType::Member => "member".to_owned(),
+        }
+    }
+}
+
+impl Into<String> for &OverwriteType {
+    fn into(self) -> String {
+        match self {
+            OverwriteType::Role => "role".to_owned(),
+            OverwriteType::Member => "member".to_owned(),
+        }
+    }
+}
+
+impl TryFrom<&str> for OverwriteType {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "role" => Ok(OverwriteType::Role),
+            "member" => Ok(OverwriteType::Member),
+            _ => Err(format!("Unknown name for OverwriteType {}", value)),
+        }
+    }
+}
+
+simple_serde_enum_to_str!(OverwriteType, "Permission overwrite type name from DISCORD");
+
+/// Guild object.
+/// Fields below `joined_at` are only sent within the GUILD_CREATE event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildPacket {
+    /// Id
+    pub id: Snowflake,
+    /// Guild name (2-100 characters)
+    pub name: String,
+    /// Icon hash
+    pub icon: Option<String>,
+    /// Splash hash
+    pub splash: Option<String>,
+    /// Whether or not the user is the owner of the guild
+    pub owner: Option<bool>,
+    /// Id of owner
+    pub owner_id: Snowflake,
+    /// Total permissions for the user in the guild (does not include channel overrides)
+    pub permissions: Option<Permissions>,
+    /// Voice region id for the guild
+    pub region: String,
+    /// Id of afk channel
+    pub afk_channel_id: Option<Snowflake>,
+    /// Afk timeout in seconds
+    pub afk_timeout: i64,
+    /// Whether this guild is embeddable (e.g. widget)
+    pub embed_enabled: Option<bool>,
+    /// If not null, the channel id that the widget will generate an invite to
+    pub embed_channel_id: Option<Snowflake>,
+    /// Verification level required for the guild
+    pub verification_level: i64,
+    /// Default message notifications level
+    pub default_message_notifications: i64,
+    /// Explicit content filter level
+    pub explicit_content_filter: i64,
+    /// Roles in the guild
+    pub roles: Vec<RolePacket>,
+    /// Custom guild emojis
+    pub emojis: Vec<EmojiPacket>,
+    /// Enabled guild features
+    pub features: Vec<String>,
+    /// Required MFA level for the guild
+    pub mfa_level: i64,
+    /// Application id of the guild creator if it is bot-created
+    pub application_id: Option<Snowflake>,
+    /// Whether or not the server widget is enabled
+    pub widget_enabled: Option<bool>,
+    /// The channel id for the server widget
+    pub widget_channel_id: Option<Snowflake>,
+    /// The id of the channel to which system messages are sent
+    pub system_channel_id: Option<Snowflake>,
+    /// When this guild was joined at
+    pub joined_at: Option<String>, // TODO it is not string
+    /// Whether this is considered a large guild
+    pub large: Option<bool>,
+    /// Whether this guild is unavailable
+    pub unavailable: Option<bool>,
+    /// Total number of members in this guild
+    pub member_count: Option<i64>,
+    /// Voice states of members currently in voice channels (lacks guild_id)
+    pub voice_states: Option<Vec<VoiceStatePacket>>,
+    /// Users in the guild
+    pub members: Option<Vec<GuildMemberPacket>>,
+    /// Channels in the guild
+    pub channels: Option<Vec<ChannelPacket>>,
+    /// Presences of the users in the guild
+    pub presences: Option<Vec<PresenceUpdatePacket>>,
+    /// The maximum amount of presences for the guild
+    pub max_presences: Option<i64>,
+    /// The maximum amount of members for the guild
+    pub max_members: Option<i64>,
+    /// The vanity url code for the guild
+    pub vanity_url_code: Option<String>,
+    /// The description for the guild
+    pub description: Option<String>,
+    /// Banner hash
+    pub banner: Option<String>,
+    /// Premium tier
+    pub premium_tier: Option<i64>,
+    /// The total number of users currently boosting this server
+    pub premium_subscription_count: Option<i64>,
+}
+
+/// Partial guild object (as sent within invites)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialGuildPacket {
+    /// Id
+    pub id: Snowflake,
+    /// Guild name
+    pub name: String,
+    /// Icon hash
+    pub icon: Option<String>,
+    /// Splash hash
+    pub splash: Option<String>,
+    /// Banner hash
+    pub banner: Option<String>,
+    /// The description for the guild
+    pub description: Option<String>,
+    /// Enabled guild features
+    pub features: Option<Vec<String>>,
+    /// Verification level required for the guild
+    pub verification_level: Option<i64>,
+    /// The vanity url code for the guild
+    pub vanity_url_code: Option<String>,
+}
+
+/// Voice state object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceStatePacket {
+    /// The guild id this voice state is for
+    pub guild_id: Option<Snowflake>,
+    /// The channel id this user is connected to
+    pub channel_id: Option<Snowflake>,
+    /// The user id this voice state is for
+    pub user_id: Snowflake,
+    /// The guild member this voice state is for
+    pub member: Option<GuildMemberPacket>,
+    /// The session id for this voice state
+    pub session_id: String,
+    /// Whether this user is deafened by the server
+    pub deaf: bool,
+    /// Whether this user is muted by the server
+    pub mute: bool,
+    /// Whether this user is locally deafened
+    pub self_deaf: bool,
+    /// Whether this user is locally muted
+    pub self_mute: bool,
+    /// Whether this user is streaming using "Go Live"
+    pub self_stream: Option<bool>,
+    /// Whether this user is muted by the current user
+    pub suppress: bool,
+}
+
+/// Presence update object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceUpdatePacket {
+    /// The user presence is being updated for (only id is guaranteed)
+    pub user: PartialUserPacket,
+    /// Roles this user is in
+    pub roles: Option<Vec<Snowflake>>,
+    /// Null, or the user's current activity
+    pub game: Option<ActivityPacket>,
+    /// Id of the guild
+    pub guild_id: Option<Snowflake>,
+    /// User status
+    pub status: Option<Status>,
+    /// User's current activities
+    pub activities: Option<Vec<ActivityPacket>>,
+    /// User's platform-dependent status
+    pub client_status: Option<ClientStatusPacket>,
+    /// When the user started boosting the guild
+    pub premium_since: Option<String>, // TODO it is not string
+    /// This users guild nickname (if one is set)
+    pub nick: Option<String>,
+}
+
+/// User object, where only id is guaranteed to be present
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialUserPacket {
+    /// Id
+    pub id: Snowflake,
+    /// Username
+    pub username: Option<String>,
+    ///Discriminator (4-digit tag)
+    pub discriminator: Option<String>,
+    /// Avatar hash
+    pub avatar: Option<String>,
+    /// Bot flag
+    pub bot: Option<bool>,
+}
+
+/// Client status object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientStatusPacket {
+    /// The user's status set for an active desktop application session
+    pub desktop: Option<Status>,
+    /// The user's status set for an active mobile application session
+    pub mobile: Option<Status>,
+    /// The user's status set for an active web application session
+    pub web: Option<Status>,
+}
+
+/// Activity object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityPacket {
+    /// The activity's name
+    pub name: String,
+    /// Activity type
+    #[serde(rename = "type")]
+    pub activity_type: ActivityType,
+    /// Stream url, is validated when type is Streaming
+    pub url: Option<String>,
+    /// Unix timestamps for start and/or end of the game
+    pub timestamps: Option<ActivityTimestampsPacket>,
+    /// Application id for the game
+    pub application_id: Option<Snowflake>,
+    /// What the player is currently doing
+    pub details: Option<String>,
+    /// The user's current party status
+    pub state: Option<String>,
+    /// Information for the current party of the player
+    pub party: Option<ActivityPartyPacket>,
+    /// Images for the presence and their hover texts
+    pub assets: Option<ActivityAssetsPacket>,
+    /// Secrets for Rich Presence joining and spectating
+    pub secrets: Option<ActivitySecretsPacket>,
+    /// Whether or not the activity is an instanced game session
+    pub instance: Option<bool>,
+    /// Activity flags ORd together, describes what the payload includes
+    pub flags: Option<i64>,
+}
+
+/// Activity timestamps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityTimestampsPacket {
+    /// Unix time (in milliseconds) of when the activity started
+    pub start: Option<u64>,
+    /// Unix time (in milliseconds) of when the activity ends
+    pub end: Option<u64>,
+}
+
+/// Activity party
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityPartyPacket {
+    /// The id of the party
+    pub id: Option<String>,
+    /// Current size and max size of the party
+    pub size: Option<Vec<i64>>,
+}
+
+/// Activity assets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityAssetsPacket {
+    /// The id for a large asset of the activity, usually a snowflake
+    pub large_image: Option<String>,
+    /// Text displayed when hovering over the large image of the activity
+    pub large_text: Option<String>,
+    /// The id for a small asset of the activity, usually a snowflake
+    pub small_image: Option<String>,
+    /// Text displayed when hovering over the small image of the activity
+    pub small_text: Option<String>,
+}
+
+/// Activity secrets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivitySecretsPacket {
+    /// The secret for joining a party
+    pub join: Option<String>,
+    /// The secret for spectating a game
+    pub spectate: Option<String>,
+    /// The secret for a specific instanced match
+    #[serde(rename = "match")]
+    pub match_secret: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActivityType {
+    Game,
+    Streaming,
+    Listening,
+    Watching,
+    Custom,
+    Competing,
+    /// Whatever DISCORD adds later
+    Unknown(u8),
+}
+
+// Some converters for ActivityType
+impl Into<u8> for ActivityType {
+    fn into(self) -> u8 {
+        match self {
+            ActivityType::Game => 0,
+            ActivityType::Streaming => 1,
+            ActivityType::Listening => 2,
+            ActivityType::Watching => 3,
+            ActivityType::Custom => 4,
+            ActivityType::Competing => 5,
+            ActivityType::Unknown(value) => value,
+        }
+    }
+}
+
+impl Into<u8> for &ActivityType {
+    fn into(self) -> u8 {
+        match self {
+            ActivityType::Game => 0,
+            ActivityType::Streaming => 1,
+            ActivityType::Listening => 2,
+            ActivityType::Watching => 3,
+            ActivityType::Custom => 4,
+            ActivityType::Competing => 5,
+            ActivityType::Unknown(value) => *value,
+        }
+    }
+}
+
+impl TryFrom<u8> for ActivityType {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ActivityType::Game),
+            1 => Ok(ActivityType::Streaming),
+            2 => Ok(ActivityType::Listening),
+            3 => Ok(ActivityType::Watching),
+            4 => Ok(ActivityType::Custom),
+            5 => Ok(ActivityType::Competing),
+            _ => Ok(ActivityType::Unknown(value)),
+        }
+    }
+}
+
+simple_serde_enum_to_u8!(ActivityType, "A number of activity type");
+
+/// Invite object (with optional metadata)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvitePacket {
+    /// The invite code (unique id)
+    pub code: String,
+    /// The guild this invite is for
+    pub guild: Option<PartialGuildPacket>,
+    /// The channel this invite is for
+    pub channel: ChannelPacket,
+    /// The target user for this invite
+    pub target_user: Option<PartialUserPacket>,
+    /// The type of user target for this invite
+    pub target_user_type: Option<i64>,
+    /// Approximate count of online members
+    pub approximate_presence_count: Option<i64>,
+    /// Approximate count of total members
+    pub approximate_member_count: Option<i64>,
+    /// User who created the invite
+    pub inviter: Option<UserPacket>,
+    /// Number of times this invite has been used
+    pub uses: Option<i64>,
+    /// Max number of times this invite can be used
+    pub max_uses: Option<i64>,
+    /// Duration (in seconds) after which the invite expires
+    pub max_age: Option<i64>,
+    /// Whether this invite only grants temporary membership
+    pub temporary: Option<bool>,
+    /// When this invite was created
+    pub created_at: Option<String>, // TODO it is not string
+    /// Whether this invite is revoked
+    pub revoked: Option<bool>,
+}
+
+/// Webhook object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPacket {
+    /// Id
+    pub id: Snowflake,
+    /// The guild id this webhook is for
+    pub guild_id: Option<Snowflake>,
+    /// The channel id this webhook is for
+    pub channel_id: Snowflake,
+    /// The user this webhook was created by (not returned when getting a webhook with its token)
+    pub user: Option<UserPacket>,
+    /// The default name of the webhook
+    pub name: Option<String>,
+    /// The default avatar of the webhook
+    pub avatar: Option<String>,
+    /// The secure token of the webhook
+    pub token: Option<String>,
+}
+
+// Gateway events, that do not reuse other objects
+/// Fields of MESSAGE_UPDATE, only id and channel_id are guaranteed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageUpdatePacket {
+    /// Id of the message
+    pub id: Snowflake,
+    /// Id of the channel the message was sent in
+    pub channel_id: Snowflake,
+    /// Id of the guild the message was sent in
+    pub guild_id: Option<Snowflake>,
+    /// The author of this message
+    pub author: Option<UserPacket>,
+    /// New contents of the message
+    pub content: Option<String>,
+    /// When this message was edited
+    pub edited_timestamp: Option<String>, // TODO it is not string
+    /// Users specifically mentioned in the message
+    pub mentions: Option<Vec<UserPacket>>,
+    /// Any attached files
+    pub attachments: Option<Vec<AttachmentPacket>>,
+    /// Any embedded content
+    pub embeds: Option<Vec<EmbedPacket>>,
+    /// Whether this message is pinned
+    pub pinned: Option<bool>,
+}
+
+/// MESSAGE_DELETE event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageDeletePacket {
+    /// Id of the message
+    pub id: Snowflake,
+    /// Id of the channel
+    pub channel_id: Snowflake,
+    /// Id of the guild
+    pub guild_id: Option<Snowflake>,
+}
+
+/// MESSAGE_DELETE_BULK event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageDeleteBulkPacket {
+    /// Ids of the messages
+    pub ids: Vec<Snowflake>,
+    /// Id of the channel
+    pub channel_id: Snowflake,
+    /// Id of the guild
+    pub guild_id: Option<Snowflake>,
+}
+
+/// MESSAGE_REACTION_ADD and MESSAGE_REACTION_REMOVE events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageReactionPacket {
+    /// Id of the user
+    pub user_id: Snowflake,
+    /// Id of the channel
+    pub channel_id: Snowflake,
+    /// Id of the message
+    pub message_id: Snowflake,
+    /// Id of the guild
+    pub guild_id: Option<Snowflake>,
+    /// Member who reacted, only on add in guilds
+    pub member: Option<GuildMemberPacket>,
+    /// Emoji used to react (partial)
+    pub emoji: EmojiPacket,
+}
+
+/// MESSAGE_REACTION_REMOVE_ALL event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageReactionRemoveAllPacket {
+    /// Id of the channel
+    pub channel_id: Snowflake,
+    /// Id of the message
+    pub message_id: Snowflake,
+    /// Id of the guild
+    pub guild_id: Option<Snowflake>,
+}
+
+/// GUILD_MEMBER_ADD event, member with extra guild_id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildMemberAddPacket {
+    /// Id of the guild
+    pub guild_id: Snowflake,
+    /// New member
+    #[serde(flatten)]
+    pub member: GuildMemberPacket,
+}
+
+/// GUILD_MEMBER_UPDATE event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildMemberUpdatePacket {
+    /// Id of the guild
+    pub guild_id: Snowflake,
+    /// User role ids
+    pub roles: Vec<Snowflake>,
+    /// The user
+    pub user: UserPacket,
+    /// Nickname of the user in the guild
+    pub nick: Option<String>,
+    /// When the user starting boosting the guild
+    pub premium_since: Option<String>, // TODO it is not string
+}
+
+/// GUILD_MEMBER_REMOVE event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildMemberRemovePacket {
+    /// Id of the guild
+    pub guild_id: Snowflake,
+    /// The user who was removed
+    pub user: UserPacket,
+}
+
+/// GUILD_BAN_ADD and GUILD_BAN_REMOVE events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildBanPacket {
+    /// Id of the guild
+    pub guild_id: Snowflake,
+    /// The banned or unbanned user
+    pub user: UserPacket,
+}
+
+/// GUILD_EMOJIS_UPDATE event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildEmojisUpdatePacket {
+    /// Id of the guild
+    pub guild_id: Snowflake,
+    /// All emojis of the guild
+    pub emojis: Vec<EmojiPacket>,
+}
+
+/// GUILD_ROLE_CREATE and GUILD_ROLE_UPDATE events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildRolePacket {
+    /// Id of the guild
+    pub guild_id: Snowflake,
+    /// The role created or updated
+    pub role: RolePacket,
+}
+
+/// GUILD_ROLE_DELETE event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildRoleDeletePacket {
+    /// Id of the guild
+    pub guild_id: Snowflake,
+    /// Id of the role
+    pub role_id: Snowflake,
+}
+
+/// CHANNEL_PINS_UPDATE event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelPinsUpdatePacket {
+    /// Id of the guild
+    pub guild_id: Option<Snowflake>,
+    /// Id of the channel
+    pub channel_id: Snowflake,
+    /// The time at which the most recent pinned message was pinned
+    pub last_pin_timestamp: Option<String>, // TODO it is not string
+}
+
+/// TYPING_START event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypingStartPacket {
+    /// Id of the channel
+    pub channel_id: Snowflake,
+    /// Id of the guild
+    pub guild_id: Option<Snowflake>,
+    /// Id of the user
+    pub user_id: Snowflake,
+    /// Unix time (in seconds) of when the user started typing
+    pub timestamp: u64,
+    /// Member who started typing, only in guilds
+    pub member: Option<GuildMemberPacket>,
+}
+
+/// WEBHOOKS_UPDATE event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhooksUpdatePacket {
+    /// Id of the guild
+    pub guild_id: Snowflake,
+    /// Id of the channel
+    pub channel_id: Snowflake,
+}
 
 // Requests
 /// Create my own messages
@@ -954,9 +1747,13 @@ pub struct MessageRequestPacket {
     pub embed: Option<EmbedPacket>,
     /// JSON encoded
     pub payload_json: Option<serde_json::Value>,
+    /// Which mentions are allowed to ping someone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_mentions: Option<AllowedMentionsPacket>,
 }
 
 impl MessageRequestPacket {
+    /// Plain text message, that can not ping anyone.
     pub fn simple_text(text: &str) -> Self {
         MessageRequestPacket {
             content: Some(text.to_string()),
@@ -965,6 +1762,84 @@ impl MessageRequestPacket {
             file: None,
             embed: None,
             payload_json: None,
+            allowed_mentions: Some(AllowedMentionsPacket::none()),
+        }
+    }
+}
+
+/// Allowed mentions object.
+/// Mentions that are not allowed are still rendered, but nobody is notified.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowedMentionsPacket {
+    /// Mention types to parse from the content
+    pub parse: Vec<AllowedMentionType>,
+    /// Roles to mention (max 100)
+    pub roles: Vec<Snowflake>,
+    /// Users to mention (max 100)
+    pub users: Vec<Snowflake>,
+}
+
+impl AllowedMentionsPacket {
+    /// Nobody is pinged.
+    pub fn none() -> Self {
+        AllowedMentionsPacket {
+            parse: vec![],
+            roles: vec![],
+            users: vec![],
+        }
+    }
+
+    /// Only listed users are pinged.
+    pub fn users(users: Vec<Snowflake>) -> Self {
+        AllowedMentionsPacket {
+            parse: vec![],
+            roles: vec![],
+            users,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AllowedMentionType {
+    /// Role mentions
+    Roles,
+    /// User mentions
+    Users,
+    /// @everyone and @here mentions
+    Everyone,
+}
+
+impl Into<String> for AllowedMentionType {
+    fn into(self) -> String {
+        match self {
+            AllowedMentionType::Roles => "roles".to_owned(),
+            AllowedMentionType::Users => "users".to_owned(),
+            AllowedMentionType::Everyone => "everyone".to_owned(),
         }
     }
 }
+
+impl Into<String> for &AllowedMentionType {
+    fn into(self) -> String {
+        match self {
+            AllowedMentionType::Roles => "roles".to_owned(),
+            AllowedMentionType::Users => "users".to_owned(),
+            AllowedMentionType::Everyone => "everyone".to_owned(),
+        }
+    }
+}
+
+impl TryFrom<&str> for AllowedMentionType {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "roles" => Ok(AllowedMentionType::Roles),
+            "users" => Ok(AllowedMentionType::Users),
+            "everyone" => Ok(AllowedMentionType::Everyone),
+            _ => Err(format!("Unknown name for AllowedMentionType {}", value)),
+        }
+    }
+}
+
+simple_serde_enum_to_str!(AllowedMentionType, "Allowed mention type name");
@@ -1,24 +1,57 @@
 use log::{debug, error, info, warn};
 
+use crate::cache::Cache;
 use crate::connector::*;
 use crate::data::POOL;
 use crate::discord::*;
+use crate::handler::{self, EventContext, EventHandler, HandlerResult};
+use crate::rest::Rest;
+use crate::settings::{Settings, SETTINGS_PATH};
 use actix::*;
-use actix_web::client;
-use actix_web::HttpMessage;
-use futures::stream::Stream;
 use futures::Future;
-use std::io::Write;
+use serde::de::DeserializeOwned;
 use std::thread::JoinHandle;
 
-#[derive(Debug)]
+/// Parses packet and passes it to the handler method with the same name.
+/// If cache method is given, cache is updated first.
+macro_rules! notify {
+    ($engine:ident, $t:ident, $d:ident, $packet:ty, $method:ident) => {
+        if let Some(packet) = parse_packet::<$packet>($t, $d) {
+            $engine.notify(&format!("{:?}", $t), |h, ctx| h.$method(ctx, &packet));
+        }
+    };
+    ($engine:ident, $t:ident, $d:ident, $packet:ty, $method:ident, $update:ident) => {
+        if let Some(packet) = parse_packet::<$packet>($t, $d) {
+            $engine.cache.$update(&packet);
+            $engine.notify(&format!("{:?}", $t), |h, ctx| h.$method(ctx, &packet));
+        }
+    };
+}
+
 pub struct Engine {
     /// Keep session id to be able to resume connection.
     session_id: Option<String>,
     /// Keep join point of heartbeat thread
     heartbeat_thread: Option<JoinHandle<()>>,
-    /// Myself identifier
-    myself_id: Option<Snowflake>,
+    /// Everything i know about DISCORD
+    cache: Cache,
+    /// Persistent settings of guilds, channels and users
+    settings: Settings,
+    /// Everything, that reacts to events, in order of registration
+    handlers: Vec<Box<dyn EventHandler>>,
+}
+
+impl std::fmt::Debug for Engine {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Engine")
+            .field("session_id", &self.session_id)
+            .field("cache", &self.cache)
+            .field(
+                "handlers",
+                &self.handlers.iter().map(|h| h.name()).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
 }
 
 impl Engine {
@@ -26,9 +59,20 @@ impl Engine {
         Engine {
             session_id: None,
             heartbeat_thread: None,
-            myself_id: None,
+            cache: Cache::new(&POOL.config.cache),
+            // Without settings every guild would silently fall back to defaults
+            settings: Settings::open(SETTINGS_PATH)
+                .unwrap_or_else(|e| panic!("Failed to open {}: {}", SETTINGS_PATH, e)),
+            handlers: Vec::new(),
         }
     }
+
+    /// Registers handler, handlers are called in order of registration.
+    pub fn add_handler(&mut self, handler: Box<dyn EventHandler>) {
+        debug!("Registered handler {}", handler.name());
+        self.handlers.push(handler);
+    }
+
     // Lets assume that:
     // DISCORD: <The internet is a scary place. Disconnections happen, especially with persistent connections.> - we ignore this statement, our internet is peaceful-friendly and stable.
     pub fn on_message(&mut self, content: WrapperPacket) {
@@ -137,115 +181,168 @@ impl Engine {
     }
 
     /// Literally all regular events that happened on server side.
+    /// Engine remembers what it needs, everything else is done by handlers.
     fn dispatch(&mut self, content: WrapperPacket) {
-        match &content.t {
-            None => debug!("There was no \"t\" parameter in Dispatch event. Ignoring packet"),
-            Some(t) => match t {
-                Event::MessageCreate => {
-                    debug!("Something was written in chat!");
-
-                    match &self.myself_id {
-                        None => warn!("I dont know who am i, so message was ignored"),
-                        Some(_) => {
-                            let message_packet: MessagePacket =
-                                serde_json::from_value(content.d.unwrap()).unwrap();
-                            debug!("The message is {:?}", &message_packet);
-                            self.on_text_message(&message_packet);
-                            self.inspect_file(&message_packet)
-                        }
-                    }
-                }
-                Event::Ready => {
-                    debug!("Found Ready packet");
-                    let ready_packet: ReadyPacket =
-                        serde_json::from_value(content.d.unwrap()).unwrap();
-                    self.myself_id = Some(ready_packet.user.id);
-                    debug!("Myself id is {:?}", &self.myself_id);
-                }
-                _ => info!("We do not care about {:?} event. Ignoring packet", t),
-            },
-        }
-    }
-
-    fn on_text_message(&mut self, message_packet: &MessagePacket) {
-        let author = &message_packet.author.id;
-        let myself_id = self.myself_id.as_ref().unwrap();
-
-        if author.eq(myself_id) {
-            debug!("My own message. Ignoring packet");
-            return;
-        }
+        let t = match &content.t {
+            None => {
+                debug!("There was no \"t\" parameter in Dispatch event. Ignoring packet");
+                return;
+            }
+            Some(t) => t,
+        };
+        let d = content.d;
 
-        let was_mentioned = message_packet.mentions.iter().any(|i| i.id.eq(myself_id));
-        if !was_mentioned {
-            debug!("I was not mentioned. Ignoring packet");
-            return;
+        match t {
+            Event::Ready => {
+                debug!("Found Ready packet");
+                notify!(self, t, d, ReadyPacket, on_ready, ready);
+                debug!("Myself id is {:?}", self.cache.myself_id());
+            }
+            Event::ChannelCreate => {
+                notify!(self, t, d, ChannelPacket, on_channel_create, insert_channel)
+            }
+            Event::ChannelUpdate => {
+                notify!(self, t, d, ChannelPacket, on_channel_update, insert_channel)
+            }
+            Event::ChannelDelete => {
+                notify!(self, t, d, ChannelPacket, on_channel_delete, remove_channel)
+            }
+            Event::ChannelPinsUpdate => {
+                notify!(self, t, d, ChannelPinsUpdatePacket, on_channel_pins_update)
+            }
+            Event::GuildCreate => notify!(self, t, d, GuildPacket, on_guild_create, insert_guild),
+            Event::GuildUpdate => notify!(self, t, d, GuildPacket, on_guild_update, update_guild),
+            Event::GuildDelete => notify!(
+                self,
+                t,
+                d,
+                UnavailableGuildPacket,
+                on_guild_delete,
+                remove_guild
+            ),
+            Event::GuildBanAdd => notify!(self, t, d, GuildBanPacket, on_ban_add),
+            Event::GuildBanRemove => notify!(self, t, d, GuildBanPacket, on_ban_remove),
+            Event::GuildEmojisUpdate => {
+                notify!(self, t, d, GuildEmojisUpdatePacket, on_emojis_update)
+            }
+            Event::GuildMemberAdd => {
+                notify!(self, t, d, GuildMemberAddPacket, on_member_add, add_member)
+            }
+            Event::GuildMemberUpdate => {
+                notify!(
+                    self,
+                    t,
+                    d,
+                    GuildMemberUpdatePacket,
+                    on_member_update,
+                    update_member
+                )
+            }
+            Event::GuildMemberRemove => {
+                notify!(
+                    self,
+                    t,
+                    d,
+                    GuildMemberRemovePacket,
+                    on_member_remove,
+                    remove_member
+                )
+            }
+            Event::GuildRoleCreate => {
+                notify!(self, t, d, GuildRolePacket, on_role_create, insert_role)
+            }
+            Event::GuildRoleUpdate => {
+                notify!(self, t, d, GuildRolePacket, on_role_update, insert_role)
+            }
+            Event::GuildRoleDelete => notify!(
+                self,
+                t,
+                d,
+                GuildRoleDeletePacket,
+                on_role_delete,
+                remove_role
+            ),
+            Event::MessageCreate => {
+                debug!("Something was written in chat!");
+                notify!(self, t, d, MessagePacket, on_message)
+            }
+            Event::MessageUpdate => notify!(self, t, d, MessageUpdatePacket, on_message_update),
+            Event::MessageDelete => notify!(self, t, d, MessageDeletePacket, on_message_delete),
+            Event::MessageDeleteBulk => {
+                notify!(self, t, d, MessageDeleteBulkPacket, on_message_delete_bulk)
+            }
+            Event::MessageReactionAdd => {
+                notify!(self, t, d, MessageReactionPacket, on_reaction_add)
+            }
+            Event::MessageReactionRemove => {
+                notify!(self, t, d, MessageReactionPacket, on_reaction_remove)
+            }
+            Event::MessageReactionRemoveAll => notify!(
+                self,
+                t,
+                d,
+                MessageReactionRemoveAllPacket,
+                on_reaction_remove_all
+            ),
+            Event::PresenceUpdate => {
+                notify!(
+                    self,
+                    t,
+                    d,
+                    PresenceUpdatePacket,
+                    on_presence_update,
+                    update_presence
+                )
+            }
+            Event::TypingStart => notify!(self, t, d, TypingStartPacket, on_typing_start),
+            Event::UserUpdate => notify!(self, t, d, UserPacket, on_user_update, update_user),
+            Event::VoiceStateUpdate => {
+                notify!(self, t, d, VoiceStatePacket, on_voice_state_update)
+            }
+            Event::WebhooksUpdate => notify!(self, t, d, WebhooksUpdatePacket, on_webhooks_update),
+            _ => info!("We do not care about {:?} event. Ignoring packet", t),
         }
+    }
 
-        let my_mention = format!("<@{}>", myself_id.0);
-        debug!("My mention is: {}", &my_mention);
-        let mention_index = &message_packet.content.find(&my_mention);
-        if mention_index.is_none() {
-            warn!("Found myself in <mentions>, but could not detect myself in message text. Ignoring packet.");
-            return;
-        }
+    /// Time passed, handlers may do their timed actions.
+    pub fn on_tick(&mut self) {
+        self.notify("Tick", |h, ctx| h.on_tick(ctx));
+    }
 
-        let mention_index = mention_index.unwrap();
-        let content = &message_packet.content[mention_index + my_mention.len()..];
-        debug!("Content is: {}", content);
-
-        // Respond with same text
-        let channel_id = &message_packet.channel_id.0;
-        let req_con = System::current().registry().get::<RequestConnector>();
-        let author_id = &author.0;
-
-        let request_data = serde_json::to_value(MessageRequestPacket::simple_text(&format!(
-            "<@{}> {}",
-            author_id, content
-        )))
-        .unwrap();
-        let msg = RequestMessage {
-            method: HttpMethod::POST,
-            url: format!("/channels/{}/messages", channel_id),
-            data: Some(request_data),
+    /// Passes event to all handlers.
+    fn notify<F>(&mut self, event: &str, f: F)
+    where
+        F: Fn(&mut dyn EventHandler, &EventContext) -> HandlerResult,
+    {
+        let myself_id = match self.cache.myself_id() {
+            Some(myself_id) => myself_id,
+            None => {
+                debug!("I dont know who am i, so {} event was ignored", event);
+                return;
+            }
         };
 
-        let res = req_con.try_send(msg);
-        debug!("Message was sent to DISCORD? {:?}", res);
+        let ctx = EventContext::new(myself_id, &self.cache, &self.settings, Rest::new());
+        handler::notify(&mut self.handlers, &ctx, event, f);
     }
+}
+
+/// Parses data of packet.
+/// Broken packets are logged and ignored, so engine never panics on them.
+fn parse_packet<T: DeserializeOwned>(event: &Event, d: Option<serde_json::Value>) -> Option<T> {
+    let d = match d {
+        Some(d) => d,
+        None => {
+            error!("There was no data in {:?} packet. Ignoring packet", event);
+            return None;
+        }
+    };
 
-    fn inspect_file(&mut self, message_packet: &MessagePacket) {
-        if !message_packet.attachments.is_empty() {
-            debug!("Found some attachments");
-            for att in &message_packet.attachments {
-                let name = att.filename.clone();
-                let url = &att.url;
-                debug!("File is {} from {}", name, url);
-
-                let f = client::get(url).finish().unwrap().send();
-                let f = f
-                    .map_err(actix_web::error::Error::from)
-                    .and_then(|resp| {
-                        resp.body()
-                            .limit(100 * 1024 * 1024)
-                            .map_err(actix_web::error::Error::from)
-                    })
-                    .map(move |body| {
-                        let curr_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
-                        let mut file = std::fs::File::create(&format!("files/{}_{}", &curr_time, &name)).unwrap();
-                        let res = file.write_all(body.as_ref());
-                        debug!("Result of writing to file is {:?}", res);
-                        ()
-                    })
-                    .map_err(|e| {
-                        error!("Error happened {}", e);
-                        ()
-                    });
-                Arbiter::spawn(f);
-                //                tokio::spawn(f);
-            }
-        } else {
-            debug!("No attachments found");
+    match serde_json::from_value(d) {
+        Ok(packet) => Some(packet),
+        Err(e) => {
+            error!("Failed to parse {:?} packet: {}. Ignoring packet", event, e);
+            None
         }
     }
 }
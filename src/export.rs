@@ -0,0 +1,655 @@
+use log::{debug, error, info, warn};
+
+use crate::archive::ArchiveIndex;
+use crate::command::*;
+use crate::discord::*;
+use crate::guard::Guard;
+use crate::i18n;
+use crate::permissions::Permissions;
+use crate::rest::{Rest, RestError};
+use crate::sanitize::escape_html;
+use actix::Arbiter;
+use actix_web::client;
+use actix_web::HttpMessage;
+use chrono::{DateTime, Utc};
+use futures::future::{self, Either, Loop};
+use futures::{Future, Stream};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::timer::Delay;
+
+/// Folder for exported channels in working directory
+pub const EXPORTS_FOLDER: &str = "exports";
+/// DISCORD gives at most this many messages at once
+const PAGE_SIZE: usize = 100;
+/// Pause between pages, so long history does not hit rate limits
+const PAGE_DELAY: Duration = Duration::from_secs(1);
+/// Size limit of one file, that transcript shows
+const ASSET_LIMIT: usize = 100 * 1024 * 1024;
+/// Transcript looks like DISCORD, more or less
+const STYLE: &str = "
+body { background: #36393f; color: #dcddde; font-family: sans-serif; font-size: 15px; margin: 20px; }
+a { color: #00b0f4; }
+h1 { color: #fff; font-size: 20px; }
+.topic, .footer, .time, .edited { color: #72767d; font-size: 12px; }
+.message { display: flex; margin: 12px 0; }
+.avatar { width: 40px; height: 40px; border-radius: 50%; margin-right: 12px; flex-shrink: 0; }
+.body { min-width: 0; }
+.author { color: #fff; font-weight: bold; }
+.time { text-decoration: none; }
+.content, .embed-description, .embed-field { white-space: pre-wrap; word-wrap: break-word; }
+.image { display: block; max-width: 400px; max-height: 300px; margin-top: 4px; }
+.embed { background: #2f3136; border-left: 4px solid #202225; border-radius: 4px; margin-top: 4px; max-width: 520px; padding: 8px 12px; }
+.embed-author, .embed-footer { font-size: 12px; }
+.embed-title { color: #fff; font-weight: bold; }
+.reaction { background: #2f3136; border-radius: 8px; display: inline-block; margin: 4px 4px 0 0; padding: 2px 6px; }
+.emoji { width: 16px; height: 16px; vertical-align: middle; }
+";
+
+/// Something went wrong while exporting.
+#[derive(Debug)]
+pub enum ExportError {
+    /// Export files could not be written
+    Io(std::io::Error),
+    Rest(RestError),
+    /// Pause between pages failed
+    Timer(tokio::timer::Error),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "Could not write export: {}", e),
+            ExportError::Rest(e) => write!(f, "Could not get history: {}", e),
+            ExportError::Timer(e) => write!(f, "Could not wait for the next page: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+/// Written export of channel.
+#[derive(Debug)]
+pub struct Export {
+    pub jsonl: PathBuf,
+    pub html: PathBuf,
+    pub messages: usize,
+}
+
+pub type ExportFuture = Box<dyn Future<Item = Export, Error = ExportError>>;
+
+/// Writes the whole history of channel to exports folder:
+/// JSONL with one message per line and HTML transcript, that is read without network,
+/// as avatars, emoji and images are downloaded next to it.
+/// History is walked from the oldest message page by page, so it is never kept in memory.
+/// Files of failed export are removed, they would have only a part of history.
+pub fn export(rest: &Rest, channel_id: &Snowflake) -> ExportFuture {
+    let pages_rest = rest.clone();
+    let channel_id = channel_id.clone();
+    let task = rest
+        .get_channel(&channel_id)
+        .map_err(ExportError::Rest)
+        .and_then(|channel| Transcript::create(&channel))
+        .and_then(move |transcript| {
+            let files = transcript.files();
+            let start = Snowflake("0".to_owned());
+            future::loop_fn((transcript, start), move |(transcript, after)| {
+                next_page(&pages_rest, &channel_id, transcript, after)
+            })
+            .and_then(Transcript::finish)
+            .or_else(move |e| {
+                remove_files(&files);
+                Err(e)
+            })
+        });
+    Box::new(task)
+}
+
+/// Removes what was written by failed export.
+fn remove_files(files: &[PathBuf]) {
+    for file in files {
+        let res = if file.is_dir() {
+            std::fs::remove_dir_all(file)
+        } else {
+            std::fs::remove_file(file)
+        };
+        match res {
+            Ok(()) => info!("Removed unfinished {:?}", file),
+            // Folder of assets is created with the first one
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Could not remove unfinished {:?}: {}", file, e),
+        }
+    }
+}
+
+type PageFuture =
+    Box<dyn Future<Item = Loop<Transcript, (Transcript, Snowflake)>, Error = ExportError>>;
+
+/// Writes messages, that go after message `after`, and tells whether there are more.
+fn next_page(
+    rest: &Rest,
+    channel_id: &Snowflake,
+    mut transcript: Transcript,
+    after: Snowflake,
+) -> PageFuture {
+    // The first page is requested at once
+    let at = if transcript.messages == 0 {
+        Instant::now()
+    } else {
+        Instant::now() + PAGE_DELAY
+    };
+    let rest = rest.clone();
+    let channel_id = channel_id.clone();
+    let task = Delay::new(at)
+        .map_err(ExportError::Timer)
+        .and_then(move |_| {
+            rest.get_messages_after(&channel_id, &after, PAGE_SIZE)
+                .map_err(ExportError::Rest)
+        })
+        .and_then(move |mut page| {
+            // Page comes newest first
+            page.sort_by_key(|m| m.id.0.parse::<u64>().unwrap_or(0));
+            transcript.write(&page)?;
+            debug!("Exported {} messages so far", transcript.messages);
+
+            let last = match page.last() {
+                Some(last) if page.len() == PAGE_SIZE => Some(last.id.clone()),
+                _ => None,
+            };
+            Ok((transcript, last))
+        })
+        .and_then(|(mut transcript, last)| {
+            download(transcript.assets.take_pending()).map(move |_| match last {
+                Some(last) => Loop::Continue((transcript, last)),
+                None => Loop::Break(transcript),
+            })
+        });
+    Box::new(task)
+}
+
+/// Downloads files one by one.
+/// File, that DISCORD does not give anymore, is skipped, transcript is still readable without it.
+fn download(files: Vec<(String, PathBuf)>) -> Box<dyn Future<Item = (), Error = ExportError>> {
+    let task = futures::stream::iter_ok(files).for_each(|(url, path)| {
+        let request = match client::get(&url).finish() {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Could not request {}: {}", &url, e);
+                return Either::A(future::ok(()));
+            }
+        };
+        let task = request
+            .send()
+            .map_err(actix_web::Error::from)
+            .and_then(|resp| {
+                let ok = resp.status().is_success();
+                resp.body()
+                    .limit(ASSET_LIMIT)
+                    .map_err(actix_web::Error::from)
+                    .map(move |body| if ok { Some(body) } else { None })
+            })
+            .then(move |res| match res {
+                Ok(Some(body)) => {
+                    if let Some(folder) = path.parent() {
+                        std::fs::create_dir_all(folder)?;
+                    }
+                    std::fs::write(&path, body.as_ref())?;
+                    Ok(())
+                }
+                Ok(None) => {
+                    warn!("Could not download {}, it is not found", &url);
+                    Ok(())
+                }
+                Err(e) => {
+                    warn!("Could not download {}: {}", &url, e);
+                    Ok(())
+                }
+            });
+        Either::B(task)
+    });
+    Box::new(task)
+}
+
+/// Files from DISCORD, that transcript shows. They are saved next to it,
+/// so it is read without network, and links to DISCORD do not live forever.
+struct Assets {
+    folder: PathBuf,
+    /// Url -> link to its local copy
+    links: HashMap<String, String>,
+    /// Files, that are linked, but not downloaded yet: url and path
+    pending: Vec<(String, PathBuf)>,
+}
+
+impl Assets {
+    /// Assets of transcript `{base}.html` lie in `{base}_files` folder.
+    fn new(base: &Path) -> Self {
+        let mut folder = base.as_os_str().to_owned();
+        folder.push("_files");
+        Assets {
+            folder: PathBuf::from(folder),
+            links: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Link to local copy of file, that is downloaded after the page.
+    fn link(&mut self, url: &str) -> String {
+        if let Some(link) = self.links.get(url) {
+            return link.clone();
+        }
+        let name = format!("{}{}", self.links.len(), extension(url));
+        let path = self.folder.join(&name);
+        // Transcript lies in the same folder as assets folder
+        let folder = self
+            .folder
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let link = encode_link(&format!("{}/{}", folder, name));
+        self.pending.push((url.to_owned(), path));
+        self.links.insert(url.to_owned(), link.clone());
+        link
+    }
+
+    fn take_pending(&mut self) -> Vec<(String, PathBuf)> {
+        std::mem::replace(&mut self.pending, Vec::new())
+    }
+}
+
+/// Extension of file in url with dot, or nothing, if url does not tell it.
+fn extension(url: &str) -> String {
+    let path = url.split(|c| c == '?' || c == '#').next().unwrap_or("");
+    let name = path.rsplit('/').next().unwrap_or("");
+    match name.rfind('.').map(|i| &name[i + 1..]) {
+        Some(extension)
+            if !extension.is_empty()
+                && extension.len() <= 5
+                && extension.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            format!(".{}", extension.to_ascii_lowercase())
+        }
+        _ => String::new(),
+    }
+}
+
+/// Export files, that are written page by page.
+struct Transcript {
+    /// Messages from REST do not know their guild
+    guild_id: Option<Snowflake>,
+    jsonl_path: PathBuf,
+    html_path: PathBuf,
+    jsonl: BufWriter<File>,
+    html: BufWriter<File>,
+    /// Attachments are linked to their saved copies, links to DISCORD do not live forever
+    archive: ArchiveIndex,
+    assets: Assets,
+    messages: usize,
+}
+
+impl Transcript {
+    fn create(channel: &ChannelPacket) -> Result<Self, ExportError> {
+        std::fs::create_dir_all(EXPORTS_FOLDER)?;
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let base = PathBuf::from(EXPORTS_FOLDER).join(format!("{}_{}", channel.id.0, started));
+        let jsonl_path = base.with_extension("jsonl");
+        let html_path = base.with_extension("html");
+        info!("Exporting channel {:?} to {:?}", &channel.id, &base);
+
+        let mut html = BufWriter::new(File::create(&html_path)?);
+        let name = channel
+            .name
+            .as_ref()
+            .map(String::as_str)
+            .unwrap_or(&channel.id.0);
+        let title = escape_html(&format!("#{}", name));
+        write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{style}</style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+            title = title,
+            style = STYLE
+        )?;
+        if let Some(topic) = channel.topic.as_ref().filter(|t| !t.is_empty()) {
+            writeln!(html, "<p class=\"topic\">{}</p>", escape_html(topic))?;
+        }
+
+        Ok(Transcript {
+            guild_id: channel.guild_id.clone(),
+            jsonl: BufWriter::new(File::create(&jsonl_path)?),
+            html,
+            jsonl_path,
+            html_path,
+            archive: ArchiveIndex::load(),
+            assets: Assets::new(&base),
+            messages: 0,
+        })
+    }
+
+    /// Files, that are written by export.
+    fn files(&self) -> Vec<PathBuf> {
+        vec![
+            self.jsonl_path.clone(),
+            self.html_path.clone(),
+            self.assets.folder.clone(),
+        ]
+    }
+
+    fn write(&mut self, page: &[MessagePacket]) -> Result<(), ExportError> {
+        for message in page {
+            serde_json::to_writer(&mut self.jsonl, message).map_err(std::io::Error::from)?;
+            self.jsonl.write_all(b"\n")?;
+            let html = render(
+                message,
+                self.guild_id.as_ref(),
+                &self.archive,
+                &mut self.assets,
+            );
+            self.html.write_all(html.as_bytes())?;
+            self.messages += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Export, ExportError> {
+        write!(
+            self.html,
+            "<p class=\"footer\">{} messages, exported {}</p>\n</body>\n</html>\n",
+            self.messages,
+            Utc::now().format("%Y-%m-%d %H:%M UTC")
+        )?;
+        self.html.flush()?;
+        self.jsonl.flush()?;
+        info!(
+            "Exported {} messages to {:?}",
+            self.messages, &self.html_path
+        );
+        Ok(Export {
+            jsonl: self.jsonl_path,
+            html: self.html_path,
+            messages: self.messages,
+        })
+    }
+}
+
+/// One message of transcript.
+fn render(
+    message: &MessagePacket,
+    guild_id: Option<&Snowflake>,
+    archive: &ArchiveIndex,
+    assets: &mut Assets,
+) -> String {
+    let author = &message.author;
+    let avatar = assets.link(
+        &author
+            .avatar_url()
+            .unwrap_or_else(|| default_avatar(author)),
+    );
+    let mut html = format!(
+        "<div class=\"message\" id=\"m{id}\">\n<img class=\"avatar\" src=\"{avatar}\" alt=\"\">\n<div class=\"body\">\n<div class=\"header\"><span class=\"author\">{author}</span> <a class=\"time\" href=\"#m{id}\">{time}</a>{edited}</div>\n",
+        id = escape_html(&message.id.0),
+        avatar = escape_html(&avatar),
+        author = escape_html(&author.username),
+        time = escape_html(&format_timestamp(&message.timestamp)),
+        edited = if message.edited_timestamp.is_some() {
+            " <span class=\"edited\">(edited)</span>"
+        } else {
+            ""
+        },
+    );
+
+    if !message.content.is_empty() {
+        html += &format!(
+            "<div class=\"content\">{}</div>\n",
+            escape_html(&resolve_mentions(message))
+        );
+    }
+    for attachment in &message.attachments {
+        html += &render_attachment(message, guild_id, attachment, archive, assets);
+    }
+    for embed in &message.embeds {
+        html += &render_embed(embed, assets);
+    }
+    if let Some(reactions) = message.reactions.as_ref().filter(|r| !r.is_empty()) {
+        html += "<div class=\"reactions\">";
+        for reaction in reactions {
+            html += &format!(
+                "<span class=\"reaction\">{} {}</span>",
+                render_emoji(&reaction.emoji, assets),
+                reaction.count
+            );
+        }
+        html += "</div>\n";
+    }
+
+    html += "</div>\n</div>\n";
+    html
+}
+
+/// Images are shown, other files are just linked.
+fn render_attachment(
+    message: &MessagePacket,
+    guild_id: Option<&Snowflake>,
+    attachment: &AttachmentPacket,
+    archive: &ArchiveIndex,
+    assets: &mut Assets,
+) -> String {
+    let link = match archive.find(guild_id, message, attachment) {
+        Some(path) => local_link(&path),
+        None => match safe_url(&attachment.url) {
+            Some(url) => assets.link(url),
+            None => return String::new(),
+        },
+    };
+    let link = escape_html(&link);
+    let name = escape_html(&attachment.filename);
+
+    if attachment.width.is_some() {
+        format!(
+            "<a href=\"{link}\"><img class=\"image\" src=\"{link}\" alt=\"{name}\"></a>\n",
+            link = link,
+            name = name
+        )
+    } else {
+        format!(
+            "<div class=\"attachment\"><a href=\"{}\">{}</a> ({} KB)</div>\n",
+            link,
+            name,
+            (attachment.size + 1023) / 1024
+        )
+    }
+}
+
+fn render_embed(embed: &EmbedPacket, assets: &mut Assets) -> String {
+    let colour = embed
+        .color
+        .map(|c| format!(" style=\"border-left-color: #{:06X}\"", c))
+        .unwrap_or_default();
+    let mut html = format!("<div class=\"embed\"{}>\n", colour);
+
+    if let Some(name) = embed.author.as_ref().and_then(|a| a.name.as_ref()) {
+        html += &format!("<div class=\"embed-author\">{}</div>\n", escape_html(name));
+    }
+    if let Some(title) = &embed.title {
+        let title = escape_html(title);
+        match embed.url.as_ref().and_then(|u| safe_url(u)) {
+            Some(url) => {
+                html += &format!(
+                    "<div class=\"embed-title\"><a href=\"{}\">{}</a></div>\n",
+                    escape_html(url),
+                    title
+                )
+            }
+            None => html += &format!("<div class=\"embed-title\">{}</div>\n", title),
+        }
+    }
+    if let Some(description) = &embed.description {
+        html += &format!(
+            "<div class=\"embed-description\">{}</div>\n",
+            escape_html(description)
+        );
+    }
+    for field in embed.fields.iter().flatten() {
+        html += &format!(
+            "<div class=\"embed-field\"><b>{}</b>\n{}</div>\n",
+            escape_html(&field.name),
+            escape_html(&field.value)
+        );
+    }
+    let image = embed
+        .image
+        .as_ref()
+        .and_then(|i| i.url.as_ref())
+        .or_else(|| embed.thumbnail.as_ref().and_then(|t| t.url.as_ref()));
+    if let Some(url) = image.and_then(|u| safe_url(u)) {
+        html += &format!(
+            "<img class=\"image\" src=\"{}\" alt=\"\">\n",
+            escape_html(&assets.link(url))
+        );
+    }
+    if let Some(footer) = &embed.footer {
+        html += &format!(
+            "<div class=\"embed-footer\">{}</div>\n",
+            escape_html(&footer.text)
+        );
+    }
+
+    html += "</div>\n";
+    html
+}
+
+/// Unicode emoji as is, custom one as image.
+fn render_emoji(emoji: &EmojiPacket, assets: &mut Assets) -> String {
+    match (&emoji.id, &emoji.name) {
+        (Some(id), name) => format!(
+            "<img class=\"emoji\" src=\"{}\" alt=\"{}\">",
+            escape_html(&assets.link(&format!("https://cdn.discordapp.com/emojis/{}.png", id.0))),
+            escape_html(name.as_ref().map(String::as_str).unwrap_or(""))
+        ),
+        (None, Some(name)) => escape_html(name),
+        (None, None) => "?".to_owned(),
+    }
+}
+
+/// Content with user mentions replaced by names, ids say nothing to reader.
+fn resolve_mentions(message: &MessagePacket) -> String {
+    message
+        .mentions
+        .iter()
+        .fold(message.content.clone(), |content, user| {
+            let name = format!("@{}", user.username);
+            content
+                .replace(&format!("<@{}>", user.id.0), &name)
+                .replace(&format!("<@!{}>", user.id.0), &name)
+        })
+}
+
+/// Avatar, that DISCORD shows for users without their own.
+fn default_avatar(user: &UserPacket) -> String {
+    let number = user.discriminator.parse::<u64>().unwrap_or(0) % 5;
+    format!("https://cdn.discordapp.com/embed/avatars/{}.png", number)
+}
+
+fn format_timestamp(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| {
+            t.with_timezone(&Utc)
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string()
+        })
+        .unwrap_or_else(|_| timestamp.to_owned())
+}
+
+/// Only web links are followed, `javascript:` in embed must not run in transcript.
+fn safe_url(url: &str) -> Option<&str> {
+    if url.starts_with("https://") || url.starts_with("http://") {
+        Some(url)
+    } else {
+        None
+    }
+}
+
+/// Link to saved file. Transcript lies in exports folder, relative paths start one level up.
+fn local_link(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{}", encode_link(&path))
+    } else {
+        encode_link(&format!("../{}", path))
+    }
+}
+
+/// Percent-encodes link to local file, slashes are kept.
+fn encode_link(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Registers command, that exports channel.
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        Command::new(
+            "export",
+            "Saves the whole history of channel to JSONL and HTML files on my host",
+            |ctx, args| start(ctx, args.channel("channel").unwrap()),
+        )
+        .argument("channel", ArgumentType::Channel)
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::ADMINISTRATOR),
+    );
+}
+
+fn start(ctx: &CommandContext, channel_id: &Snowflake) -> CommandResult {
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    let in_guild = ctx
+        .cache
+        .channel(channel_id)
+        .map(|c| c.guild_id.as_ref() == Some(guild_id))
+        .unwrap_or(false);
+    if !in_guild {
+        return Err(CommandError::Usage(ctx.text("channel-not-in-guild", &[])));
+    }
+    ctx.reply(&ctx.text("export-started", &[("channel", &channel_id.0)]));
+
+    let language = ctx.language;
+    let rest = ctx.rest.clone();
+    let reply_to = ctx.message.channel_id.clone();
+    let exported = channel_id.clone();
+    Arbiter::spawn(export(&ctx.rest, channel_id).then(move |res| {
+        let text = match res {
+            Ok(export) => i18n::plural(
+                language,
+                "export-done",
+                export.messages as i64,
+                &[
+                    ("channel", &exported.0),
+                    ("jsonl", &export.jsonl.display()),
+                    ("html", &export.html.display()),
+                ],
+            ),
+            Err(e) => {
+                error!("Export of {:?} failed: {}", &exported, e);
+                i18n::text(language, "export-failed", &[("channel", &exported.0)])
+            }
+        };
+        rest.say(&reply_to, &MessageRequestPacket::simple_text(&text));
+        Ok(())
+    }));
+    Ok(())
+}
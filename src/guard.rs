@@ -0,0 +1,185 @@
+use crate::command::CommandContext;
+use crate::discord::Snowflake;
+use crate::i18n::{self, Language};
+use crate::permissions::Permissions;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Condition, that has to be met before command is executed.
+#[derive(Debug, Clone)]
+pub enum Guard {
+    /// Author must have all these permissions in the channel
+    UserPermissions(Permissions),
+    /// I must have all these permissions in the channel
+    BotPermissions(Permissions),
+    /// Only bot owners from config
+    OwnerOnly,
+    /// Command does not make sense outside of guilds
+    GuildOnly,
+    /// Command does not make sense inside of guilds
+    DirectOnly,
+    /// Author must have at least one of these roles
+    Roles(Vec<Snowflake>),
+}
+
+impl Guard {
+    /// Checks guard, on failure returns friendly explanation.
+    pub fn check(&self, ctx: &CommandContext) -> Result<(), String> {
+        match self {
+            Guard::UserPermissions(permissions) => match ctx.author_permissions() {
+                Some(p) if p.contains(*permissions) => Ok(()),
+                Some(p) => Err(ctx.text(
+                    "guard-user-permissions",
+                    &[("permissions", &format!("{:?}", *permissions - p))],
+                )),
+                None if ctx.message.is_direct() => Err(ctx.text("guard-guild-only", &[])),
+                // Guild, that is not fully cached yet
+                None => Err(ctx.text("guard-user-permissions-unknown", &[])),
+            },
+            Guard::BotPermissions(permissions) => match ctx.bot_permissions() {
+                Some(p) if p.contains(*permissions) => Ok(()),
+                Some(p) => Err(ctx.text(
+                    "guard-bot-permissions",
+                    &[("permissions", &format!("{:?}", *permissions - p))],
+                )),
+                // I can do everything, that makes sense in direct messages
+                None if ctx.message.is_direct() => Ok(()),
+                None => Err(ctx.text("guard-bot-permissions-unknown", &[])),
+            },
+            Guard::OwnerOnly => {
+                if ctx.commands.owners().contains(&ctx.message.author.id) {
+                    Ok(())
+                } else {
+                    Err(ctx.text("guard-owner-only", &[]))
+                }
+            }
+            Guard::GuildOnly => {
+                if ctx.message.is_direct() {
+                    Err(ctx.text("guard-guild-only", &[]))
+                } else {
+                    Ok(())
+                }
+            }
+            Guard::DirectOnly => {
+                if ctx.message.is_direct() {
+                    Ok(())
+                } else {
+                    Err(ctx.text("guard-direct-only", &[]))
+                }
+            }
+            Guard::Roles(roles) => {
+                let has_role = ctx
+                    .message
+                    .member
+                    .as_ref()
+                    .map(|m| m.roles.iter().any(|r| roles.contains(r)))
+                    .unwrap_or(false);
+                if has_role {
+                    Ok(())
+                } else {
+                    Err(ctx.text("guard-roles", &[]))
+                }
+            }
+        }
+    }
+
+    /// Whether guard is about author of message (and not about me).
+    pub fn is_about_author(&self) -> bool {
+        !matches!(self, Guard::BotPermissions(_))
+    }
+
+    /// Human readable requirement for help.
+    pub fn describe(&self, language: Language) -> String {
+        match self {
+            Guard::UserPermissions(p) => i18n::text(
+                language,
+                "guard-user-permissions",
+                &[("permissions", &format!("{:?}", p))],
+            ),
+            Guard::BotPermissions(p) => i18n::text(
+                language,
+                "guard-bot-permissions",
+                &[("permissions", &format!("{:?}", p))],
+            ),
+            Guard::OwnerOnly => i18n::text(language, "requirement-owner-only", &[]),
+            Guard::GuildOnly => i18n::text(language, "requirement-guild-only", &[]),
+            Guard::DirectOnly => i18n::text(language, "requirement-direct-only", &[]),
+            Guard::Roles(roles) => {
+                let roles = roles
+                    .iter()
+                    .map(|r| format!("<@&{}>", r.0))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                i18n::text(language, "requirement-roles", &[("roles", &roles)])
+            }
+        }
+    }
+}
+
+/// Who shares cooldown.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bucket {
+    User,
+    Channel,
+    /// Channel is used for direct messages
+    Guild,
+}
+
+/// Command can be used `uses` times per `per` in a bucket.
+#[derive(Debug, Clone)]
+pub struct Cooldown {
+    pub bucket: Bucket,
+    pub uses: usize,
+    pub per: Duration,
+}
+
+impl Cooldown {
+    fn key(&self, ctx: &CommandContext) -> Snowflake {
+        let message = ctx.message;
+        match self.bucket {
+            Bucket::User => message.author.id.clone(),
+            Bucket::Channel => message.channel_id.clone(),
+            Bucket::Guild => message
+                .guild_id
+                .clone()
+                .unwrap_or_else(|| message.channel_id.clone()),
+        }
+    }
+}
+
+/// Remembers recent uses of commands, by the time when they stop counting.
+#[derive(Debug, Default)]
+pub struct CooldownTracker {
+    uses: RefCell<HashMap<(&'static str, Snowflake), VecDeque<Instant>>>,
+}
+
+impl CooldownTracker {
+    /// Registers use of command.
+    /// If command is on cooldown, returns time to wait instead.
+    pub fn hit(
+        &self,
+        command: &'static str,
+        cooldown: &Cooldown,
+        ctx: &CommandContext,
+    ) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut uses = self.uses.borrow_mut();
+        // Forget expired uses, so users, that are gone, are not remembered forever
+        uses.retain(|_, recent| {
+            while recent.front().map(|t| *t <= now).unwrap_or(false) {
+                recent.pop_front();
+            }
+            !recent.is_empty()
+        });
+
+        let recent = uses.entry((command, cooldown.key(ctx))).or_default();
+        if recent.len() >= cooldown.uses.max(1) {
+            let expires = *recent.front().unwrap();
+            return Err(expires - now);
+        }
+
+        recent.push_back(now + cooldown.per);
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use log::{debug, error};
+
+use crate::cache::Cache;
+use crate::discord::*;
+use crate::rest::Rest;
+use crate::settings::Settings;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+pub type HandlerResult = Result<(), Box<dyn std::error::Error>>;
+
+/// What engine knows, while handlers are running.
+pub struct EventContext<'a> {
+    /// Myself identifier
+    pub myself_id: &'a Snowflake,
+    /// Everything engine knows about DISCORD
+    pub cache: &'a Cache,
+    /// Persistent settings of guilds, channels and users
+    pub settings: &'a Settings,
+    /// Handle to DISCORD REST API
+    pub rest: Rest,
+    /// Event is not passed to the rest of handlers
+    stopped: Cell<bool>,
+}
+
+impl<'a> EventContext<'a> {
+    pub fn new(
+        myself_id: &'a Snowflake,
+        cache: &'a Cache,
+        settings: &'a Settings,
+        rest: Rest,
+    ) -> Self {
+        EventContext {
+            myself_id,
+            cache,
+            settings,
+            rest,
+            stopped: Cell::new(false),
+        }
+    }
+
+    /// Handlers after the current one will not see the event,
+    /// e.g. commands do not run from message, that automod deleted.
+    pub fn stop(&self) {
+        self.stopped.set(true);
+    }
+}
+
+/// Reacts to gateway events.
+/// Every method does nothing by default, so handler implements only events it cares about.
+/// Cache is already updated, when handler is called.
+#[allow(unused_variables)]
+pub trait EventHandler {
+    /// Name for logs
+    fn name(&self) -> &'static str;
+
+    /// Called every second, for timed actions.
+    fn on_tick(&mut self, ctx: &EventContext) -> HandlerResult {
+        Ok(())
+    }
+    fn on_ready(&mut self, ctx: &EventContext, ready: &ReadyPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_channel_create(&mut self, ctx: &EventContext, channel: &ChannelPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_channel_update(&mut self, ctx: &EventContext, channel: &ChannelPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_channel_delete(&mut self, ctx: &EventContext, channel: &ChannelPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_channel_pins_update(
+        &mut self,
+        ctx: &EventContext,
+        pins: &ChannelPinsUpdatePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_guild_create(&mut self, ctx: &EventContext, guild: &GuildPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_guild_update(&mut self, ctx: &EventContext, guild: &GuildPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_guild_delete(
+        &mut self,
+        ctx: &EventContext,
+        guild: &UnavailableGuildPacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_ban_add(&mut self, ctx: &EventContext, ban: &GuildBanPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_ban_remove(&mut self, ctx: &EventContext, ban: &GuildBanPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_emojis_update(
+        &mut self,
+        ctx: &EventContext,
+        emojis: &GuildEmojisUpdatePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_member_add(
+        &mut self,
+        ctx: &EventContext,
+        member: &GuildMemberAddPacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_member_update(
+        &mut self,
+        ctx: &EventContext,
+        member: &GuildMemberUpdatePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_member_remove(
+        &mut self,
+        ctx: &EventContext,
+        member: &GuildMemberRemovePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_role_create(&mut self, ctx: &EventContext, role: &GuildRolePacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_role_update(&mut self, ctx: &EventContext, role: &GuildRolePacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_role_delete(
+        &mut self,
+        ctx: &EventContext,
+        role: &GuildRoleDeletePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_message(&mut self, ctx: &EventContext, message: &MessagePacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_message_update(
+        &mut self,
+        ctx: &EventContext,
+        message: &MessageUpdatePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_message_delete(
+        &mut self,
+        ctx: &EventContext,
+        message: &MessageDeletePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_message_delete_bulk(
+        &mut self,
+        ctx: &EventContext,
+        messages: &MessageDeleteBulkPacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_reaction_add(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_reaction_remove(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_reaction_remove_all(
+        &mut self,
+        ctx: &EventContext,
+        reactions: &MessageReactionRemoveAllPacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_presence_update(
+        &mut self,
+        ctx: &EventContext,
+        presence: &PresenceUpdatePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_typing_start(&mut self, ctx: &EventContext, typing: &TypingStartPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_user_update(&mut self, ctx: &EventContext, user: &UserPacket) -> HandlerResult {
+        Ok(())
+    }
+    fn on_voice_state_update(
+        &mut self,
+        ctx: &EventContext,
+        voice_state: &VoiceStatePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+    fn on_webhooks_update(
+        &mut self,
+        ctx: &EventContext,
+        webhooks: &WebhooksUpdatePacket,
+    ) -> HandlerResult {
+        Ok(())
+    }
+}
+
+/// Calls handlers in order of registration.
+/// Error or panic in one handler is logged and does not stop the rest,
+/// only `EventContext::stop` does.
+pub fn notify<F>(handlers: &mut [Box<dyn EventHandler>], ctx: &EventContext, event: &str, f: F)
+where
+    F: Fn(&mut dyn EventHandler, &EventContext) -> HandlerResult,
+{
+    for handler in handlers.iter_mut() {
+        let res = panic::catch_unwind(AssertUnwindSafe(|| f(handler.as_mut(), ctx)));
+        match res {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Handler {} failed on {}: {}", handler.name(), event, e),
+            Err(_) => error!("Handler {} panicked on {}", handler.name(), event),
+        }
+        if ctx.stopped.get() {
+            debug!("Handler {} stopped {}", handler.name(), event);
+            break;
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::builder::{EmbedBuilder, MessageBuilder};
+use crate::command::*;
+use crate::guard::Bucket;
+use crate::i18n;
+use crate::paginator::Paginator;
+use std::time::Duration;
+
+/// How many commands are shown on one help page
+const COMMANDS_PER_PAGE: usize = 10;
+/// Colour of help embeds
+const HELP_COLOUR: u32 = 0xDC_14_3C;
+
+/// Registers help command.
+/// Help is generated from the registry itself, so it never lies about commands.
+pub fn register(registry: &mut CommandRegistry, paginator: &Paginator) {
+    let paginator = paginator.clone();
+    registry.register(
+        Command::new(
+            "help",
+            "Shows all commands, or details of one command",
+            move |ctx, args| match args.text("query") {
+                None => list(ctx, &paginator, 1),
+                Some(query) => match query.parse::<usize>() {
+                    Ok(page) => list(ctx, &paginator, page),
+                    Err(_) => details(ctx, query),
+                },
+            },
+        )
+        .alias("commands")
+        .optional_argument("query", ArgumentType::Text)
+        .cooldown(Bucket::Channel, 2, Duration::from_secs(10)),
+    );
+}
+
+/// Commands, that author of message is allowed to use.
+fn visible_commands<'a>(ctx: &'a CommandContext) -> Vec<&'a Command> {
+    ctx.commands
+        .commands()
+        .iter()
+        .filter(|c| c.is_allowed(ctx))
+        .collect()
+}
+
+/// All commands, starting from given page.
+fn list(ctx: &CommandContext, paginator: &Paginator, page: usize) -> CommandResult {
+    let commands = visible_commands(ctx);
+    let pages = (commands.len() + COMMANDS_PER_PAGE - 1) / COMMANDS_PER_PAGE;
+    if page == 0 || page > pages {
+        return Err(CommandError::Usage(ctx.plural(
+            "help-no-page",
+            pages as i64,
+            &[("page", &page)],
+        )));
+    }
+
+    let prefix = ctx.prefix();
+    let embeds = commands
+        .chunks(COMMANDS_PER_PAGE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            chunk
+                .iter()
+                .fold(
+                    EmbedBuilder::new()
+                        .title(&ctx.text("help-title", &[]))
+                        .colour(HELP_COLOUR),
+                    |embed, c| {
+                        embed.field(
+                            &format!("{}{} {}", prefix, c.name, c.signature()),
+                            &describe(ctx, c),
+                            false,
+                        )
+                    },
+                )
+                .footer(
+                    &ctx.text(
+                        "help-footer",
+                        &[("page", &(i + 1)), ("pages", &pages), ("prefix", &prefix)],
+                    ),
+                    None,
+                )
+        })
+        .collect();
+
+    paginator.send(ctx, embeds, page - 1)
+}
+
+/// Details of one command.
+fn details(ctx: &CommandContext, name: &str) -> CommandResult {
+    let command = match ctx.commands.find(name) {
+        Some(command) if command.is_allowed(ctx) => command,
+        _ => {
+            return Err(CommandError::Usage(
+                ctx.text("help-no-command", &[("command", &name)]),
+            ))
+        }
+    };
+
+    let prefix = ctx.prefix();
+    let mut embed = EmbedBuilder::new()
+        .title(&format!("{}{}", prefix, command.name))
+        .description(&description(ctx, command))
+        .colour(HELP_COLOUR)
+        .field(
+            &ctx.text("help-usage", &[]),
+            &format!("`{}{} {}`", prefix, command.name, command.signature()),
+            false,
+        );
+
+    if !command.aliases.is_empty() {
+        embed = embed.field(
+            &ctx.text("help-aliases", &[]),
+            &command.aliases.join(", "),
+            false,
+        );
+    }
+    if !command.guards.is_empty() {
+        let requirements = command
+            .guards
+            .iter()
+            .map(|g| g.describe(ctx.language))
+            .collect::<Vec<String>>()
+            .join("\n");
+        embed = embed.field(&ctx.text("help-requirements", &[]), &requirements, false);
+    }
+    if let Some(cooldown) = &command.cooldown {
+        let bucket = match cooldown.bucket {
+            Bucket::User => "help-bucket-user",
+            Bucket::Channel => "help-bucket-channel",
+            Bucket::Guild => "help-bucket-guild",
+        };
+        embed = embed.field(
+            &ctx.text("help-cooldown", &[]),
+            &ctx.plural(
+                "help-cooldown-value",
+                cooldown.uses as i64,
+                &[
+                    ("seconds", &cooldown.per.as_secs()),
+                    ("bucket", &ctx.text(bucket, &[])),
+                ],
+            ),
+            false,
+        );
+    }
+
+    ctx.send(MessageBuilder::new().embed(embed))
+}
+
+/// Description of command in language of answers, commands have it in English.
+fn description(ctx: &CommandContext, command: &Command) -> String {
+    i18n::find(ctx.language, &format!("description-{}", command.name), &[])
+        .unwrap_or_else(|| command.description.to_owned())
+}
+
+fn describe(ctx: &CommandContext, command: &Command) -> String {
+    if command.aliases.is_empty() {
+        description(ctx, command)
+    } else {
+        format!(
+            "{}\n{}: {}",
+            description(ctx, command),
+            ctx.text("help-aliases", &[]),
+            command.aliases.join(", ")
+        )
+    }
+}
@@ -0,0 +1,430 @@
+use lazy_static::lazy_static;
+use log::{error, warn};
+
+use crate::command::*;
+use crate::discord::Snowflake;
+use crate::guard::Guard;
+use crate::permissions::Permissions;
+use crate::settings::{Key, Scope, Settings};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// Language chosen by admins of guild, or by user for themselves
+pub const LANGUAGE: Key<Language> = Key::new("language");
+/// Every message has English text, other languages fall back to it
+pub const DEFAULT_LANGUAGE: Language = Language::En;
+pub const LANGUAGES: &[Language] = &[Language::En, Language::Ru];
+
+/// Language of bot responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    En,
+    Ru,
+}
+
+impl Language {
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::Ru => "ru",
+        }
+    }
+
+    /// Name of language in itself, so everybody finds their own.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::En => "English",
+            Language::Ru => "Русский",
+        }
+    }
+
+    /// Language of code like "ru", or of DISCORD locale like "en-US".
+    pub fn from_locale(locale: &str) -> Option<Language> {
+        let code = locale
+            .split(|c| c == '-' || c == '_')
+            .next()?
+            .to_lowercase();
+        LANGUAGES.iter().cloned().find(|l| l.code() == code)
+    }
+
+    /// Catalog is compiled into bot, so it can not go missing.
+    fn source(self) -> &'static str {
+        match self {
+            Language::En => include_str!("../locales/en.yaml"),
+            Language::Ru => include_str!("../locales/ru.yaml"),
+        }
+    }
+
+    /// Plural form of number, as named by CLDR.
+    fn plural_form(self, count: i64) -> &'static str {
+        let n = count.abs();
+        match self {
+            Language::En if n == 1 => "one",
+            Language::En => "other",
+            Language::Ru if n % 10 == 1 && n % 100 != 11 => "one",
+            Language::Ru if (2..=4).contains(&(n % 10)) && !(12..=14).contains(&(n % 100)) => "few",
+            Language::Ru => "many",
+        }
+    }
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        DEFAULT_LANGUAGE
+    }
+}
+
+/// Text of catalog. Plural one has text for every plural form of language.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Message {
+    Simple(String),
+    Plural(HashMap<String, String>),
+}
+
+type Catalog = HashMap<String, Message>;
+
+lazy_static! {
+    static ref CATALOGS: HashMap<Language, Catalog> = LANGUAGES
+        .iter()
+        .map(|&language| {
+            let catalog = serde_yaml::from_str(language.source())
+                .unwrap_or_else(|e| panic!("Failed to parse catalog {}: {}", language.code(), e));
+            (language, catalog)
+        })
+        .collect();
+}
+
+/// Arguments of message, e.g. `&[("command", &name)]` for `{command}`.
+pub type MessageArgs<'a> = [(&'a str, &'a dyn Display)];
+
+/// Message in language, or in English, if it is not translated yet.
+fn lookup(language: Language, key: &str) -> Option<(Language, &'static Message)> {
+    let find = |language: Language| CATALOGS.get(&language)?.get(key);
+    if let Some(message) = find(language) {
+        return Some((language, message));
+    }
+    let message = find(DEFAULT_LANGUAGE)?;
+    if language != DEFAULT_LANGUAGE {
+        warn!("Message {} is not translated to {}", key, language.code());
+    }
+    Some((DEFAULT_LANGUAGE, message))
+}
+
+/// Translated message with arguments put into `{name}` placeholders.
+/// Unknown messages are shown as their keys, so they are noticed.
+pub fn text(language: Language, key: &str, args: &MessageArgs) -> String {
+    match lookup(language, key) {
+        Some((_, Message::Simple(template))) => interpolate(template, args),
+        Some((_, Message::Plural(_))) => {
+            error!("Message {} is plural, but has no count", key);
+            key.to_owned()
+        }
+        None => {
+            error!("There is no message {}", key);
+            key.to_owned()
+        }
+    }
+}
+
+/// Message, that is translated to exactly this language, e.g. description of command.
+/// Caller has its own English text, so nothing is logged when it is missing.
+pub fn find(language: Language, key: &str, args: &MessageArgs) -> Option<String> {
+    match CATALOGS.get(&language)?.get(key)? {
+        Message::Simple(template) => Some(interpolate(template, args)),
+        Message::Plural(_) => None,
+    }
+}
+
+/// Translated message in plural form for count.
+/// Count is available to message as `{count}` too.
+pub fn plural(language: Language, key: &str, count: i64, args: &MessageArgs) -> String {
+    let mut all: Vec<(&str, &dyn Display)> = vec![("count", &count)];
+    all.extend_from_slice(args);
+
+    match lookup(language, key) {
+        Some((_, Message::Simple(template))) => interpolate(template, &all),
+        Some((found, Message::Plural(forms))) => {
+            match forms
+                .get(found.plural_form(count))
+                .or_else(|| forms.get("other"))
+            {
+                Some(template) => interpolate(template, &all),
+                None => {
+                    error!("Message {} has no form for {}", key, count);
+                    key.to_owned()
+                }
+            }
+        }
+        None => {
+            error!("There is no message {}", key);
+            key.to_owned()
+        }
+    }
+}
+
+/// Replaces `{name}` with arguments in one pass, so text of arguments is never replaced.
+/// Unknown placeholders are left as is.
+pub fn interpolate(template: &str, args: &MessageArgs) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            let (_, value) = args.iter().find(|(n, _)| *n == name)?;
+            Some((value.to_string(), end))
+        });
+        match value {
+            Some((value, end)) => {
+                result.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Language of guild from settings, otherwise the one, that user chose for themselves.
+/// DISCORD does not tell locale of users to bots, so they have to choose it.
+/// Broken settings are logged and treated as absent.
+pub fn language(
+    settings: &Settings,
+    guild_id: Option<&Snowflake>,
+    user_id: &Snowflake,
+) -> Language {
+    guild_id
+        .and_then(|id| chosen(settings, Scope::Guild, id))
+        .or_else(|| chosen(settings, Scope::User, user_id))
+        .unwrap_or(DEFAULT_LANGUAGE)
+}
+
+/// Language of guild for posts, that nobody in particular asked for, like logs and reposts.
+pub fn guild_language(settings: &Settings, guild_id: &Snowflake) -> Language {
+    chosen(settings, Scope::Guild, guild_id).unwrap_or(DEFAULT_LANGUAGE)
+}
+
+fn chosen(settings: &Settings, scope: Scope, id: &Snowflake) -> Option<Language> {
+    settings.get(&LANGUAGE, scope, id).unwrap_or_else(|e| {
+        error!("Failed to get language of {:?}: {}", id, e);
+        None
+    })
+}
+
+/// Registers commands, that choose language of guild and of user.
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        Command::new(
+            "language",
+            "Shows or sets my language here, `auto` follows language of every user",
+            |ctx, args| match args.text("language") {
+                Some(code) => set_language(ctx, code),
+                None => {
+                    show_language(ctx);
+                    Ok(())
+                }
+            },
+        )
+        .alias("lang")
+        .optional_argument("language", ArgumentType::Text)
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+
+    registry.register(
+        Command::new(
+            "mylanguage",
+            "Shows or sets my language for you, where guild did not choose one",
+            |ctx, args| match args.text("language") {
+                Some(code) => set_own_language(ctx, code),
+                None => {
+                    show_own_language(ctx);
+                    Ok(())
+                }
+            },
+        )
+        .alias("mylang")
+        .optional_argument("language", ArgumentType::Text),
+    );
+}
+
+fn known_languages() -> String {
+    LANGUAGES
+        .iter()
+        .map(|l| format!("`{}` {}", l.code(), l.name()))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn show_language(ctx: &CommandContext) {
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    let known = known_languages();
+    let key = match ctx.settings.get(&LANGUAGE, Scope::Guild, guild_id) {
+        Ok(Some(_)) => "language-current",
+        _ => "language-auto",
+    };
+    ctx.reply(&ctx.text(
+        key,
+        &[("language", &ctx.language.name()), ("known", &known)],
+    ));
+}
+
+fn set_language(ctx: &CommandContext, code: &str) -> CommandResult {
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    let saved = if code.eq_ignore_ascii_case("auto") {
+        ctx.settings
+            .remove(&LANGUAGE, Scope::Guild, guild_id)
+            .map(|_| ())
+    } else {
+        let language = Language::from_locale(code)
+            .ok_or_else(|| CommandError::Usage(ctx.text("language-unknown", &[("code", &code)])))?;
+        ctx.settings
+            .set(&LANGUAGE, Scope::Guild, guild_id, &language)
+    };
+    saved.map_err(|e| {
+        error!("Failed to save language of {:?}: {}", guild_id, e);
+        CommandError::Failed(ctx.text("setting-failed", &[]))
+    })?;
+
+    // Answer in the language, that was just chosen
+    let language = language(ctx.settings, Some(guild_id), &ctx.message.author.id);
+    if code.eq_ignore_ascii_case("auto") {
+        ctx.reply(&text(language, "language-auto-set", &[]));
+    } else {
+        ctx.reply(&text(
+            language,
+            "language-set",
+            &[("language", &language.name())],
+        ));
+    }
+    Ok(())
+}
+
+fn show_own_language(ctx: &CommandContext) {
+    let user_id = &ctx.message.author.id;
+    let own = match ctx.settings.get(&LANGUAGE, Scope::User, user_id) {
+        Ok(Some(language)) => language,
+        _ => DEFAULT_LANGUAGE,
+    };
+    ctx.reply(&ctx.text(
+        "my-language-current",
+        &[("language", &own.name()), ("known", &known_languages())],
+    ));
+}
+
+fn set_own_language(ctx: &CommandContext, code: &str) -> CommandResult {
+    let user_id = &ctx.message.author.id;
+    let language = Language::from_locale(code)
+        .ok_or_else(|| CommandError::Usage(ctx.text("language-unknown", &[("code", &code)])))?;
+    ctx.settings
+        .set(&LANGUAGE, Scope::User, user_id, &language)
+        .map_err(|e| {
+            error!("Failed to save language of {:?}: {}", user_id, e);
+            CommandError::Failed(ctx.text("setting-failed", &[]))
+        })?;
+    ctx.reply(&text(
+        language,
+        "my-language-set",
+        &[("language", &language.name())],
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_plural() {
+        assert_eq!(Language::En.plural_form(1), "one");
+        assert_eq!(Language::En.plural_form(-1), "one");
+        for &n in &[0, 2, 11, 21, 101] {
+            assert_eq!(Language::En.plural_form(n), "other", "{}", n);
+        }
+    }
+
+    #[test]
+    fn russian_plural() {
+        let form = |n| Language::Ru.plural_form(n);
+        for &n in &[1, 21, 101, 121, 1001, -1] {
+            assert_eq!(form(n), "one", "{}", n);
+        }
+        for &n in &[2, 3, 4, 22, 34, 102, -2] {
+            assert_eq!(form(n), "few", "{}", n);
+        }
+        for &n in &[0, 5, 10, 11, 12, 13, 14, 15, 20, 25, 111, 112, 114, 1000] {
+            assert_eq!(form(n), "many", "{}", n);
+        }
+    }
+
+    #[test]
+    fn interpolate_once() {
+        assert_eq!(
+            interpolate("{a} and {b}", &[("a", &"{b}"), ("b", &"{a}")]),
+            "{b} and {a}"
+        );
+        assert_eq!(interpolate("{a}{a}", &[("a", &1)]), "11");
+    }
+
+    #[test]
+    fn interpolate_unknown() {
+        assert_eq!(
+            interpolate("{a} {unknown} {", &[("a", &"x")]),
+            "x {unknown} {"
+        );
+        assert_eq!(interpolate("{{a}}", &[("a", &"x")]), "{x}");
+        assert_eq!(interpolate("no placeholders", &[]), "no placeholders");
+    }
+
+    #[test]
+    fn plural_text() {
+        assert_eq!(
+            plural(Language::Ru, "audit-deleted-bulk", 3, &[]),
+            "Удалено 3 сообщения"
+        );
+        assert_eq!(
+            plural(Language::Ru, "audit-deleted-bulk", 11, &[]),
+            "Удалено 11 сообщений"
+        );
+        assert_eq!(
+            plural(Language::En, "audit-deleted-bulk", 1, &[]),
+            "1 message deleted"
+        );
+    }
+
+    #[test]
+    fn fallback() {
+        assert_eq!(
+            text(Language::En, "no-such-message", &[]),
+            "no-such-message"
+        );
+        assert_eq!(find(Language::Ru, "no-such-message", &[]), None);
+    }
+
+    #[test]
+    fn catalogs_match() {
+        let english = &CATALOGS[&Language::En];
+        for (key, message) in &CATALOGS[&Language::Ru] {
+            let original = english.get(key);
+            // English descriptions are in commands themselves
+            assert!(
+                original.is_some() || key.starts_with("description-"),
+                "{} is not in English catalog",
+                key
+            );
+            if let (Message::Plural(forms), Some(Message::Plural(_))) = (message, original) {
+                for form in &["one", "few", "many"] {
+                    assert!(forms.contains_key(*form), "{} has no {}", key, form);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// Map with limited size, that forgets least recently used entries.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// Incremented on every use, entry with the smallest tick is the oldest
+    tick: u64,
+    entries: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    /// Inserts entry, the oldest one is forgotten if there is no space.
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+        if self.entries.len() >= self.capacity {
+            let oldest = self.order.keys().next().cloned();
+            if let Some(oldest) = oldest.and_then(|t| self.order.remove(&t)) {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    /// Entry, that becomes the most recently used.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let (value, used) = self.entries.get_mut(key)?;
+        self.tick += 1;
+        self.order.remove(used);
+        self.order.insert(self.tick, key.clone());
+        *used = self.tick;
+        Some(value)
+    }
+
+    /// Entry without changing the order.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.keys()
+    }
+
+    /// All entries without changing the order.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.entries.values_mut().map(|(value, _)| value)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, used) = self.entries.remove(key)?;
+        self.order.remove(&used);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_entry_is_forgotten() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        cache.insert(3, "three");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.peek(&2), Some(&"two"));
+        assert_eq!(cache.peek(&3), Some(&"three"));
+    }
+
+    #[test]
+    fn used_entry_becomes_the_newest() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        cache.get_mut(&1);
+        cache.insert(3, "three");
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+
+        // Peek does not change the order
+        cache.peek(&3);
+        cache.insert(4, "four");
+        assert!(!cache.contains_key(&1));
+    }
+
+    #[test]
+    fn inserting_again_replaces_entry() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(1, "uno");
+        cache.insert(2, "two");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.peek(&1), Some(&"uno"));
+    }
+
+    #[test]
+    fn removed_entry_frees_space() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        assert_eq!(cache.remove(&1), Some("one"));
+        cache.insert(3, "three");
+        assert!(cache.contains_key(&2));
+        assert!(cache.contains_key(&3));
+    }
+
+    #[test]
+    fn nothing_is_kept_without_capacity() {
+        let mut cache = LruCache::new(0);
+        cache.insert(1, "one");
+        assert!(cache.is_empty());
+    }
+}
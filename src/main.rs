@@ -13,28 +13,71 @@ use engine::Engine;
 use futures::Future;
 use log::{debug, error};
 
+mod archive;
+mod audit;
+mod automod;
+mod builder;
+mod cache;
+mod collector;
+mod command;
 mod connector;
+mod cron;
 mod data;
 mod discord;
 mod engine;
+mod export;
+mod guard;
+mod handler;
+mod help;
+mod i18n;
+mod lru;
+mod modlog;
+mod paginator;
+mod parser;
+mod permissions;
+mod polls;
+mod reaction_roles;
+mod rest;
+mod sanitize;
+mod scheduler;
+mod settings;
+mod starboard;
+mod storage;
+mod welcome;
 
 fn main() -> Result<(), Box<std::error::Error>> {
     let sys = actix::System::new("my-little-discord");
 
     log4rs::init_file("conf/log4rs.yaml", Default::default())?;
 
-    debug!("Trying to create pool");
-    let p: &data::Pool = &data::POOL;
-    debug!("Done with pool");
+    // Task is parsed before pool, so tasks without DISCORD do not read the key
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let task = parse_task(&args)?;
 
     debug!("Starting files folder check");
     create_files_folder()?;
     debug!("Finished files folder");
 
+    if let Some(Task::MigrateFiles) = task {
+        let moved = archive::migrate(&data::Config::load().archive)?;
+        println!("Moved {} files to path template", moved);
+        return Ok(());
+    }
+
+    debug!("Trying to create pool");
+    let p: &data::Pool = &data::POOL;
+    debug!("Done with pool");
+
     let _addr = register_actor(RequestConnector {
         key_header: format!("Bot {}", &p.key),
     });
 
+    if let Some(Task::Export(channel_id)) = task {
+        export_channel(channel_id);
+        let _ = sys.run();
+        return Ok(());
+    }
+
     // Just for test
     Arbiter::spawn({
         System::current()
@@ -72,7 +115,7 @@ fn main() -> Result<(), Box<std::error::Error>> {
                     WssConnector {
                         writer,
                         last_sequence: None,
-                        engine: Engine::new(),
+                        engine: create_engine(),
                     }
                 });
 
@@ -86,6 +129,48 @@ fn main() -> Result<(), Box<std::error::Error>> {
     Ok(())
 }
 
+/// Task from command line, that is run instead of bot.
+enum Task {
+    /// Needs the key, as messages are fetched from DISCORD
+    Export(discord::Snowflake),
+    /// Works with local files only, so the key is not needed
+    MigrateFiles,
+}
+
+/// Parses arguments, which are the key and optional task after it.
+/// Key is not read here, so it is not required by tasks, that do not need it.
+fn parse_task(args: &[String]) -> Result<Option<Task>, Box<dyn std::error::Error>> {
+    match args {
+        [mode] | [_, mode] if mode == "migrate-files" => Ok(Some(Task::MigrateFiles)),
+        [_] | [] => Ok(None),
+        [_, mode, channel_id] if mode == "export" => {
+            Ok(Some(Task::Export(discord::Snowflake(channel_id.clone()))))
+        }
+        [_, task @ ..] => Err(format!(
+            "Unknown task {:?}. Known tasks are: export <channel id>, migrate-files",
+            task
+        )
+        .into()),
+    }
+}
+
+/// Exports channel in background, system is stopped when it is done.
+fn export_channel(channel_id: discord::Snowflake) {
+    Arbiter::spawn(export::export(&rest::Rest::new(), &channel_id).then(|res| {
+        match res {
+            Ok(export) => println!(
+                "Exported {} messages to {} and {}",
+                export.messages,
+                export.jsonl.display(),
+                export.html.display()
+            ),
+            Err(e) => eprintln!("Export failed: {}", e),
+        }
+        System::current().stop();
+        Ok(())
+    }));
+}
+
 /// Manually register SystemService in Registry pool
 /// All SystemServices have to implement Default, which is messing with complex actors.
 /// So all of actors are created manually and then are registered via this function.
@@ -95,9 +180,50 @@ fn register_actor<A: SystemService>(actor: A) -> Addr<A> {
     addr
 }
 
+/// Engine with all handlers.
+/// Order matters: handlers are called in order of registration.
+fn create_engine() -> Engine {
+    let mut commands = command::CommandRegistry::new(&data::POOL.config);
+    command::register_builtin(&mut commands);
+    let paginator = paginator::Paginator::new();
+    help::register(&mut commands, &paginator);
+    settings::register(&mut commands);
+    i18n::register(&mut commands);
+    let collector = collector::Collector::new();
+    modlog::register(&mut commands, &collector);
+    export::register(&mut commands);
+    archive::register(&mut commands);
+    welcome::register(&mut commands);
+    starboard::register(&mut commands);
+    let reaction_roles = reaction_roles::ReactionRoles::new();
+    reaction_roles.register(&mut commands);
+    let polls = polls::PollManager::new();
+    polls.register(&mut commands);
+    let scheduler = scheduler::Scheduler::new();
+    scheduler.register(&mut commands);
+
+    let removed = audit::Removed::new();
+
+    let mut engine = Engine::new();
+    // Spam is removed before anything reacts to it, automod stops handlers after it
+    engine.add_handler(Box::new(automod::Automod::new(&removed)));
+    // Answers are collected before commands, so command does not answer its own question
+    engine.add_handler(Box::new(collector));
+    engine.add_handler(Box::new(commands));
+    engine.add_handler(Box::new(archive::Archive));
+    engine.add_handler(Box::new(audit::Audit::new(&removed)));
+    engine.add_handler(Box::new(welcome::Welcome));
+    engine.add_handler(Box::new(reaction_roles));
+    engine.add_handler(Box::new(polls));
+    engine.add_handler(Box::new(scheduler));
+    engine.add_handler(Box::new(starboard::Starboard::new()));
+    engine.add_handler(Box::new(paginator));
+    engine
+}
+
 /// Creates folder for files.
 fn create_files_folder() -> std::io::Result<()> {
     let mut dir = std::env::current_dir()?;
-    dir.push("files");
+    dir.push(archive::FILES_FOLDER);
     std::fs::create_dir_all(dir)
 }
@@ -0,0 +1,187 @@
+use log::{debug, error};
+
+use crate::builder::{truncate, EmbedBuilder, MessageBuilder, FIELD_VALUE_LIMIT};
+use crate::collector::Collector;
+use crate::command::*;
+use crate::data::POOL;
+use crate::discord::{MessageRequestPacket, Snowflake};
+use crate::guard::Guard;
+use crate::handler::EventContext;
+use crate::i18n::{self, Language};
+use crate::permissions::Permissions;
+use crate::sanitize::escape_markdown;
+use crate::settings::{Key, Scope, Settings};
+use actix::Arbiter;
+use futures::{future, Future};
+use std::time::Duration;
+
+/// Colours of log entries
+pub const EDIT_COLOUR: u32 = 0xF0_A0_30;
+pub const DELETE_COLOUR: u32 = 0xDC_14_3C;
+pub const AUTOMOD_COLOUR: u32 = 0x8B_00_8B;
+/// How long admin has to answer the question
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Log channel chosen by admins of guild
+pub const LOG_CHANNEL: Key<Snowflake> = Key::new("log_channel");
+
+/// Moderation log channel of guild from settings, or from config if it is not set.
+pub fn log_channel(settings: &Settings, guild_id: &Snowflake) -> Option<Snowflake> {
+    match settings.get(&LOG_CHANNEL, Scope::Guild, guild_id) {
+        Ok(Some(channel_id)) => return Some(channel_id),
+        Ok(None) => (),
+        Err(e) => error!("Failed to get log channel of {:?}: {}", guild_id, e),
+    }
+    POOL.config.log_channels.get(guild_id).cloned()
+}
+
+/// Registers command, that chooses log channel.
+pub fn register(registry: &mut CommandRegistry, collector: &Collector) {
+    let collector = collector.clone();
+    registry.register(
+        Command::new(
+            "logchannel",
+            "Sends log to another channel, or asks for it",
+            move |ctx, args| match args.channel("channel") {
+                Some(channel_id) => set_log_channel(ctx, channel_id),
+                None => ask_log_channel(ctx, &collector),
+            },
+        )
+        .optional_argument("channel", ArgumentType::Channel)
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+}
+
+fn set_log_channel(ctx: &CommandContext, channel_id: &Snowflake) -> CommandResult {
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    let in_guild = ctx
+        .cache
+        .channel(channel_id)
+        .map(|c| c.guild_id.as_ref() == Some(guild_id))
+        .unwrap_or(false);
+    if !in_guild {
+        return Err(CommandError::Usage(ctx.text("channel-not-in-guild", &[])));
+    }
+
+    save_log_channel(ctx.settings, ctx.language, guild_id, channel_id)
+        .map_err(CommandError::Failed)?;
+    ctx.reply(&ctx.text("log-channel-set", &[("channel", &channel_id.0)]));
+    Ok(())
+}
+
+/// Shows current log channel and waits for admin to mention the new one.
+fn ask_log_channel(ctx: &CommandContext, collector: &Collector) -> CommandResult {
+    let guild_id = ctx.message.guild_id.clone().unwrap();
+    let current = match log_channel(ctx.settings, &guild_id) {
+        Some(channel_id) => ctx.text("log-channel-current", &[("channel", &channel_id.0)]),
+        None => ctx.text("log-channel-none", &[]),
+    };
+    ctx.reply(&ctx.text("log-channel-ask", &[("current", &current)]));
+
+    let language = ctx.language;
+    let rest = ctx.rest.clone();
+    let settings = ctx.settings.clone();
+    let channel_id = ctx.message.channel_id.clone();
+    let checked_guild_id = guild_id.clone();
+    let task = collector
+        .reply_to(ctx.message, ANSWER_TIMEOUT)
+        .map_err(move |_| i18n::text(language, "answer-timeout", &[]))
+        .and_then(
+            move |answer| -> Box<dyn Future<Item = Snowflake, Error = String>> {
+                let answer = answer.content.trim();
+                if answer.eq_ignore_ascii_case("cancel") {
+                    return Box::new(future::err(i18n::text(
+                        language,
+                        "log-channel-unchanged",
+                        &[],
+                    )));
+                }
+                let log_id = match parse_value(&ArgumentType::Channel, answer) {
+                    Some(Value::Channel(log_id)) => log_id,
+                    _ => {
+                        return Box::new(future::err(i18n::text(
+                            language,
+                            "log-channel-not-channel",
+                            &[],
+                        )))
+                    }
+                };
+                // Cache is not available here, so channel is requested
+                let task = rest
+                    .get_channel(&log_id)
+                    .then(move |channel| match channel {
+                        Ok(ref channel) if channel.guild_id.as_ref() == Some(&checked_guild_id) => {
+                            Ok(log_id)
+                        }
+                        _ => Err(i18n::text(language, "channel-not-in-guild", &[])),
+                    });
+                Box::new(task)
+            },
+        );
+
+    let rest = ctx.rest.clone();
+    Arbiter::spawn(task.then(move |res| {
+        let text = match res.and_then(|log_id| {
+            save_log_channel(&settings, language, &guild_id, &log_id)?;
+            Ok(log_id)
+        }) {
+            Ok(log_id) => i18n::text(language, "log-channel-set", &[("channel", &log_id.0)]),
+            Err(reason) => reason,
+        };
+        rest.say(&channel_id, &MessageRequestPacket::simple_text(&text));
+        Ok(())
+    }));
+    Ok(())
+}
+
+fn save_log_channel(
+    settings: &Settings,
+    language: Language,
+    guild_id: &Snowflake,
+    channel_id: &Snowflake,
+) -> Result<(), String> {
+    settings
+        .set(&LOG_CHANNEL, Scope::Guild, guild_id, channel_id)
+        .map_err(|e| {
+            error!("Failed to set log channel of {:?}: {}", guild_id, e);
+            i18n::text(language, "setting-failed", &[])
+        })
+}
+
+/// Posts entry to moderation log of guild.
+/// Guilds without log channel are silently skipped.
+pub fn post(ctx: &EventContext, guild_id: &Snowflake, entry: EmbedBuilder) {
+    post_later(ctx, guild_id, future::ok(entry));
+}
+
+/// Posts entry, that is complete only later, e.g. when results of actions are known.
+pub fn post_later<F>(ctx: &EventContext, guild_id: &Snowflake, entry: F)
+where
+    F: Future<Item = EmbedBuilder, Error = ()> + 'static,
+{
+    let channel_id = match log_channel(ctx.settings, guild_id) {
+        Some(channel_id) => channel_id,
+        None => {
+            debug!("Guild {:?} has no log channel. Entry is dropped", guild_id);
+            return;
+        }
+    };
+
+    let rest = ctx.rest.clone();
+    let guild_id = guild_id.clone();
+    Arbiter::spawn(entry.map(
+        move |entry| match MessageBuilder::new().embed(entry).build() {
+            Ok(packet) => rest.say(&channel_id, &packet),
+            Err(e) => error!("Failed to build log entry for guild {:?}: {}", guild_id, e),
+        },
+    ));
+}
+
+/// Content of message, that fits into embed field.
+pub fn quote(content: &str) -> String {
+    if content.is_empty() {
+        return "*empty*".to_owned();
+    }
+    truncate(&escape_markdown(content), FIELD_VALUE_LIMIT)
+}
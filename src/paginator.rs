@@ -0,0 +1,197 @@
+use log::debug;
+
+use crate::builder::{EmbedBuilder, MessageBuilder};
+use crate::command::{CommandContext, CommandError, CommandResult};
+use crate::discord::*;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::permissions::Permissions;
+use crate::rest::{self, RestError};
+use futures::{Future, Stream};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const PREVIOUS: &str = "\u{25C0}\u{FE0F}";
+const NEXT: &str = "\u{25B6}\u{FE0F}";
+const STOP: &str = "\u{23F9}\u{FE0F}";
+/// Controls stop working after this much time without clicks
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Paginated message, that is still controlled.
+struct Book {
+    channel_id: Snowflake,
+    /// Only the one, who asked, turns pages
+    owner_id: Snowflake,
+    pages: Vec<EmbedBuilder>,
+    current: usize,
+    used: Instant,
+}
+
+/// Message id -> its pages
+type Books = HashMap<Snowflake, Book>;
+
+/// Shows long outputs page by page, pages are turned by reactions.
+/// Both adding and removing of reaction is a click, so nobody has to remove their reactions.
+/// Cheap to clone, all clones control the same messages.
+#[derive(Clone)]
+pub struct Paginator {
+    books: Rc<RefCell<Books>>,
+}
+
+impl Paginator {
+    pub fn new() -> Self {
+        Paginator {
+            books: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Sends page `start` to the channel of command.
+    /// If there are more pages, author of command can turn them.
+    pub fn send(
+        &self,
+        ctx: &CommandContext,
+        pages: Vec<EmbedBuilder>,
+        start: usize,
+    ) -> CommandResult {
+        let page = pages
+            .get(start)
+            .cloned()
+            .ok_or_else(|| CommandError::Failed(ctx.text("nothing-to-show", &[])))?;
+        if pages.len() == 1 {
+            return ctx.send(MessageBuilder::new().embed(page));
+        }
+        let packet = MessageBuilder::new()
+            .embed(page)
+            .build()
+            .map_err(|e| CommandError::Failed(RestError::Build(e).to_string()))?;
+
+        let books = self.books.clone();
+        let rest = ctx.rest.clone();
+        let book = Book {
+            channel_id: ctx.message.channel_id.clone(),
+            owner_id: ctx.message.author.id.clone(),
+            pages,
+            current: start,
+            used: Instant::now(),
+        };
+        let task = ctx
+            .rest
+            .send_message(&ctx.message.channel_id, &packet)
+            .and_then(move |message| {
+                debug!("Paginated message {:?} was sent", &message.id);
+                let channel_id = message.channel_id.clone();
+                books.borrow_mut().insert(message.id.clone(), book);
+
+                // One by one, so they are in order
+                futures::stream::iter_ok(vec![PREVIOUS, NEXT, STOP]).for_each(move |emoji| {
+                    rest.add_reaction(&channel_id, &message.id, emoji)
+                        .map(|_| ())
+                })
+            });
+        rest::spawn(Box::new(task));
+        Ok(())
+    }
+
+    fn click(&self, ctx: &EventContext, reaction: &MessageReactionPacket) -> HandlerResult {
+        let mut books = self.books.borrow_mut();
+        let book = match books.get_mut(&reaction.message_id) {
+            Some(book) if book.owner_id.eq(&reaction.user_id) => book,
+            _ => return Ok(()),
+        };
+
+        let current = if reaction.emoji.matches(PREVIOUS) {
+            book.current.checked_sub(1).unwrap_or(book.pages.len() - 1)
+        } else if reaction.emoji.matches(NEXT) {
+            (book.current + 1) % book.pages.len()
+        } else if reaction.emoji.matches(STOP) {
+            let book = books.remove(&reaction.message_id).unwrap();
+            close(ctx, &reaction.message_id, &book);
+            return Ok(());
+        } else {
+            return Ok(());
+        };
+
+        book.used = Instant::now();
+        book.current = current;
+        let packet = MessageBuilder::new()
+            .embed(book.pages[current].clone())
+            .build()?;
+        rest::spawn(
+            ctx.rest
+                .edit_message(&book.channel_id, &reaction.message_id, &packet),
+        );
+        Ok(())
+    }
+}
+
+impl EventHandler for Paginator {
+    fn name(&self) -> &'static str {
+        "paginator"
+    }
+
+    fn on_reaction_add(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        self.click(ctx, reaction)
+    }
+
+    fn on_reaction_remove(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        self.click(ctx, reaction)
+    }
+
+    fn on_message_delete(
+        &mut self,
+        _ctx: &EventContext,
+        delete: &MessageDeletePacket,
+    ) -> HandlerResult {
+        self.books.borrow_mut().remove(&delete.id);
+        Ok(())
+    }
+
+    /// Stops listening to messages, that nobody clicks.
+    fn on_tick(&mut self, ctx: &EventContext) -> HandlerResult {
+        let mut books = self.books.borrow_mut();
+        let expired: Vec<Snowflake> = books
+            .iter()
+            .filter(|(_, b)| b.used.elapsed() >= TIMEOUT)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired {
+            debug!("Paginated message {:?} timed out", &id);
+            if let Some(book) = books.remove(&id) {
+                close(ctx, &id, &book);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Removes controls. Reactions of others can be removed only with permission,
+/// otherwise only mine are removed.
+fn close(ctx: &EventContext, message_id: &Snowflake, book: &Book) {
+    let can_clear = ctx
+        .cache
+        .member_permissions(&book.channel_id, ctx.myself_id)
+        .map(|p| p.contains(Permissions::MANAGE_MESSAGES))
+        .unwrap_or(false);
+    if can_clear {
+        rest::spawn(ctx.rest.remove_all_reactions(&book.channel_id, message_id));
+        return;
+    }
+
+    let rest = ctx.rest.clone();
+    let channel_id = book.channel_id.clone();
+    let message_id = message_id.clone();
+    let task = futures::stream::iter_ok(vec![PREVIOUS, NEXT, STOP]).for_each(move |emoji| {
+        rest.remove_own_reaction(&channel_id, &message_id, emoji)
+            .map(|_| ())
+    });
+    rest::spawn(Box::new(task));
+}
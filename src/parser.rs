@@ -0,0 +1,370 @@
+use crate::discord::Snowflake;
+
+/// Typed segment of message content.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token<'a> {
+    /// Anything that is not special
+    Text(&'a str),
+    /// <@id> or <@!id> (nickname form)
+    UserMention { id: Snowflake, nickname: bool },
+    /// <@&id>
+    RoleMention(Snowflake),
+    /// <#id>
+    ChannelMention(Snowflake),
+    /// @everyone
+    EveryoneMention,
+    /// @here
+    HereMention,
+    /// <:name:id> or <a:name:id>
+    Emoji {
+        name: &'a str,
+        id: Snowflake,
+        animated: bool,
+    },
+    /// <t:unix> or <t:unix:style>
+    Timestamp { unix: i64, style: Option<char> },
+    /// ```language
+    /// code```
+    CodeBlock {
+        language: Option<&'a str>,
+        code: &'a str,
+    },
+    /// `code`
+    InlineCode(&'a str),
+}
+
+impl<'a> Token<'a> {
+    /// Whether this token is a mention of user in any form.
+    pub fn is_user_mention(&self, user_id: &Snowflake) -> bool {
+        match self {
+            Token::UserMention { id, .. } => id.eq(user_id),
+            _ => false,
+        }
+    }
+}
+
+impl<'a> std::fmt::Display for Token<'a> {
+    /// Writes token back as DISCORD markup.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Text(text) => write!(f, "{}", text),
+            Token::UserMention { id, nickname } => {
+                write!(f, "<@{}{}>", if *nickname { "!" } else { "" }, id.0)
+            }
+            Token::RoleMention(id) => write!(f, "<@&{}>", id.0),
+            Token::ChannelMention(id) => write!(f, "<#{}>", id.0),
+            Token::EveryoneMention => write!(f, "@everyone"),
+            Token::HereMention => write!(f, "@here"),
+            Token::Emoji { name, id, animated } => {
+                write!(
+                    f,
+                    "<{}:{}:{}>",
+                    if *animated { "a" } else { "" },
+                    name,
+                    id.0
+                )
+            }
+            Token::Timestamp { unix, style } => match style {
+                Some(style) => write!(f, "<t:{}:{}>", unix, style),
+                None => write!(f, "<t:{}>", unix),
+            },
+            Token::CodeBlock { language, code } => match language {
+                Some(language) => write!(f, "```{}\n{}```", language, code),
+                None => write!(f, "```{}```", code),
+            },
+            Token::InlineCode(code) => write!(f, "`{}`", code),
+        }
+    }
+}
+
+/// Splits message content into tokens.
+/// Malformed markup (e.g. "<@abc>" or unclosed code) stays as text.
+pub fn tokenize(content: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+
+    while i < content.len() {
+        let rest = &content[i..];
+        let parsed = if rest.starts_with("```") {
+            parse_code_block(rest)
+        } else if rest.starts_with('`') {
+            parse_inline_code(rest)
+        } else if rest.starts_with('<') {
+            parse_angle(rest)
+        } else if rest.starts_with("@everyone") {
+            Some((Token::EveryoneMention, "@everyone".len()))
+        } else if rest.starts_with("@here") {
+            Some((Token::HereMention, "@here".len()))
+        } else {
+            None
+        };
+
+        match parsed {
+            Some((token, length)) => {
+                if text_start < i {
+                    tokens.push(Token::Text(&content[text_start..i]));
+                }
+                tokens.push(token);
+                i += length;
+                text_start = i;
+            }
+            None => {
+                // Step over whole character, not byte
+                i += rest.chars().next().map(char::len_utf8).unwrap_or(1);
+            }
+        }
+    }
+
+    if text_start < content.len() {
+        tokens.push(Token::Text(&content[text_start..]));
+    }
+
+    tokens
+}
+
+/// Writes tokens back as message content.
+pub fn join(tokens: &[Token]) -> String {
+    tokens.iter().map(|t| t.to_string()).collect()
+}
+
+fn parse_code_block(rest: &str) -> Option<(Token<'_>, usize)> {
+    let end = rest[3..].find("```")? + 3;
+    let inner = &rest[3..end];
+
+    // Language is the first line, if there is something after it
+    let (language, code) = match inner.find('\n') {
+        Some(n) if n > 0 && !inner[..n].contains(char::is_whitespace) => {
+            (Some(&inner[..n]), &inner[n + 1..])
+        }
+        _ => (None, inner),
+    };
+
+    Some((Token::CodeBlock { language, code }, end + 3))
+}
+
+fn parse_inline_code(rest: &str) -> Option<(Token<'_>, usize)> {
+    let end = rest[1..].find('`')? + 1;
+    if end == 1 {
+        return None;
+    }
+    Some((Token::InlineCode(&rest[1..end]), end + 1))
+}
+
+fn parse_angle(rest: &str) -> Option<(Token<'_>, usize)> {
+    let end = rest.find('>')?;
+    let inner = &rest[1..end];
+    let length = end + 1;
+
+    let token = if let Some(id) = inner.strip_prefix("@!") {
+        Token::UserMention {
+            id: parse_id(id)?,
+            nickname: true,
+        }
+    } else if let Some(id) = inner.strip_prefix("@&") {
+        Token::RoleMention(parse_id(id)?)
+    } else if let Some(id) = inner.strip_prefix('@') {
+        Token::UserMention {
+            id: parse_id(id)?,
+            nickname: false,
+        }
+    } else if let Some(id) = inner.strip_prefix('#') {
+        Token::ChannelMention(parse_id(id)?)
+    } else if let Some(timestamp) = inner.strip_prefix("t:") {
+        let mut parts = timestamp.splitn(2, ':');
+        let unix = parts.next()?.parse().ok()?;
+        let style = match parts.next() {
+            None => None,
+            Some(style) if style.len() == 1 => style.chars().next(),
+            Some(_) => return None,
+        };
+        Token::Timestamp { unix, style }
+    } else if inner.starts_with(':') || inner.starts_with("a:") {
+        let animated = inner.starts_with('a');
+        let emoji = if animated { &inner[2..] } else { &inner[1..] };
+        let separator = emoji.find(':')?;
+        let name = &emoji[..separator];
+        if name.is_empty() {
+            return None;
+        }
+        Token::Emoji {
+            name,
+            id: parse_id(&emoji[separator + 1..])?,
+            animated,
+        }
+    } else {
+        return None;
+    };
+
+    Some((token, length))
+}
+
+fn parse_id(text: &str) -> Option<Snowflake> {
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(Snowflake(text.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(id: &str) -> Snowflake {
+        Snowflake(id.to_owned())
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(tokenize(""), vec![]);
+        assert_eq!(tokenize("just text"), vec![Token::Text("just text")]);
+    }
+
+    #[test]
+    fn user_mentions() {
+        assert_eq!(
+            tokenize("hi <@123> and <@!456>"),
+            vec![
+                Token::Text("hi "),
+                Token::UserMention {
+                    id: id("123"),
+                    nickname: false
+                },
+                Token::Text(" and "),
+                Token::UserMention {
+                    id: id("456"),
+                    nickname: true
+                },
+            ]
+        );
+        assert!(tokenize("<@!456>")[0].is_user_mention(&id("456")));
+        assert!(!tokenize("<@&456>")[0].is_user_mention(&id("456")));
+    }
+
+    #[test]
+    fn role_and_channel_mentions() {
+        assert_eq!(
+            tokenize("<@&1><#2>"),
+            vec![Token::RoleMention(id("1")), Token::ChannelMention(id("2"))]
+        );
+    }
+
+    #[test]
+    fn everyone_and_here() {
+        assert_eq!(
+            tokenize("@everyone @here"),
+            vec![Token::EveryoneMention, Token::Text(" "), Token::HereMention]
+        );
+    }
+
+    #[test]
+    fn custom_emoji() {
+        assert_eq!(
+            tokenize("<:pepe:11><a:dance:22>"),
+            vec![
+                Token::Emoji {
+                    name: "pepe",
+                    id: id("11"),
+                    animated: false
+                },
+                Token::Emoji {
+                    name: "dance",
+                    id: id("22"),
+                    animated: true
+                },
+            ]
+        );
+        // No name or no id
+        assert_eq!(tokenize("<::11>"), vec![Token::Text("<::11>")]);
+        assert_eq!(tokenize("<:pepe:>"), vec![Token::Text("<:pepe:>")]);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            tokenize("<t:1600000000><t:-5:R>"),
+            vec![
+                Token::Timestamp {
+                    unix: 1_600_000_000,
+                    style: None
+                },
+                Token::Timestamp {
+                    unix: -5,
+                    style: Some('R')
+                },
+            ]
+        );
+        assert_eq!(tokenize("<t:now>"), vec![Token::Text("<t:now>")]);
+        assert_eq!(tokenize("<t:1:RR>"), vec![Token::Text("<t:1:RR>")]);
+    }
+
+    #[test]
+    fn code_blocks() {
+        assert_eq!(
+            tokenize("```rust\nfn main() {}```"),
+            vec![Token::CodeBlock {
+                language: Some("rust"),
+                code: "fn main() {}"
+            }]
+        );
+        assert_eq!(
+            tokenize("```one line```"),
+            vec![Token::CodeBlock {
+                language: None,
+                code: "one line"
+            }]
+        );
+        // Markup inside code is not parsed
+        assert_eq!(
+            tokenize("`<@1>` @everyone"),
+            vec![
+                Token::InlineCode("<@1>"),
+                Token::Text(" "),
+                Token::EveryoneMention
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_markup_stays_text() {
+        assert_eq!(tokenize("<@123"), vec![Token::Text("<@123")]);
+        assert_eq!(tokenize("<@abc>"), vec![Token::Text("<@abc>")]);
+        assert_eq!(tokenize("```code"), vec![Token::Text("```code")]);
+        assert_eq!(tokenize("`code"), vec![Token::Text("`code")]);
+        assert_eq!(tokenize("``"), vec![Token::Text("``")]);
+        assert_eq!(
+            tokenize("< <#1>"),
+            vec![Token::Text("< "), Token::ChannelMention(id("1"))]
+        );
+    }
+
+    #[test]
+    fn multibyte_text() {
+        assert_eq!(
+            tokenize("привет <@1> 👍🏽`кот`"),
+            vec![
+                Token::Text("привет "),
+                Token::UserMention {
+                    id: id("1"),
+                    nickname: false
+                },
+                Token::Text(" 👍🏽"),
+                Token::InlineCode("кот"),
+            ]
+        );
+        assert_eq!(tokenize("<ж>"), vec![Token::Text("<ж>")]);
+        assert_eq!(tokenize("<:ёж:5>")[0].to_string(), "<:ёж:5>");
+    }
+
+    #[test]
+    fn join_restores_content() {
+        for content in &[
+            "hi <@1> <@!2> <@&3> <#4> @everyone @here",
+            "<:a:5><a:b:6> <t:7> <t:8:f>",
+            "```rust\ncode``` `inline` ```plain```",
+            "<@broken `open ```open",
+        ] {
+            assert_eq!(join(&tokenize(content)), *content);
+        }
+    }
+}
@@ -0,0 +1,516 @@
+use crate::discord::{ChannelPacket, GuildPacket, OverwriteType, Snowflake};
+use bitflags::bitflags;
+use serde::de::Visitor;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+bitflags! {
+    /// Permission bit set of DISCORD.
+    /// Used by roles, permission overwrites and guilds.
+    pub struct Permissions: u64 {
+        /// Allows creation of instant invites
+        const CREATE_INSTANT_INVITE = 1 << 0;
+        /// Allows kicking members
+        const KICK_MEMBERS = 1 << 1;
+        /// Allows banning members
+        const BAN_MEMBERS = 1 << 2;
+        /// Allows all permissions and bypasses channel permission overwrites
+        const ADMINISTRATOR = 1 << 3;
+        /// Allows management and editing of channels
+        const MANAGE_CHANNELS = 1 << 4;
+        /// Allows management and editing of the guild
+        const MANAGE_GUILD = 1 << 5;
+        /// Allows for the addition of reactions to messages
+        const ADD_REACTIONS = 1 << 6;
+        /// Allows for viewing of audit logs
+        const VIEW_AUDIT_LOG = 1 << 7;
+        /// Allows for using priority speaker in a voice channel
+        const PRIORITY_SPEAKER = 1 << 8;
+        /// Allows the user to go live
+        const STREAM = 1 << 9;
+        /// Allows guild members to view a channel
+        const VIEW_CHANNEL = 1 << 10;
+        /// Allows for sending messages in a channel
+        const SEND_MESSAGES = 1 << 11;
+        /// Allows for sending of /tts messages
+        const SEND_TTS_MESSAGES = 1 << 12;
+        /// Allows for deletion of other users messages
+        const MANAGE_MESSAGES = 1 << 13;
+        /// Links sent by users with this permission will be auto-embedded
+        const EMBED_LINKS = 1 << 14;
+        /// Allows for uploading images and files
+        const ATTACH_FILES = 1 << 15;
+        /// Allows for reading of message history
+        const READ_MESSAGE_HISTORY = 1 << 16;
+        /// Allows for using the @everyone and @here tags
+        const MENTION_EVERYONE = 1 << 17;
+        /// Allows the usage of custom emojis from other servers
+        const USE_EXTERNAL_EMOJIS = 1 << 18;
+        /// Allows for viewing guild insights
+        const VIEW_GUILD_INSIGHTS = 1 << 19;
+        /// Allows for joining of a voice channel
+        const CONNECT = 1 << 20;
+        /// Allows for speaking in a voice channel
+        const SPEAK = 1 << 21;
+        /// Allows for muting members in a voice channel
+        const MUTE_MEMBERS = 1 << 22;
+        /// Allows for deafening of members in a voice channel
+        const DEAFEN_MEMBERS = 1 << 23;
+        /// Allows for moving of members between voice channels
+        const MOVE_MEMBERS = 1 << 24;
+        /// Allows for using voice-activity-detection in a voice channel
+        const USE_VAD = 1 << 25;
+        /// Allows for modification of own nickname
+        const CHANGE_NICKNAME = 1 << 26;
+        /// Allows for modification of other users nicknames
+        const MANAGE_NICKNAMES = 1 << 27;
+        /// Allows management and editing of roles
+        const MANAGE_ROLES = 1 << 28;
+        /// Allows management and editing of webhooks
+        const MANAGE_WEBHOOKS = 1 << 29;
+        /// Allows management and editing of emojis
+        const MANAGE_EMOJIS = 1 << 30;
+        /// Allows members to use application commands
+        const USE_APPLICATION_COMMANDS = 1 << 31;
+        /// Allows for requesting to speak in stage channels
+        const REQUEST_TO_SPEAK = 1 << 32;
+        /// Allows for creating, editing, and deleting scheduled events
+        const MANAGE_EVENTS = 1 << 33;
+        /// Allows for deleting and archiving threads, and viewing all private threads
+        const MANAGE_THREADS = 1 << 34;
+        /// Allows for creating public and announcement threads
+        const CREATE_PUBLIC_THREADS = 1 << 35;
+        /// Allows for creating private threads
+        const CREATE_PRIVATE_THREADS = 1 << 36;
+        /// Allows the usage of custom stickers from other servers
+        const USE_EXTERNAL_STICKERS = 1 << 37;
+        /// Allows for sending messages in threads
+        const SEND_MESSAGES_IN_THREADS = 1 << 38;
+        /// Allows for using activities in a voice channel
+        const USE_EMBEDDED_ACTIVITIES = 1 << 39;
+        /// Allows for timing out users
+        const MODERATE_MEMBERS = 1 << 40;
+        /// Allows for viewing role subscription insights
+        const VIEW_CREATOR_MONETIZATION_ANALYTICS = 1 << 41;
+        /// Allows for using soundboard in a voice channel
+        const USE_SOUNDBOARD = 1 << 42;
+        /// Allows for creating emojis, stickers, and soundboard sounds
+        const CREATE_GUILD_EXPRESSIONS = 1 << 43;
+        /// Allows for creating scheduled events
+        const CREATE_EVENTS = 1 << 44;
+        /// Allows the usage of custom soundboard sounds from other servers
+        const USE_EXTERNAL_SOUNDS = 1 << 45;
+        /// Allows sending voice messages
+        const SEND_VOICE_MESSAGES = 1 << 46;
+        /// Allows for setting status of a voice channel
+        const SET_VOICE_CHANNEL_STATUS = 1 << 48;
+        /// Allows sending polls
+        const SEND_POLLS = 1 << 49;
+        /// Allows user-installed apps to send public responses
+        const USE_EXTERNAL_APPS = 1 << 50;
+    }
+}
+
+impl Serialize for Permissions {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u64(self.bits())
+    }
+}
+
+// API v6 sends permissions as numbers, newer versions send them as strings
+// so lets accept both
+impl<'de> Deserialize<'de> for Permissions {
+    fn deserialize<D>(deserializer: D) -> Result<Permissions, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MyLittleVisitor;
+
+        impl<'de> Visitor<'de> for MyLittleVisitor {
+            type Value = Permissions;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("Permission bit set as a number or a string")
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                // Unknown bits are dropped, DISCORD adds new permissions from time to time
+                Ok(Permissions::from_bits_truncate(v))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(Permissions::from_bits_truncate(v as u64))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                v.parse::<u64>()
+                    .map(Permissions::from_bits_truncate)
+                    .map_err(serde::de::Error::custom)
+            }
+        }
+        deserializer.deserialize_any(MyLittleVisitor)
+    }
+}
+
+/// Computes permissions of a member on guild level (without channel overwrites).
+/// `member_roles` are roles of member without @everyone role.
+pub fn guild_permissions(
+    guild: &GuildPacket,
+    user_id: &Snowflake,
+    member_roles: &[Snowflake],
+) -> Permissions {
+    if guild.owner_id.eq(user_id) {
+        return Permissions::all();
+    }
+
+    // @everyone role has same id as guild
+    let mut permissions = guild
+        .roles
+        .iter()
+        .filter(|r| r.id.eq(&guild.id) || member_roles.contains(&r.id))
+        .fold(Permissions::empty(), |acc, r| acc | r.permissions);
+
+    if permissions.contains(Permissions::ADMINISTRATOR) {
+        permissions = Permissions::all();
+    }
+
+    permissions
+}
+
+/// Computes effective permissions of a member in a channel.
+/// Follows DISCORD rules: owner and administrators can do anything,
+/// then @everyone overwrite, role overwrites and member overwrite are applied in that order.
+pub fn channel_permissions(
+    guild: &GuildPacket,
+    user_id: &Snowflake,
+    member_roles: &[Snowflake],
+    channel: &ChannelPacket,
+) -> Permissions {
+    let mut permissions = guild_permissions(guild, user_id, member_roles);
+    if permissions.contains(Permissions::ADMINISTRATOR) {
+        return Permissions::all();
+    }
+
+    let overwrites = match &channel.permission_overwrites {
+        Some(overwrites) => overwrites,
+        None => return implicit_permissions(permissions),
+    };
+
+    if let Some(everyone) = overwrites.iter().find(|o| o.id.eq(&guild.id)) {
+        permissions &= !everyone.deny;
+        permissions |= everyone.allow;
+    }
+
+    let mut allow = Permissions::empty();
+    let mut deny = Permissions::empty();
+    for o in overwrites
+        .iter()
+        .filter(|o| o.overwrite_type == OverwriteType::Role && member_roles.contains(&o.id))
+    {
+        allow |= o.allow;
+        deny |= o.deny;
+    }
+    permissions &= !deny;
+    permissions |= allow;
+
+    if let Some(member) = overwrites
+        .iter()
+        .find(|o| o.overwrite_type == OverwriteType::Member && o.id.eq(user_id))
+    {
+        permissions &= !member.deny;
+        permissions |= member.allow;
+    }
+
+    implicit_permissions(permissions)
+}
+
+/// Some permissions are useless without others.
+/// Nobody can send anything in a channel they can not see, and so on.
+fn implicit_permissions(mut permissions: Permissions) -> Permissions {
+    if !permissions.contains(Permissions::VIEW_CHANNEL) {
+        return Permissions::empty();
+    }
+
+    if !permissions.contains(Permissions::SEND_MESSAGES) {
+        permissions &= !(Permissions::SEND_TTS_MESSAGES
+            | Permissions::MENTION_EVERYONE
+            | Permissions::EMBED_LINKS
+            | Permissions::ATTACH_FILES);
+    }
+
+    permissions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const GUILD: &str = "1";
+    const OWNER: &str = "2";
+    const USER: &str = "3";
+    const ROLE: &str = "4";
+    const OTHER_ROLE: &str = "5";
+
+    fn id(id: &str) -> Snowflake {
+        Snowflake(id.to_owned())
+    }
+
+    /// Guild, where @everyone and other roles have given permissions.
+    fn guild(everyone: Permissions, roles: &[(&str, Permissions)]) -> GuildPacket {
+        let roles: Vec<_> = std::iter::once((GUILD, everyone))
+            .chain(roles.iter().cloned())
+            .map(|(id, permissions)| {
+                json!({
+                    "id": id,
+                    "name": id,
+                    "color": 0,
+                    "hoist": false,
+                    "position": 0,
+                    "permissions": permissions.bits(),
+                    "managed": false,
+                    "mentionable": false,
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "id": GUILD,
+            "name": "guild",
+            "owner_id": OWNER,
+            "region": "europe",
+            "afk_timeout": 300,
+            "verification_level": 0,
+            "default_message_notifications": 0,
+            "explicit_content_filter": 0,
+            "roles": roles,
+            "emojis": [],
+            "features": [],
+            "mfa_level": 0,
+        }))
+        .unwrap()
+    }
+
+    /// Text channel with overwrites: id, "role" or "member", allow and deny.
+    fn channel(overwrites: &[(&str, &str, Permissions, Permissions)]) -> ChannelPacket {
+        let overwrites: Vec<_> = overwrites
+            .iter()
+            .map(|(id, kind, allow, deny)| {
+                json!({
+                    "id": id,
+                    "type": kind,
+                    "allow": allow.bits(),
+                    "deny": deny.bits(),
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "id": "10",
+            "type": 0,
+            "guild_id": GUILD,
+            "permission_overwrites": overwrites,
+        }))
+        .unwrap()
+    }
+
+    fn basic() -> Permissions {
+        Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES | Permissions::EMBED_LINKS
+    }
+
+    #[test]
+    fn guild_permissions_join_everyone_and_member_roles() {
+        let guild = guild(
+            Permissions::VIEW_CHANNEL,
+            &[
+                (ROLE, Permissions::KICK_MEMBERS),
+                (OTHER_ROLE, Permissions::BAN_MEMBERS),
+            ],
+        );
+        assert_eq!(
+            guild_permissions(&guild, &id(USER), &[id(ROLE)]),
+            Permissions::VIEW_CHANNEL | Permissions::KICK_MEMBERS
+        );
+        assert_eq!(
+            guild_permissions(&guild, &id(USER), &[]),
+            Permissions::VIEW_CHANNEL
+        );
+    }
+
+    #[test]
+    fn owner_can_do_anything() {
+        let guild = guild(Permissions::empty(), &[]);
+        let channel = channel(&[(GUILD, "role", Permissions::empty(), Permissions::all())]);
+        assert_eq!(
+            guild_permissions(&guild, &id(OWNER), &[]),
+            Permissions::all()
+        );
+        assert_eq!(
+            channel_permissions(&guild, &id(OWNER), &[], &channel),
+            Permissions::all()
+        );
+    }
+
+    #[test]
+    fn administrator_ignores_overwrites() {
+        let guild = guild(basic(), &[(ROLE, Permissions::ADMINISTRATOR)]);
+        let channel = channel(&[
+            (GUILD, "role", Permissions::empty(), Permissions::all()),
+            (USER, "member", Permissions::empty(), Permissions::all()),
+        ]);
+        assert_eq!(
+            guild_permissions(&guild, &id(USER), &[id(ROLE)]),
+            Permissions::all()
+        );
+        assert_eq!(
+            channel_permissions(&guild, &id(USER), &[id(ROLE)], &channel),
+            Permissions::all()
+        );
+    }
+
+    #[test]
+    fn everyone_overwrite_is_applied() {
+        let guild = guild(basic(), &[]);
+        let channel = channel(&[(
+            GUILD,
+            "role",
+            Permissions::ATTACH_FILES,
+            Permissions::EMBED_LINKS,
+        )]);
+        assert_eq!(
+            channel_permissions(&guild, &id(USER), &[], &channel),
+            Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES | Permissions::ATTACH_FILES
+        );
+    }
+
+    #[test]
+    fn role_overwrites_go_after_everyone_and_allow_wins_among_roles() {
+        let guild = guild(basic(), &[(ROLE, Permissions::empty())]);
+        let denied = channel(&[
+            (
+                GUILD,
+                "role",
+                Permissions::empty(),
+                Permissions::SEND_MESSAGES,
+            ),
+            (
+                ROLE,
+                "role",
+                Permissions::SEND_MESSAGES,
+                Permissions::empty(),
+            ),
+            (
+                OTHER_ROLE,
+                "role",
+                Permissions::empty(),
+                Permissions::SEND_MESSAGES,
+            ),
+        ]);
+        assert!(channel_permissions(&guild, &id(USER), &[id(ROLE)], &denied)
+            .contains(Permissions::SEND_MESSAGES));
+        assert!(!channel_permissions(&guild, &id(USER), &[], &denied)
+            .contains(Permissions::SEND_MESSAGES));
+
+        // Both roles of member: allow of one beats deny of another
+        let both = channel(&[
+            (
+                ROLE,
+                "role",
+                Permissions::SEND_MESSAGES,
+                Permissions::empty(),
+            ),
+            (
+                OTHER_ROLE,
+                "role",
+                Permissions::empty(),
+                Permissions::SEND_MESSAGES,
+            ),
+        ]);
+        assert!(
+            channel_permissions(&guild, &id(USER), &[id(ROLE), id(OTHER_ROLE)], &both)
+                .contains(Permissions::SEND_MESSAGES)
+        );
+    }
+
+    #[test]
+    fn member_overwrite_goes_last() {
+        let guild = guild(basic(), &[(ROLE, Permissions::empty())]);
+        let channel = channel(&[
+            (
+                ROLE,
+                "role",
+                Permissions::ATTACH_FILES,
+                Permissions::empty(),
+            ),
+            (
+                USER,
+                "member",
+                Permissions::empty(),
+                Permissions::ATTACH_FILES,
+            ),
+        ]);
+        assert!(
+            !channel_permissions(&guild, &id(USER), &[id(ROLE)], &channel)
+                .contains(Permissions::ATTACH_FILES)
+        );
+
+        // Overwrite of another member does not matter
+        assert!(
+            channel_permissions(&guild, &id(OWNER), &[id(ROLE)], &channel)
+                .contains(Permissions::ATTACH_FILES)
+        );
+    }
+
+    #[test]
+    fn nothing_is_left_without_view_channel() {
+        let guild = guild(basic(), &[]);
+        let channel = channel(&[(
+            GUILD,
+            "role",
+            Permissions::empty(),
+            Permissions::VIEW_CHANNEL,
+        )]);
+        assert_eq!(
+            channel_permissions(&guild, &id(USER), &[], &channel),
+            Permissions::empty()
+        );
+    }
+
+    #[test]
+    fn sending_permissions_are_lost_without_send_messages() {
+        let guild = guild(
+            basic() | Permissions::ATTACH_FILES | Permissions::ADD_REACTIONS,
+            &[],
+        );
+        let channel = channel(&[(
+            GUILD,
+            "role",
+            Permissions::empty(),
+            Permissions::SEND_MESSAGES,
+        )]);
+        assert_eq!(
+            channel_permissions(&guild, &id(USER), &[], &channel),
+            Permissions::VIEW_CHANNEL | Permissions::ADD_REACTIONS
+        );
+    }
+
+    #[test]
+    fn channel_without_overwrites_has_guild_permissions() {
+        let guild = guild(basic(), &[]);
+        let mut channel = channel(&[]);
+        channel.permission_overwrites = None;
+        assert_eq!(
+            channel_permissions(&guild, &id(USER), &[], &channel),
+            basic()
+        );
+    }
+}
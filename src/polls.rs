@@ -0,0 +1,480 @@
+use log::{debug, error};
+
+use crate::builder::{EmbedBuilder, MessageBuilder};
+use crate::command::*;
+use crate::discord::*;
+use crate::guard::Guard;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::i18n::{self, Language};
+use crate::permissions::Permissions;
+use crate::rest::{self, Rest, RestError};
+use crate::sanitize::escape_markdown;
+use crate::scheduler::format_time;
+use crate::storage;
+use chrono::Utc;
+use futures::{Future, Stream};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Name of file in data folder
+const STORAGE_NAME: &str = "polls";
+/// Colour of running polls
+const OPEN_COLOUR: u32 = 0x1A_BC_9C;
+/// Colour of closed polls
+const CLOSED_COLOUR: u32 = 0x7F_8C_8D;
+/// Reactions of options, in order
+const NUMBERS: [&str; 10] = [
+    "1\u{FE0F}\u{20E3}",
+    "2\u{FE0F}\u{20E3}",
+    "3\u{FE0F}\u{20E3}",
+    "4\u{FE0F}\u{20E3}",
+    "5\u{FE0F}\u{20E3}",
+    "6\u{FE0F}\u{20E3}",
+    "7\u{FE0F}\u{20E3}",
+    "8\u{FE0F}\u{20E3}",
+    "9\u{FE0F}\u{20E3}",
+    "\u{1F51F}",
+];
+/// Width of result bars in characters
+const BAR_WIDTH: usize = 12;
+/// Poll message is edited at most this often, DISCORD limits edits
+const LIVE_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Poll, that is tallied by reactions to its message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Poll {
+    pub channel_id: Snowflake,
+    pub author_id: Snowflake,
+    pub question: String,
+    pub options: Vec<String>,
+    /// Voting for another option takes back the previous vote
+    pub single: bool,
+    /// Unix time in seconds, when poll closes by itself
+    pub closes: Option<i64>,
+    /// User id -> indexes of options
+    pub votes: HashMap<Snowflake, Vec<usize>>,
+    /// Poll is shown in language of the place, where it was started
+    #[serde(default)]
+    pub language: Language,
+    /// Votes changed since the message was edited
+    #[serde(skip)]
+    dirty: bool,
+    #[serde(skip)]
+    edited: Option<Instant>,
+}
+
+impl Poll {
+    fn tally(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.options.len()];
+        for index in self.votes.values().flatten() {
+            if let Some(count) = counts.get_mut(*index) {
+                *count += 1;
+            }
+        }
+        counts
+    }
+
+    /// Options with bar chart of votes.
+    fn embed(&self, closed: bool) -> EmbedBuilder {
+        let counts = self.tally();
+        let total: usize = counts.iter().sum();
+        let best = counts.iter().cloned().max().unwrap_or(0);
+
+        let lines = self
+            .options
+            .iter()
+            .zip(counts.iter())
+            .enumerate()
+            .map(|(i, (option, &count))| {
+                let option = escape_markdown(option);
+                let option = if closed && count > 0 && count == best {
+                    format!("**{}**", option)
+                } else {
+                    option
+                };
+                format!(
+                    "{} {}\n`{}` {} ({}%)",
+                    NUMBERS[i],
+                    option,
+                    bar(count, total),
+                    i18n::plural(self.language, "poll-votes", count as i64, &[]),
+                    percent(count, total)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        let language = self.language;
+        let footer = if closed {
+            i18n::plural(language, "poll-closed", self.votes.len() as i64, &[])
+        } else {
+            let choices = if self.single {
+                "poll-single"
+            } else {
+                "poll-multiple"
+            };
+            let choices = i18n::text(language, choices, &[]);
+            match self.closes {
+                Some(closes) => i18n::text(
+                    language,
+                    "poll-closes",
+                    &[("choices", &choices), ("time", &format_time(closes))],
+                ),
+                None => choices,
+            }
+        };
+
+        EmbedBuilder::new()
+            .title(&self.question)
+            .description(&lines)
+            .colour(if closed { CLOSED_COLOUR } else { OPEN_COLOUR })
+            .footer(&footer, None)
+    }
+
+    /// Edits poll message with current votes.
+    fn update(&mut self, rest: &Rest, message_id: &Snowflake, closed: bool) {
+        self.dirty = false;
+        self.edited = Some(Instant::now());
+        match MessageBuilder::new().embed(self.embed(closed)).build() {
+            Ok(packet) => rest::spawn(rest.edit_message(&self.channel_id, message_id, &packet)),
+            Err(e) => error!("Poll {:?} can not be shown: {}", message_id, e),
+        }
+    }
+}
+
+/// Message id -> its poll
+type Polls = HashMap<Snowflake, Poll>;
+
+/// Polls with numbered reactions, results are updated while people vote.
+/// Running polls are kept in data folder.
+pub struct PollManager {
+    polls: Rc<RefCell<Polls>>,
+}
+
+impl PollManager {
+    pub fn new() -> Self {
+        PollManager {
+            polls: Rc::new(RefCell::new(storage::load(STORAGE_NAME))),
+        }
+    }
+
+    /// Registers commands, that start and close polls.
+    pub fn register(&self, registry: &mut CommandRegistry) {
+        let polls = self.polls.clone();
+        registry.register(
+            Command::new(
+                "poll",
+                "Starts poll, e.g. `poll 1h single \"Pizza or pasta?\" Pizza Pasta`. Duration and `single` (one choice per member) are optional",
+                move |ctx, args| start(ctx, args, &polls),
+            )
+            .argument("poll", ArgumentType::Rest)
+            .guard(Guard::GuildOnly)
+            .bot_permissions(Permissions::ADD_REACTIONS | Permissions::EMBED_LINKS),
+        );
+
+        let polls = self.polls.clone();
+        registry.register(
+            Command::new(
+                "endpoll",
+                "Closes poll and shows results, only its author or moderators can do it",
+                move |ctx, args| end(ctx, args, &polls),
+            )
+            .argument("message", ArgumentType::Text)
+            .guard(Guard::GuildOnly),
+        );
+    }
+
+    fn save(polls: &Polls) -> HandlerResult {
+        storage::save(STORAGE_NAME, polls)?;
+        Ok(())
+    }
+}
+
+impl EventHandler for PollManager {
+    fn name(&self) -> &'static str {
+        "polls"
+    }
+
+    fn on_reaction_add(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        if reaction.user_id.eq(ctx.myself_id) {
+            return Ok(());
+        }
+        let mut polls = self.polls.borrow_mut();
+        let poll = match polls.get_mut(&reaction.message_id) {
+            Some(poll) => poll,
+            None => return Ok(()),
+        };
+        let index = match option_index(&reaction.emoji) {
+            Some(index) if index < poll.options.len() => index,
+            _ => return Ok(()),
+        };
+
+        let votes = poll.votes.entry(reaction.user_id.clone()).or_default();
+        if votes.contains(&index) {
+            return Ok(());
+        }
+        if poll.single {
+            for previous in votes.drain(..) {
+                debug!("Taking back previous vote of {:?}", &reaction.user_id);
+                rest::spawn(ctx.rest.remove_user_reaction(
+                    &reaction.channel_id,
+                    &reaction.message_id,
+                    NUMBERS[previous],
+                    &reaction.user_id,
+                ));
+            }
+        }
+        votes.push(index);
+        poll.dirty = true;
+
+        Self::save(&polls)
+    }
+
+    fn on_reaction_remove(
+        &mut self,
+        _ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        let mut polls = self.polls.borrow_mut();
+        let poll = match polls.get_mut(&reaction.message_id) {
+            Some(poll) => poll,
+            None => return Ok(()),
+        };
+        let index = match option_index(&reaction.emoji) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+
+        // Reaction, that was removed by single choice, is not a vote anymore
+        let removed = match poll.votes.get_mut(&reaction.user_id) {
+            Some(votes) if votes.contains(&index) => {
+                votes.retain(|i| *i != index);
+                true
+            }
+            _ => false,
+        };
+        if !removed {
+            return Ok(());
+        }
+        poll.votes.retain(|_, votes| !votes.is_empty());
+        poll.dirty = true;
+
+        Self::save(&polls)
+    }
+
+    fn on_reaction_remove_all(
+        &mut self,
+        _ctx: &EventContext,
+        reactions: &MessageReactionRemoveAllPacket,
+    ) -> HandlerResult {
+        let mut polls = self.polls.borrow_mut();
+        if let Some(poll) = polls.get_mut(&reactions.message_id) {
+            poll.votes.clear();
+            poll.dirty = true;
+            Self::save(&polls)?;
+        }
+        Ok(())
+    }
+
+    fn on_message_delete(
+        &mut self,
+        _ctx: &EventContext,
+        delete: &MessageDeletePacket,
+    ) -> HandlerResult {
+        let mut polls = self.polls.borrow_mut();
+        if polls.remove(&delete.id).is_some() {
+            debug!("Poll {:?} was deleted", &delete.id);
+            Self::save(&polls)?;
+        }
+        Ok(())
+    }
+
+    /// Shows new votes and closes expired polls.
+    fn on_tick(&mut self, ctx: &EventContext) -> HandlerResult {
+        let now = Utc::now().timestamp();
+        let mut polls = self.polls.borrow_mut();
+
+        let expired: Vec<Snowflake> = polls
+            .iter()
+            .filter(|(_, p)| p.closes.map(|c| c <= now).unwrap_or(false))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            debug!("Poll {:?} is over", id);
+            if let Some(mut poll) = polls.remove(id) {
+                poll.update(&ctx.rest, id, true);
+            }
+        }
+
+        for (id, poll) in polls.iter_mut() {
+            let due = poll
+                .edited
+                .map(|e| e.elapsed() >= LIVE_UPDATE_INTERVAL)
+                .unwrap_or(true);
+            if poll.dirty && due {
+                poll.update(&ctx.rest, id, false);
+            }
+        }
+
+        if !expired.is_empty() {
+            Self::save(&polls)?;
+        }
+        Ok(())
+    }
+}
+
+fn start(ctx: &CommandContext, args: &Args, polls: &Rc<RefCell<Polls>>) -> CommandResult {
+    let poll = parse_poll(ctx, args.text("poll").unwrap())?;
+    if poll.single {
+        let can_remove = ctx
+            .bot_permissions()
+            .map(|p| p.contains(Permissions::MANAGE_MESSAGES))
+            .unwrap_or(false);
+        if !can_remove {
+            return Err(CommandError::Usage(
+                ctx.text("poll-needs-manage-messages", &[]),
+            ));
+        }
+    }
+
+    let packet = MessageBuilder::new()
+        .embed(poll.embed(false))
+        .build()
+        .map_err(|e| CommandError::Failed(RestError::Build(e).to_string()))?;
+
+    let polls = polls.clone();
+    let rest = ctx.rest.clone();
+    let channel_id = poll.channel_id.clone();
+    let options = poll.options.len();
+    let task = ctx
+        .rest
+        .send_message(&channel_id, &packet)
+        .and_then(move |message| {
+            debug!("Poll {:?} started", &message.id);
+            {
+                let mut polls = polls.borrow_mut();
+                polls.insert(message.id.clone(), poll);
+                if let Err(e) = PollManager::save(&polls) {
+                    error!("Failed to save polls: {}", e);
+                }
+            }
+
+            // One by one, so they are in order
+            futures::stream::iter_ok(NUMBERS[..options].iter()).for_each(move |emoji| {
+                rest.add_reaction(&channel_id, &message.id, emoji)
+                    .map(|_| ())
+            })
+        });
+    rest::spawn(Box::new(task));
+    Ok(())
+}
+
+fn end(ctx: &CommandContext, args: &Args, polls: &RefCell<Polls>) -> CommandResult {
+    let message_id = Snowflake(args.text("message").unwrap().to_owned());
+    let mut polls = polls.borrow_mut();
+
+    let allowed = match polls.get(&message_id) {
+        Some(poll) if poll.channel_id.ne(&ctx.message.channel_id) => false,
+        Some(poll) => {
+            poll.author_id.eq(&ctx.message.author.id)
+                || ctx
+                    .author_permissions()
+                    .map(|p| p.contains(Permissions::MANAGE_MESSAGES))
+                    .unwrap_or(false)
+        }
+        None => false,
+    };
+    if !allowed {
+        return Err(CommandError::Usage(ctx.text("poll-not-found", &[])));
+    }
+
+    let mut poll = polls.remove(&message_id).unwrap();
+    poll.update(&ctx.rest, &message_id, true);
+    PollManager::save(&polls).map_err(|e| {
+        error!("Failed to save polls: {}", e);
+        CommandError::Failed(ctx.text("poll-save-failed", &[]))
+    })
+}
+
+/// Parses "[duration] [single] question option option...", words may be "quoted".
+fn parse_poll(ctx: &CommandContext, mut input: &str) -> Result<Poll, CommandError> {
+    let mut words = vec![];
+    while let Some(word) = next_word(&mut input) {
+        words.push(word);
+    }
+    let mut words = words.into_iter().peekable();
+
+    let closes = match words.peek().and_then(|w| parse_duration(w)) {
+        Some(after) => {
+            words.next();
+            Some(Utc::now().timestamp() + after.as_secs() as i64)
+        }
+        None => None,
+    };
+    let single = match words.peek() {
+        Some(word) if word.eq_ignore_ascii_case("single") => {
+            words.next();
+            true
+        }
+        _ => false,
+    };
+    let question = words
+        .next()
+        .ok_or_else(|| CommandError::Usage(ctx.text("poll-no-question", &[])))?;
+    let options: Vec<String> = words.collect();
+    if options.len() < 2 || options.len() > NUMBERS.len() {
+        return Err(CommandError::Usage(ctx.plural(
+            "poll-options",
+            NUMBERS.len() as i64,
+            &[],
+        )));
+    }
+
+    Ok(Poll {
+        channel_id: ctx.message.channel_id.clone(),
+        author_id: ctx.message.author.id.clone(),
+        question,
+        options,
+        single,
+        closes,
+        votes: HashMap::new(),
+        language: ctx.language,
+        dirty: false,
+        edited: None,
+    })
+}
+
+/// Index of option by its number emoji, variation selectors are optional.
+fn option_index(emoji: &EmojiPacket) -> Option<usize> {
+    if emoji.id.is_some() {
+        return None;
+    }
+    let name = emoji.name.as_ref()?.replace('\u{FE0F}', "");
+    NUMBERS
+        .iter()
+        .position(|n| n.replace('\u{FE0F}', "") == name)
+}
+
+fn bar(count: usize, total: usize) -> String {
+    let filled = if total == 0 {
+        0
+    } else {
+        (count * BAR_WIDTH + total / 2) / total
+    };
+    "█".repeat(filled) + &"░".repeat(BAR_WIDTH - filled)
+}
+
+fn percent(count: usize, total: usize) -> usize {
+    if total == 0 {
+        0
+    } else {
+        (count * 100 + total / 2) / total
+    }
+}
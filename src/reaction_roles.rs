@@ -0,0 +1,470 @@
+use log::{debug, error};
+
+use crate::builder::{EmbedBuilder, MessageBuilder, FIELDS_LIMIT};
+use crate::command::*;
+use crate::discord::*;
+use crate::guard::Guard;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::i18n;
+use crate::permissions::Permissions;
+use crate::rest;
+use crate::storage;
+use actix::Arbiter;
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Name of file in data folder
+const STORAGE_NAME: &str = "reaction_roles";
+/// Colour of list embed
+const LIST_COLOUR: u32 = 0x34_98_DB;
+
+/// How reactions of one message give roles.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// React to get role, remove reaction to lose it
+    Normal,
+    /// Only one role of the message at a time
+    Unique,
+    /// Role is only given, removing reaction does nothing
+    Verify,
+}
+
+impl Mode {
+    fn parse(text: &str) -> Option<Self> {
+        match text.to_lowercase().as_str() {
+            "normal" => Some(Mode::Normal),
+            "unique" => Some(Mode::Unique),
+            "verify" => Some(Mode::Verify),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Unique => "unique",
+            Mode::Verify => "verify",
+        }
+    }
+}
+
+/// Emoji bound to role.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Binding {
+    /// Unicode emoji or "name:id" of custom emoji, as DISCORD expects it in urls
+    pub emoji: String,
+    pub role_id: Snowflake,
+}
+
+/// Message, that gives roles.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleMessage {
+    pub guild_id: Snowflake,
+    pub channel_id: Snowflake,
+    pub mode: Mode,
+    pub bindings: Vec<Binding>,
+}
+
+impl RoleMessage {
+    fn role(&self, emoji: &EmojiPacket) -> Option<&Snowflake> {
+        self.bindings
+            .iter()
+            .find(|b| emoji.matches(&b.emoji))
+            .map(|b| &b.role_id)
+    }
+}
+
+/// Message id -> its roles
+type RoleMessages = HashMap<Snowflake, RoleMessage>;
+
+/// Members assign roles to themselves by reacting to messages.
+/// Bindings are created with commands and are kept in data folder.
+pub struct ReactionRoles {
+    messages: Rc<RefCell<RoleMessages>>,
+}
+
+impl ReactionRoles {
+    pub fn new() -> Self {
+        ReactionRoles {
+            messages: Rc::new(RefCell::new(storage::load(STORAGE_NAME))),
+        }
+    }
+
+    /// Registers commands, that manage bindings.
+    pub fn register(&self, registry: &mut CommandRegistry) {
+        let messages = self.messages.clone();
+        registry.register(
+            admin_command(
+                "rradd",
+                "Binds emoji on message to role, members get role by reacting",
+                move |ctx, args| add(ctx, args, &messages),
+            )
+            .argument("channel", ArgumentType::Channel)
+            .argument("message", ArgumentType::Text)
+            .argument("emoji", ArgumentType::Text)
+            .argument("role", ArgumentType::Role)
+            .bot_permissions(Permissions::ADD_REACTIONS),
+        );
+
+        let messages = self.messages.clone();
+        registry.register(
+            admin_command(
+                "rrremove",
+                "Unbinds one emoji, or the whole message",
+                move |ctx, args| remove(ctx, args, &messages),
+            )
+            .argument("message", ArgumentType::Text)
+            .optional_argument("emoji", ArgumentType::Text),
+        );
+
+        let messages = self.messages.clone();
+        registry.register(
+            admin_command(
+                "rrmode",
+                "Sets mode of message: normal, unique (one role at a time) or verify (role can not be lost)",
+                move |ctx, args| mode(ctx, args, &messages),
+            )
+            .argument("message", ArgumentType::Text)
+            .argument("mode", ArgumentType::Text),
+        );
+
+        let messages = self.messages.clone();
+        registry.register(admin_command(
+            "rrlist",
+            "Shows reaction roles of this guild",
+            move |ctx, _args| list(ctx, &messages),
+        ));
+    }
+}
+
+impl EventHandler for ReactionRoles {
+    fn name(&self) -> &'static str {
+        "reaction roles"
+    }
+
+    fn on_reaction_add(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        if reaction.user_id.eq(ctx.myself_id) {
+            return Ok(());
+        }
+        let messages = self.messages.borrow();
+        let message = match messages.get(&reaction.message_id) {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+        let role_id = match message.role(&reaction.emoji) {
+            Some(role_id) => role_id,
+            None => return Ok(()),
+        };
+
+        debug!("Giving role {:?} to {:?}", role_id, &reaction.user_id);
+        if message.mode == Mode::Unique {
+            let roles = reaction
+                .member
+                .as_ref()
+                .map(|m| m.roles.as_slice())
+                .or_else(|| ctx.cache.member_roles(&message.guild_id, &reaction.user_id))
+                .unwrap_or(&[]);
+            // Old reaction would not match roles anymore
+            for other in message
+                .bindings
+                .iter()
+                .filter(|b| b.role_id.ne(role_id) && roles.contains(&b.role_id))
+            {
+                rest::spawn(ctx.rest.remove_member_role(
+                    &message.guild_id,
+                    &reaction.user_id,
+                    &other.role_id,
+                ));
+                rest::spawn(ctx.rest.remove_user_reaction(
+                    &message.channel_id,
+                    &reaction.message_id,
+                    &other.emoji,
+                    &reaction.user_id,
+                ));
+            }
+        }
+        rest::spawn(
+            ctx.rest
+                .add_member_role(&message.guild_id, &reaction.user_id, role_id),
+        );
+        Ok(())
+    }
+
+    fn on_reaction_remove(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        let messages = self.messages.borrow();
+        let message = match messages.get(&reaction.message_id) {
+            Some(message) if message.mode != Mode::Verify => message,
+            _ => return Ok(()),
+        };
+        if let Some(role_id) = message.role(&reaction.emoji) {
+            debug!("Taking role {:?} from {:?}", role_id, &reaction.user_id);
+            rest::spawn(
+                ctx.rest
+                    .remove_member_role(&message.guild_id, &reaction.user_id, role_id),
+            );
+        }
+        Ok(())
+    }
+
+    /// Bindings of deleted message are useless.
+    fn on_message_delete(
+        &mut self,
+        _ctx: &EventContext,
+        delete: &MessageDeletePacket,
+    ) -> HandlerResult {
+        let mut messages = self.messages.borrow_mut();
+        if messages.remove(&delete.id).is_some() {
+            debug!("Message {:?} with reaction roles was deleted", &delete.id);
+            storage::save(STORAGE_NAME, &*messages)?;
+        }
+        Ok(())
+    }
+}
+
+/// Command for people, who manage roles of guild.
+fn admin_command<F>(name: &'static str, description: &'static str, handler: F) -> Command
+where
+    F: Fn(&CommandContext, &Args) -> CommandResult + 'static,
+{
+    Command::new(name, description, handler)
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_ROLES)
+        .bot_permissions(Permissions::MANAGE_ROLES)
+}
+
+fn add(ctx: &CommandContext, args: &Args, messages: &Rc<RefCell<RoleMessages>>) -> CommandResult {
+    let guild_id = ctx.message.guild_id.clone().unwrap();
+    let channel_id = args.channel("channel").unwrap().clone();
+    let message_id = parse_id(ctx, args.text("message").unwrap())?;
+    let emoji = parse_emoji(ctx, args.text("emoji").unwrap())?;
+    let role_id = args.role("role").unwrap().clone();
+
+    let in_guild = ctx
+        .cache
+        .channel(&channel_id)
+        .map(|c| c.guild_id.as_ref() == Some(&guild_id))
+        .unwrap_or(false);
+    if !in_guild {
+        return Err(CommandError::Usage(ctx.text("channel-not-in-guild", &[])));
+    }
+    check_assignable_role(ctx, &guild_id, &role_id)?;
+    check_message(ctx, &messages.borrow(), &message_id, &channel_id)?;
+
+    // Message must exist, otherwise the binding would wait for it forever
+    let messages = messages.clone();
+    let rest = ctx.rest.clone();
+    let reply_to = ctx.message.channel_id.clone();
+    let language = ctx.language;
+    let task = ctx
+        .rest
+        .get_message(&channel_id, &message_id)
+        .then(move |res| {
+            let text = match res {
+                Ok(_) => {
+                    let mut messages = messages.borrow_mut();
+                    let message =
+                        messages
+                            .entry(message_id.clone())
+                            .or_insert_with(|| RoleMessage {
+                                guild_id,
+                                channel_id: channel_id.clone(),
+                                mode: Mode::Normal,
+                                bindings: vec![],
+                            });
+                    message.bindings.retain(|b| b.emoji.ne(&emoji));
+                    message.bindings.push(Binding {
+                        emoji: emoji.clone(),
+                        role_id: role_id.clone(),
+                    });
+                    match storage::save(STORAGE_NAME, &*messages) {
+                        Ok(()) => {
+                            // Members see which reactions to use
+                            rest::spawn(rest.add_reaction(&channel_id, &message_id, &emoji));
+                            i18n::text(
+                                language,
+                                "rr-added",
+                                &[("emoji", &display_emoji(&emoji)), ("role", &role_id.0)],
+                            )
+                        }
+                        Err(e) => {
+                            error!("Failed to save reaction roles: {}", e);
+                            i18n::text(language, "rr-save-failed", &[])
+                        }
+                    }
+                }
+                Err(e) => {
+                    debug!("Message {:?} for reaction roles: {}", &message_id, e);
+                    i18n::text(language, "rr-message-not-found", &[])
+                }
+            };
+            rest.say(&reply_to, &MessageRequestPacket::simple_text(&text));
+            Ok(())
+        });
+    Arbiter::spawn(task);
+    Ok(())
+}
+
+/// Bound message has to stay in its guild and channel.
+fn check_message(
+    ctx: &CommandContext,
+    messages: &RoleMessages,
+    message_id: &Snowflake,
+    channel_id: &Snowflake,
+) -> CommandResult {
+    match messages.get(message_id) {
+        Some(message) if ctx.message.guild_id.as_ref() != Some(&message.guild_id) => {
+            Err(CommandError::Usage(ctx.text("rr-message-not-found", &[])))
+        }
+        Some(message) if message.channel_id.ne(channel_id) => Err(CommandError::Usage(ctx.text(
+            "rr-message-in-other-channel",
+            &[("channel", &message.channel_id.0)],
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn remove(ctx: &CommandContext, args: &Args, messages: &RefCell<RoleMessages>) -> CommandResult {
+    let message_id = parse_id(ctx, args.text("message").unwrap())?;
+    let mut messages = messages.borrow_mut();
+    let message = guild_message(ctx, &mut messages, &message_id)?;
+
+    match args.text("emoji") {
+        Some(emoji) => {
+            let emoji = parse_emoji(ctx, emoji)?;
+            let count = message.bindings.len();
+            message.bindings.retain(|b| b.emoji.ne(&emoji));
+            if message.bindings.len() == count {
+                return Err(CommandError::Usage(ctx.text("rr-emoji-not-bound", &[])));
+            }
+            if message.bindings.is_empty() {
+                messages.remove(&message_id);
+            }
+        }
+        None => {
+            messages.remove(&message_id);
+        }
+    }
+
+    save(ctx, &messages)?;
+    ctx.reply(&ctx.text("rr-removed", &[]));
+    Ok(())
+}
+
+fn mode(ctx: &CommandContext, args: &Args, messages: &RefCell<RoleMessages>) -> CommandResult {
+    let message_id = parse_id(ctx, args.text("message").unwrap())?;
+    let mode = Mode::parse(args.text("mode").unwrap())
+        .ok_or_else(|| CommandError::Usage(ctx.text("rr-mode-usage", &[])))?;
+
+    let mut messages = messages.borrow_mut();
+    let message = guild_message(ctx, &mut messages, &message_id)?;
+    // Unique mode takes back reactions of others
+    if mode == Mode::Unique {
+        let can_remove = ctx
+            .cache
+            .member_permissions(&message.channel_id, ctx.myself_id)
+            .map(|p| p.contains(Permissions::MANAGE_MESSAGES))
+            .unwrap_or(false);
+        if !can_remove {
+            return Err(CommandError::Usage(
+                ctx.text("rr-unique-needs-manage-messages", &[]),
+            ));
+        }
+    }
+    message.mode = mode;
+    save(ctx, &messages)?;
+    ctx.reply(&ctx.text("rr-mode-set", &[("mode", &mode.name())]));
+    Ok(())
+}
+
+fn list(ctx: &CommandContext, messages: &RefCell<RoleMessages>) -> CommandResult {
+    let messages = messages.borrow();
+    let guild_messages: Vec<(&Snowflake, &RoleMessage)> = messages
+        .iter()
+        .filter(|(_, m)| ctx.message.guild_id.as_ref() == Some(&m.guild_id))
+        .take(FIELDS_LIMIT)
+        .collect();
+    if guild_messages.is_empty() {
+        ctx.reply(&ctx.text("rr-none", &[]));
+        return Ok(());
+    }
+
+    let embed = guild_messages.iter().fold(
+        EmbedBuilder::new()
+            .title(&ctx.text("rr-title", &[]))
+            .colour(LIST_COLOUR),
+        |embed, (id, m)| {
+            let roles = m
+                .bindings
+                .iter()
+                .map(|b| format!("{} <@&{}>", display_emoji(&b.emoji), b.role_id.0))
+                .collect::<Vec<String>>()
+                .join("\n");
+            embed.field(
+                &ctx.text(
+                    "rr-list-message",
+                    &[
+                        ("message", &id.0),
+                        ("channel", &channel_name(ctx, m)),
+                        ("mode", &m.mode.name()),
+                    ],
+                ),
+                &roles,
+                false,
+            )
+        },
+    );
+    ctx.send(MessageBuilder::new().embed(embed))
+}
+
+fn channel_name(ctx: &CommandContext, message: &RoleMessage) -> String {
+    match ctx.cache.channel_name(&message.channel_id) {
+        Some(name) => name.to_owned(),
+        None => ctx.text("unknown-channel", &[]),
+    }
+}
+
+/// Bound message of the guild of command.
+fn guild_message<'a>(
+    ctx: &CommandContext,
+    messages: &'a mut RoleMessages,
+    message_id: &Snowflake,
+) -> Result<&'a mut RoleMessage, CommandError> {
+    match messages.get_mut(message_id) {
+        Some(message) if ctx.message.guild_id.as_ref() == Some(&message.guild_id) => Ok(message),
+        _ => Err(CommandError::Usage(ctx.text("rr-not-found", &[]))),
+    }
+}
+
+fn save(ctx: &CommandContext, messages: &RoleMessages) -> CommandResult {
+    storage::save(STORAGE_NAME, messages).map_err(|e| {
+        error!("Failed to save reaction roles: {}", e);
+        CommandError::Failed(ctx.text("rr-save-failed", &[]))
+    })
+}
+
+fn parse_id(ctx: &CommandContext, text: &str) -> Result<Snowflake, CommandError> {
+    if !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) {
+        Ok(Snowflake(text.to_owned()))
+    } else {
+        Err(CommandError::Usage(
+            ctx.text("not-message-id", &[("text", &text)]),
+        ))
+    }
+}
+
+fn parse_emoji(ctx: &CommandContext, text: &str) -> Result<String, CommandError> {
+    crate::command::parse_emoji(text)
+        .ok_or_else(|| CommandError::Usage(ctx.text("not-emoji", &[("text", &text)])))
+}
@@ -0,0 +1,410 @@
+use log::{debug, error, warn};
+
+use crate::builder::BuildError;
+use crate::connector::*;
+use crate::discord::*;
+use actix::*;
+use actix_web::http::StatusCode;
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use serde::de::DeserializeOwned;
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+
+/// Failed request is sent again at most this many times
+const MAX_RETRIES: usize = 3;
+/// Pause before reading again, when DISCORD had trouble with the request.
+/// It grows with every retry.
+const SERVER_ERROR_DELAY: Duration = Duration::from_secs(2);
+
+/// Something went wrong while talking to DISCORD REST API.
+#[derive(Debug)]
+pub enum RestError {
+    /// RequestConnector is dead or overloaded
+    Mailbox(MailboxError),
+    /// Request failed, or DISCORD refused it
+    Http(RequestError),
+    /// DISCORD responded with something unexpected
+    Parse(serde_json::Error),
+    /// Request could not be built
+    Build(BuildError),
+}
+
+impl std::fmt::Display for RestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RestError::Mailbox(e) => write!(f, "Could not deliver request to connector: {}", e),
+            RestError::Http(e) => write!(f, "Request failed: {}", e),
+            RestError::Parse(e) => write!(f, "Could not parse response: {}", e),
+            RestError::Build(e) => write!(f, "Could not build request: {}", e),
+        }
+    }
+}
+
+impl RestError {
+    /// How long DISCORD asks to wait, if request hit rate limit.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            RestError::Http(RequestError::Status { status, body })
+                if *status == StatusCode::TOO_MANY_REQUESTS =>
+            {
+                // API v6 tells milliseconds
+                let body: serde_json::Value = serde_json::from_str(body).ok()?;
+                body["retry_after"].as_u64().map(Duration::from_millis)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether DISCORD failed on its side, so the same request may succeed later.
+    pub fn is_server_error(&self) -> bool {
+        match self {
+            RestError::Http(RequestError::Status { status, .. }) => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+pub type RestFuture<T> = Box<dyn Future<Item = T, Error = RestError>>;
+
+/// Handle to DISCORD REST API.
+/// Cheap to clone, all requests go through RequestConnector.
+#[derive(Clone)]
+pub struct Rest {
+    connector: Addr<RequestConnector>,
+}
+
+/// How long to wait before sending failed request again, if it is worth it.
+fn retry_delay(error: &RestError, is_read: bool, retries: usize) -> Option<Duration> {
+    error.retry_after().or_else(|| {
+        if is_read && error.is_server_error() {
+            Some(SERVER_ERROR_DELAY * (retries as u32 + 1))
+        } else {
+            None
+        }
+    })
+}
+
+impl Rest {
+    pub fn new() -> Self {
+        Rest {
+            connector: System::current().registry().get::<RequestConnector>(),
+        }
+    }
+
+    /// Raw request, url must be with leading '/'.
+    /// Rate limited request is sent again, when DISCORD allows it.
+    /// Reading is also repeated after server errors, as it changes nothing.
+    /// Other requests are not, they may have been done before DISCORD failed.
+    pub fn request(
+        &self,
+        method: HttpMethod,
+        url: String,
+        data: Option<serde_json::Value>,
+    ) -> RestFuture<serde_json::Value> {
+        let connector = self.connector.clone();
+        let res = future::loop_fn(0, move |retries| {
+            let is_read = method == HttpMethod::GET;
+            let message = RequestMessage {
+                method: method.clone(),
+                url: url.clone(),
+                data: data.clone(),
+            };
+            connector
+                .send(message)
+                .map_err(RestError::Mailbox)
+                .and_then(|res| res.map_err(RestError::Http))
+                .then(move |res| match res {
+                    Err(e) => match retry_delay(&e, is_read, retries) {
+                        Some(after) if retries < MAX_RETRIES => {
+                            warn!("Request failed: {}, retrying in {:?}", e, after);
+                            let retry = Delay::new(Instant::now() + after)
+                                .then(move |_| Ok(Loop::Continue(retries + 1)));
+                            Either::A(retry)
+                        }
+                        _ => Either::B(future::err(e)),
+                    },
+                    Ok(value) => Either::B(future::ok(Loop::Break(value))),
+                })
+        });
+        Box::new(res)
+    }
+
+    /// Request with response parsed to some packet.
+    pub fn request_packet<T: DeserializeOwned + 'static>(
+        &self,
+        method: HttpMethod,
+        url: String,
+        data: Option<serde_json::Value>,
+    ) -> RestFuture<T> {
+        let res = self
+            .request(method, url, data)
+            .and_then(|value| serde_json::from_value(value).map_err(RestError::Parse));
+        Box::new(res)
+    }
+
+    /// Posts message to channel.
+    pub fn send_message(
+        &self,
+        channel_id: &Snowflake,
+        message: &MessageRequestPacket,
+    ) -> RestFuture<MessagePacket> {
+        let data = match serde_json::to_value(message) {
+            Ok(data) => data,
+            Err(e) => return Box::new(futures::future::err(RestError::Parse(e))),
+        };
+        self.request_packet(
+            HttpMethod::POST,
+            format!("/channels/{}/messages", channel_id.0),
+            Some(data),
+        )
+    }
+
+    pub fn get_message(
+        &self,
+        channel_id: &Snowflake,
+        message_id: &Snowflake,
+    ) -> RestFuture<MessagePacket> {
+        self.request_packet(
+            HttpMethod::GET,
+            format!("/channels/{}/messages/{}", channel_id.0, message_id.0),
+            None,
+        )
+    }
+
+    /// Messages of channel, that were sent after message `after`, at most `limit` of them.
+    /// Id "0" means from the very beginning of channel.
+    pub fn get_messages_after(
+        &self,
+        channel_id: &Snowflake,
+        after: &Snowflake,
+        limit: usize,
+    ) -> RestFuture<Vec<MessagePacket>> {
+        self.request_packet(
+            HttpMethod::GET,
+            format!(
+                "/channels/{}/messages?after={}&limit={}",
+                channel_id.0, after.0, limit
+            ),
+            None,
+        )
+    }
+
+    /// Replaces content and embed of my message.
+    pub fn edit_message(
+        &self,
+        channel_id: &Snowflake,
+        message_id: &Snowflake,
+        message: &MessageRequestPacket,
+    ) -> RestFuture<MessagePacket> {
+        let data = match serde_json::to_value(message) {
+            Ok(data) => data,
+            Err(e) => return Box::new(futures::future::err(RestError::Parse(e))),
+        };
+        self.request_packet(
+            HttpMethod::PATCH,
+            format!("/channels/{}/messages/{}", channel_id.0, message_id.0),
+            Some(data),
+        )
+    }
+
+    pub fn delete_message(
+        &self,
+        channel_id: &Snowflake,
+        message_id: &Snowflake,
+    ) -> RestFuture<serde_json::Value> {
+        self.request(
+            HttpMethod::DELETE,
+            format!("/channels/{}/messages/{}", channel_id.0, message_id.0),
+            None,
+        )
+    }
+
+    pub fn add_member_role(
+        &self,
+        guild_id: &Snowflake,
+        user_id: &Snowflake,
+        role_id: &Snowflake,
+    ) -> RestFuture<serde_json::Value> {
+        self.request(
+            HttpMethod::PUT,
+            format!(
+                "/guilds/{}/members/{}/roles/{}",
+                guild_id.0, user_id.0, role_id.0
+            ),
+            None,
+        )
+    }
+
+    pub fn remove_member_role(
+        &self,
+        guild_id: &Snowflake,
+        user_id: &Snowflake,
+        role_id: &Snowflake,
+    ) -> RestFuture<serde_json::Value> {
+        self.request(
+            HttpMethod::DELETE,
+            format!(
+                "/guilds/{}/members/{}/roles/{}",
+                guild_id.0, user_id.0, role_id.0
+            ),
+            None,
+        )
+    }
+
+    pub fn kick(&self, guild_id: &Snowflake, user_id: &Snowflake) -> RestFuture<serde_json::Value> {
+        self.request(
+            HttpMethod::DELETE,
+            format!("/guilds/{}/members/{}", guild_id.0, user_id.0),
+            None,
+        )
+    }
+
+    /// Bans user and deletes their messages for the last `delete_message_days` (0-7).
+    pub fn ban(
+        &self,
+        guild_id: &Snowflake,
+        user_id: &Snowflake,
+        delete_message_days: u8,
+    ) -> RestFuture<serde_json::Value> {
+        self.request(
+            HttpMethod::PUT,
+            format!("/guilds/{}/bans/{}", guild_id.0, user_id.0),
+            Some(serde_json::json!({ "delete-message-days": delete_message_days })),
+        )
+    }
+
+    /// Reacts to message. Emoji is unicode or "name:id" of custom emoji.
+    pub fn add_reaction(
+        &self,
+        channel_id: &Snowflake,
+        message_id: &Snowflake,
+        emoji: &str,
+    ) -> RestFuture<serde_json::Value> {
+        self.request(
+            HttpMethod::PUT,
+            format!(
+                "/channels/{}/messages/{}/reactions/{}/@me",
+                channel_id.0,
+                message_id.0,
+                encode_path(emoji)
+            ),
+            None,
+        )
+    }
+
+    /// Removes my reaction from message.
+    pub fn remove_own_reaction(
+        &self,
+        channel_id: &Snowflake,
+        message_id: &Snowflake,
+        emoji: &str,
+    ) -> RestFuture<serde_json::Value> {
+        self.request(
+            HttpMethod::DELETE,
+            format!(
+                "/channels/{}/messages/{}/reactions/{}/@me",
+                channel_id.0,
+                message_id.0,
+                encode_path(emoji)
+            ),
+            None,
+        )
+    }
+
+    /// Removes all reactions of everyone from message.
+    pub fn remove_all_reactions(
+        &self,
+        channel_id: &Snowflake,
+        message_id: &Snowflake,
+    ) -> RestFuture<serde_json::Value> {
+        self.request(
+            HttpMethod::DELETE,
+            format!(
+                "/channels/{}/messages/{}/reactions",
+                channel_id.0, message_id.0
+            ),
+            None,
+        )
+    }
+
+    /// Removes reaction of some user from message.
+    pub fn remove_user_reaction(
+        &self,
+        channel_id: &Snowflake,
+        message_id: &Snowflake,
+        emoji: &str,
+        user_id: &Snowflake,
+    ) -> RestFuture<serde_json::Value> {
+        self.request(
+            HttpMethod::DELETE,
+            format!(
+                "/channels/{}/messages/{}/reactions/{}/{}",
+                channel_id.0,
+                message_id.0,
+                encode_path(emoji),
+                user_id.0
+            ),
+            None,
+        )
+    }
+
+    pub fn get_channel(&self, channel_id: &Snowflake) -> RestFuture<ChannelPacket> {
+        self.request_packet(HttpMethod::GET, format!("/channels/{}", channel_id.0), None)
+    }
+
+    /// Opens direct message channel with user, or returns already opened one.
+    pub fn create_dm(&self, user_id: &Snowflake) -> RestFuture<ChannelPacket> {
+        self.request_packet(
+            HttpMethod::POST,
+            "/users/@me/channels".to_owned(),
+            Some(serde_json::json!({ "recipient_id": user_id.0 })),
+        )
+    }
+
+    /// Posts message to direct message channel with user.
+    pub fn send_direct(
+        &self,
+        user_id: &Snowflake,
+        message: &MessageRequestPacket,
+    ) -> RestFuture<MessagePacket> {
+        let data = match serde_json::to_value(message) {
+            Ok(data) => data,
+            Err(e) => return Box::new(futures::future::err(RestError::Parse(e))),
+        };
+        let rest = self.clone();
+        let res = self.create_dm(user_id).and_then(move |channel| {
+            rest.request_packet(
+                HttpMethod::POST,
+                format!("/channels/{}/messages", channel.id.0),
+                Some(data),
+            )
+        });
+        Box::new(res)
+    }
+
+    /// Posts message to channel and forgets about it.
+    pub fn say(&self, channel_id: &Snowflake, message: &MessageRequestPacket) {
+        spawn(self.send_message(channel_id, message));
+    }
+}
+
+/// Percent-encodes part of url, unicode emoji are not allowed there as is.
+fn encode_path(part: &str) -> String {
+    part.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Runs request in background. Errors are only logged.
+pub fn spawn<T: std::fmt::Debug + 'static>(f: RestFuture<T>) {
+    Arbiter::spawn(
+        f.map(|res| debug!("DISCORD responded with {:?}", res))
+            .map_err(|e| error!("Request to DISCORD failed: {}", e)),
+    );
+}
@@ -0,0 +1,64 @@
+/// Zero width space. It breaks mentions and is not visible in chat.
+const ZERO_WIDTH_SPACE: char = '\u{200B}';
+
+/// Characters that have special meaning in DISCORD markdown.
+/// '>' is special only at the beginning of line (quotes).
+const MARKDOWN_CHARACTERS: &[char] = &['\\', '*', '_', '~', '`', '|'];
+
+/// Makes user text safe to be repeated by bot.
+/// @everyone, @here, user, role and channel mentions are shown as text, but do not work.
+pub fn escape_mentions(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        result.push(c);
+        match (c, chars.peek()) {
+            // <@id>, <@!id>, <@&id> and <#id>
+            ('<', Some('@')) | ('<', Some('#')) => {
+                result.push(chars.next().unwrap());
+                result.push(ZERO_WIDTH_SPACE);
+            }
+            // @everyone and @here
+            ('@', Some(_)) => result.push(ZERO_WIDTH_SPACE),
+            _ => {}
+        }
+    }
+
+    result
+}
+
+/// Escapes markdown, so user text is shown exactly as it was typed.
+pub fn escape_markdown(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut line_start = true;
+    for c in text.chars() {
+        if MARKDOWN_CHARACTERS.contains(&c) || (c == '>' && line_start) {
+            result.push('\\');
+        }
+        result.push(c);
+        line_start = c == '\n';
+    }
+    result
+}
+
+/// Escapes both mentions and markdown.
+pub fn escape_all(text: &str) -> String {
+    escape_mentions(&escape_markdown(text))
+}
+
+/// Makes text safe to be put into HTML, both as text and as attribute value.
+pub fn escape_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
@@ -0,0 +1,449 @@
+use log::{debug, error, info};
+
+use crate::builder::{truncate, EmbedBuilder, MessageBuilder, FIELDS_LIMIT, FIELD_VALUE_LIMIT};
+use crate::command::*;
+use crate::cron::Cron;
+use crate::discord::*;
+use crate::guard::{Bucket, Guard};
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::i18n::{self, Language};
+use crate::permissions::Permissions;
+use crate::sanitize::escape_markdown;
+use crate::storage;
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Name of file in data folder
+const STORAGE_NAME: &str = "schedule";
+/// Colour of list embeds
+const LIST_COLOUR: u32 = 0x9B_59_B6;
+/// Reminders one user may have at once
+const MAX_REMINDERS_PER_USER: usize = 25;
+/// Scheduled posts one guild may have at once
+const MAX_POSTS_PER_GUILD: usize = 50;
+
+/// When job fires.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", tag = "type")]
+pub enum Kind {
+    /// Pings author once
+    Reminder,
+    /// Posts to channel once
+    Once,
+    /// Posts to channel every time cron expression matches
+    Cron { expression: String },
+}
+
+/// Something that has to be sent later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    /// None for reminders in direct messages
+    pub guild_id: Option<Snowflake>,
+    pub channel_id: Snowflake,
+    pub author_id: Snowflake,
+    pub text: String,
+    pub kind: Kind,
+    /// Unix time in seconds of the next firing
+    pub next: i64,
+}
+
+impl Job {
+    /// Message to be sent, reminders ping their author only.
+    fn message(&self, language: Language) -> MessageBuilder {
+        match self.kind {
+            Kind::Reminder => MessageBuilder::new()
+                .content(&i18n::text(
+                    language,
+                    "reminder-fired",
+                    &[("user", &self.author_id.0), ("text", &self.text)],
+                ))
+                .allowed_mentions(AllowedMentionsPacket::users(vec![self.author_id.clone()])),
+            _ => MessageBuilder::new()
+                .content(&self.text)
+                .allowed_mentions(AllowedMentionsPacket::none()),
+        }
+    }
+
+    fn describe(&self, language: Language) -> String {
+        let when = match &self.kind {
+            Kind::Cron { expression } => i18n::text(
+                language,
+                "job-cron-time",
+                &[("cron", expression), ("time", &format_time(self.next))],
+            ),
+            _ => format_time(self.next),
+        };
+        i18n::text(
+            language,
+            "job-describe",
+            &[
+                ("id", &self.id),
+                ("channel", &self.channel_id.0),
+                ("when", &when),
+            ],
+        )
+    }
+}
+
+/// All jobs, saved to data folder after every change.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Jobs {
+    next_id: u64,
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    fn add(&mut self, mut job: Job) -> u64 {
+        self.next_id += 1;
+        job.id = self.next_id;
+        self.jobs.push(job);
+        self.next_id
+    }
+}
+
+/// Reminders of users and scheduled posts of admins.
+/// Jobs are checked every tick, the ones missed while bot was offline fire once it is ready.
+pub struct Scheduler {
+    jobs: Rc<RefCell<Jobs>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler {
+            jobs: Rc::new(RefCell::new(storage::load(STORAGE_NAME))),
+        }
+    }
+
+    /// Registers commands, that manage jobs.
+    pub fn register(&self, registry: &mut CommandRegistry) {
+        let jobs = self.jobs.clone();
+        registry.register(
+            Command::new(
+                "remind",
+                "Reminds you later, e.g. `remind me in 2h to check the oven`",
+                move |ctx, args| remind(ctx, args, &jobs),
+            )
+            .argument("text", ArgumentType::Rest)
+            .cooldown(Bucket::User, 5, Duration::from_secs(60)),
+        );
+
+        let jobs = self.jobs.clone();
+        registry.register(Command::new(
+            "reminders",
+            "Shows your reminders",
+            move |ctx, _args| reminders(ctx, &jobs),
+        ));
+
+        let jobs = self.jobs.clone();
+        registry.register(
+            Command::new(
+                "schedule",
+                "Posts text to channel after duration like 1h30m, or repeatedly by quoted cron expression like \"0 9 * * 1\" (UTC)",
+                move |ctx, args| schedule(ctx, args, &jobs),
+            )
+            .argument("channel", ArgumentType::Channel)
+            .argument("when", ArgumentType::Text)
+            .argument("text", ArgumentType::Rest)
+            .guard(Guard::GuildOnly)
+            .permissions(Permissions::MANAGE_GUILD),
+        );
+
+        let jobs = self.jobs.clone();
+        registry.register(
+            Command::new(
+                "schedules",
+                "Shows scheduled posts of this guild",
+                move |ctx, _args| schedules(ctx, &jobs),
+            )
+            .guard(Guard::GuildOnly)
+            .permissions(Permissions::MANAGE_GUILD),
+        );
+
+        let jobs = self.jobs.clone();
+        registry.register(
+            Command::new(
+                "unschedule",
+                "Cancels your reminder, or scheduled post of this guild",
+                move |ctx, args| unschedule(ctx, args, &jobs),
+            )
+            .argument("id", ArgumentType::Integer),
+        );
+    }
+}
+
+impl EventHandler for Scheduler {
+    fn name(&self) -> &'static str {
+        "scheduler"
+    }
+
+    /// Sends due jobs, one-off jobs are forgotten and cron jobs are moved to their next time.
+    fn on_tick(&mut self, ctx: &EventContext) -> HandlerResult {
+        let now = Utc::now();
+        let mut jobs = self.jobs.borrow_mut();
+        if !jobs.jobs.iter().any(|j| j.next <= now.timestamp()) {
+            return Ok(());
+        }
+
+        let mut pending = Vec::with_capacity(jobs.jobs.len());
+        for mut job in jobs.jobs.drain(..) {
+            if job.next > now.timestamp() {
+                pending.push(job);
+                continue;
+            }
+
+            debug!("Job {} is due", job.id);
+            let language = i18n::language(ctx.settings, job.guild_id.as_ref(), &job.author_id);
+            match job.message(language).build() {
+                Ok(packet) => ctx.rest.say(&job.channel_id, &packet),
+                Err(e) => error!("Job {} has bad message: {}", job.id, e),
+            }
+
+            // Overdue cron job fires once, not once per every missed time
+            if let Kind::Cron { expression } = &job.kind {
+                match Cron::parse(expression).map(|c| c.next_after(now)) {
+                    Ok(Some(next)) => {
+                        job.next = next.timestamp();
+                        pending.push(job);
+                    }
+                    _ => info!("Job {} will never fire again", job.id),
+                }
+            }
+        }
+        jobs.jobs = pending;
+
+        storage::save(STORAGE_NAME, &*jobs)?;
+        Ok(())
+    }
+}
+
+fn remind(ctx: &CommandContext, args: &Args, jobs: &RefCell<Jobs>) -> CommandResult {
+    let (after, text) = parse_reminder(args.text("text").unwrap())
+        .ok_or_else(|| CommandError::Usage(ctx.text("remind-usage", &[])))?;
+    let author_id = &ctx.message.author.id;
+
+    let mut jobs = jobs.borrow_mut();
+    let count = jobs
+        .jobs
+        .iter()
+        .filter(|j| j.kind == Kind::Reminder && j.author_id.eq(author_id))
+        .count();
+    if count >= MAX_REMINDERS_PER_USER {
+        return Err(CommandError::Usage(ctx.plural(
+            "reminders-too-many",
+            count as i64,
+            &[],
+        )));
+    }
+
+    let job = Job {
+        id: 0,
+        guild_id: ctx.message.guild_id.clone(),
+        channel_id: ctx.message.channel_id.clone(),
+        author_id: author_id.clone(),
+        text: text.to_owned(),
+        kind: Kind::Reminder,
+        next: Utc::now().timestamp() + after.as_secs() as i64,
+    };
+    check_message(ctx, &job)?;
+    let next = job.next;
+    let id = jobs.add(job);
+    save(ctx, &jobs)?;
+
+    ctx.reply(&ctx.text("remind-set", &[("time", &format_time(next)), ("id", &id)]));
+    Ok(())
+}
+
+fn reminders(ctx: &CommandContext, jobs: &RefCell<Jobs>) -> CommandResult {
+    let jobs = jobs.borrow();
+    let own: Vec<&Job> = jobs
+        .jobs
+        .iter()
+        .filter(|j| j.kind == Kind::Reminder && j.author_id.eq(&ctx.message.author.id))
+        .collect();
+    if own.is_empty() {
+        ctx.reply(&ctx.text("reminders-none", &[]));
+        return Ok(());
+    }
+    send_list(ctx, &ctx.text("reminders-title", &[]), &own)
+}
+
+fn schedule(ctx: &CommandContext, args: &Args, jobs: &RefCell<Jobs>) -> CommandResult {
+    let guild_id = ctx.message.guild_id.clone().unwrap();
+    let channel_id = args.channel("channel").unwrap();
+    let when = args.text("when").unwrap();
+    let text = args.text("text").unwrap();
+
+    let in_guild = ctx
+        .cache
+        .channel(channel_id)
+        .map(|c| c.guild_id.as_ref() == Some(&guild_id))
+        .unwrap_or(false);
+    if !in_guild {
+        return Err(CommandError::Usage(ctx.text("channel-not-in-guild", &[])));
+    }
+
+    let now = Utc::now();
+    let (kind, next) = match parse_duration(when) {
+        Some(after) => (Kind::Once, now.timestamp() + after.as_secs() as i64),
+        None => {
+            let cron = Cron::parse(when).map_err(CommandError::Usage)?;
+            let next = cron.next_after(now).ok_or_else(|| {
+                CommandError::Usage(ctx.text("schedule-never", &[("when", &when)]))
+            })?;
+            (
+                Kind::Cron {
+                    expression: when.to_owned(),
+                },
+                next.timestamp(),
+            )
+        }
+    };
+
+    let mut jobs = jobs.borrow_mut();
+    let count = jobs
+        .jobs
+        .iter()
+        .filter(|j| j.kind != Kind::Reminder && j.guild_id.as_ref() == Some(&guild_id))
+        .count();
+    if count >= MAX_POSTS_PER_GUILD {
+        return Err(CommandError::Usage(ctx.plural(
+            "schedules-too-many",
+            count as i64,
+            &[],
+        )));
+    }
+
+    let job = Job {
+        id: 0,
+        guild_id: Some(guild_id),
+        channel_id: channel_id.clone(),
+        author_id: ctx.message.author.id.clone(),
+        text: text.to_owned(),
+        kind,
+        next,
+    };
+    check_message(ctx, &job)?;
+    let id = jobs.add(job);
+    save(ctx, &jobs)?;
+
+    ctx.reply(&ctx.text(
+        "schedule-set",
+        &[
+            ("id", &id),
+            ("channel", &channel_id.0),
+            ("time", &format_time(next)),
+        ],
+    ));
+    Ok(())
+}
+
+fn schedules(ctx: &CommandContext, jobs: &RefCell<Jobs>) -> CommandResult {
+    let jobs = jobs.borrow();
+    let posts: Vec<&Job> = jobs
+        .jobs
+        .iter()
+        .filter(|j| j.kind != Kind::Reminder && j.guild_id == ctx.message.guild_id)
+        .collect();
+    if posts.is_empty() {
+        ctx.reply(&ctx.text("schedules-none", &[]));
+        return Ok(());
+    }
+    send_list(ctx, &ctx.text("schedules-title", &[]), &posts)
+}
+
+/// Authors cancel their reminders, guild managers cancel posts of their guild.
+fn unschedule(ctx: &CommandContext, args: &Args, jobs: &RefCell<Jobs>) -> CommandResult {
+    let id = args.integer("id").unwrap() as u64;
+    let mut jobs = jobs.borrow_mut();
+
+    let allowed = match jobs.jobs.iter().find(|j| j.id == id) {
+        Some(job) if job.kind == Kind::Reminder => job.author_id.eq(&ctx.message.author.id),
+        Some(job) => {
+            job.guild_id.is_some()
+                && job.guild_id == ctx.message.guild_id
+                && ctx
+                    .author_permissions()
+                    .map(|p| p.contains(Permissions::MANAGE_GUILD))
+                    .unwrap_or(false)
+        }
+        None => false,
+    };
+    if !allowed {
+        return Err(CommandError::Usage(
+            ctx.text("unschedule-not-found", &[("id", &id)]),
+        ));
+    }
+
+    jobs.jobs.retain(|j| j.id != id);
+    save(ctx, &jobs)?;
+    ctx.reply(&ctx.text("unschedule-done", &[("id", &id)]));
+    Ok(())
+}
+
+fn send_list(ctx: &CommandContext, title: &str, jobs: &[&Job]) -> CommandResult {
+    let embed = jobs.iter().take(FIELDS_LIMIT).fold(
+        EmbedBuilder::new().title(title).colour(LIST_COLOUR),
+        |embed, job| {
+            embed.field(
+                &job.describe(ctx.language),
+                &truncate(&escape_markdown(&job.text), FIELD_VALUE_LIMIT),
+                false,
+            )
+        },
+    );
+    ctx.send(MessageBuilder::new().embed(embed))
+}
+
+/// Text of job must fit into a message, it is better to know it now than when it fires.
+fn check_message(ctx: &CommandContext, job: &Job) -> CommandResult {
+    job.message(ctx.language)
+        .build()
+        .map(|_| ())
+        .map_err(|e| CommandError::Usage(e.to_string()))
+}
+
+fn save(ctx: &CommandContext, jobs: &Jobs) -> CommandResult {
+    storage::save(STORAGE_NAME, jobs).map_err(|e| {
+        error!("Failed to save schedule: {}", e);
+        CommandError::Failed(ctx.text("schedule-save-failed", &[]))
+    })
+}
+
+/// Time in UTC, as people read it.
+pub fn format_time(unix: i64) -> String {
+    Utc.timestamp_opt(unix, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "never".to_owned())
+}
+
+/// Parses "me in 2h to do something", both "me", "in" and "to" are optional.
+fn parse_reminder(text: &str) -> Option<(Duration, &str)> {
+    let text = skip_word(text.trim(), "me");
+    let text = skip_word(text, "in");
+    let end = text.find(char::is_whitespace)?;
+    let after = parse_duration(&text[..end])?;
+    let text = skip_word(text[end..].trim_start(), "to");
+    if text.is_empty() {
+        None
+    } else {
+        Some((after, text))
+    }
+}
+
+/// Text without leading word, if it is there.
+fn skip_word<'a>(text: &'a str, word: &str) -> &'a str {
+    match text.get(..word.len()) {
+        Some(start)
+            if start.eq_ignore_ascii_case(word)
+                && text[word.len()..].starts_with(char::is_whitespace) =>
+        {
+            text[word.len()..].trim_start()
+        }
+        _ => text,
+    }
+}
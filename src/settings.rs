@@ -0,0 +1,343 @@
+use log::{error, info};
+
+use crate::builder::{truncate, EmbedBuilder, MessageBuilder, FIELDS_LIMIT, FIELD_VALUE_LIMIT};
+use crate::command::*;
+use crate::discord::Snowflake;
+use crate::guard::Guard;
+use crate::permissions::Permissions;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Database of settings in working directory
+pub const SETTINGS_PATH: &str = "data/settings.db";
+/// Colour of settings embed
+const SETTINGS_COLOUR: u32 = 0x60_7D_8B;
+
+/// Schema changes, applied in order. Version of schema is the number of applied migrations.
+/// Never edit applied migration, add a new one.
+const MIGRATIONS: &[&str] = &["CREATE TABLE settings (
+        scope TEXT NOT NULL,
+        id TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (scope, id, key)
+    )"];
+
+/// Applies migrations, that database does not have yet.
+fn migrate(db: &mut Connection, migrations: &[&str]) -> SettingsResult<()> {
+    let version: u32 = db.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let version = version as usize;
+    if version > migrations.len() {
+        return Err(SettingsError::Version {
+            found: version,
+            known: migrations.len(),
+        });
+    }
+
+    for (i, migration) in migrations.iter().enumerate().skip(version) {
+        info!("Migrating settings to version {}", i + 1);
+        // Migration and its version are applied together or not at all,
+        // transaction is rolled back, when it is dropped without commit
+        let tx = db.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.execute_batch(&format!("PRAGMA user_version = {}", i + 1))?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Something went wrong with settings database.
+#[derive(Debug)]
+pub enum SettingsError {
+    /// Folder of database could not be created
+    Io(std::io::Error),
+    Database(rusqlite::Error),
+    /// Stored value does not match type of key
+    Value(serde_json::Error),
+    /// Database was written by newer version of bot
+    Version {
+        found: usize,
+        known: usize,
+    },
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SettingsError::Io(e) => write!(f, "Settings folder failed: {}", e),
+            SettingsError::Database(e) => write!(f, "Settings database failed: {}", e),
+            SettingsError::Value(e) => write!(f, "Setting has bad value: {}", e),
+            SettingsError::Version { found, known } => write!(
+                f,
+                "Settings schema version {} is newer than known {}",
+                found, known
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<std::io::Error> for SettingsError {
+    fn from(e: std::io::Error) -> Self {
+        SettingsError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for SettingsError {
+    fn from(e: rusqlite::Error) -> Self {
+        SettingsError::Database(e)
+    }
+}
+
+impl From<serde_json::Error> for SettingsError {
+    fn from(e: serde_json::Error) -> Self {
+        SettingsError::Value(e)
+    }
+}
+
+/// What setting belongs to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Guild,
+    Channel,
+    User,
+}
+
+impl Scope {
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Guild => "guild",
+            Scope::Channel => "channel",
+            Scope::User => "user",
+        }
+    }
+}
+
+/// Name of setting together with type of its value.
+/// Features declare their keys as constants, e.g. `Key<Snowflake>` for log channel.
+#[derive(Debug)]
+pub struct Key<T> {
+    pub name: &'static str,
+    value: PhantomData<T>,
+}
+
+impl<T> Key<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Key {
+            name,
+            value: PhantomData,
+        }
+    }
+}
+
+/// Persistent settings of guilds, channels and users.
+/// Values are stored as JSON, so any serializable type fits.
+/// Cheap to clone, so it can be used later in futures.
+#[derive(Clone)]
+pub struct Settings {
+    db: Rc<Connection>,
+}
+
+impl std::fmt::Debug for Settings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Settings").finish()
+    }
+}
+
+pub type SettingsResult<T> = Result<T, SettingsError>;
+
+impl Settings {
+    /// Opens database, creates it if needed and migrates it to the current schema.
+    pub fn open<P: AsRef<Path>>(path: P) -> SettingsResult<Self> {
+        if let Some(folder) = path.as_ref().parent() {
+            std::fs::create_dir_all(folder)?;
+        }
+        let mut db = Connection::open(path)?;
+        migrate(&mut db, MIGRATIONS)?;
+        Ok(Settings { db: Rc::new(db) })
+    }
+
+    pub fn get<T: DeserializeOwned>(
+        &self,
+        key: &Key<T>,
+        scope: Scope,
+        id: &Snowflake,
+    ) -> SettingsResult<Option<T>> {
+        let value: Option<String> = self
+            .db
+            .query_row(
+                "SELECT value FROM settings WHERE scope = ?1 AND id = ?2 AND key = ?3",
+                params![scope.name(), id.0, key.name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match value {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set<T: Serialize>(
+        &self,
+        key: &Key<T>,
+        scope: Scope,
+        id: &Snowflake,
+        value: &T,
+    ) -> SettingsResult<()> {
+        let value = serde_json::to_string(value)?;
+        self.db.execute(
+            "INSERT OR REPLACE INTO settings (scope, id, key, value) VALUES (?1, ?2, ?3, ?4)",
+            params![scope.name(), id.0, key.name, value],
+        )?;
+        Ok(())
+    }
+
+    /// Removes setting, so default is used again. Returns whether it was set.
+    pub fn remove<T>(&self, key: &Key<T>, scope: Scope, id: &Snowflake) -> SettingsResult<bool> {
+        let removed = self.db.execute(
+            "DELETE FROM settings WHERE scope = ?1 AND id = ?2 AND key = ?3",
+            params![scope.name(), id.0, key.name],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// All settings of guild, channel or user as raw JSON, sorted by key.
+    pub fn all(&self, scope: Scope, id: &Snowflake) -> SettingsResult<Vec<(String, String)>> {
+        let mut statement = self
+            .db
+            .prepare("SELECT key, value FROM settings WHERE scope = ?1 AND id = ?2 ORDER BY key")?;
+        let rows = statement.query_map(params![scope.name(), id.0], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        let mut all = vec![];
+        for row in rows {
+            all.push(row?);
+        }
+        Ok(all)
+    }
+}
+
+/// Registers command, that shows settings of guild.
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        Command::new("settings", "Shows settings of this guild", |ctx, _args| {
+            list(ctx)
+        })
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+}
+
+fn list(ctx: &CommandContext) -> CommandResult {
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    let all = ctx.settings.all(Scope::Guild, guild_id).map_err(|e| {
+        error!("Failed to list settings of {:?}: {}", guild_id, e);
+        CommandError::Failed(ctx.text("settings-failed", &[]))
+    })?;
+    if all.is_empty() {
+        ctx.reply(&ctx.text("settings-default", &[]));
+        return Ok(());
+    }
+
+    let embed = all.iter().take(FIELDS_LIMIT).fold(
+        EmbedBuilder::new()
+            .title(&ctx.text("settings-title", &[]))
+            .colour(SETTINGS_COLOUR),
+        |embed, (key, value)| {
+            embed.field(
+                key,
+                &truncate(&format!("`{}`", value), FIELD_VALUE_LIMIT),
+                false,
+            )
+        },
+    );
+    ctx.send(MessageBuilder::new().embed(embed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(db: &Connection) -> u32 {
+        db.pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    fn has_table(db: &Connection, name: &str) -> bool {
+        db.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+            params![name],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            > 0
+    }
+
+    #[test]
+    fn migrates_once() {
+        let mut db = Connection::open_in_memory().unwrap();
+        migrate(&mut db, &["CREATE TABLE a (x)", "CREATE TABLE b (x)"]).unwrap();
+        assert_eq!(version(&db), 2);
+        migrate(&mut db, &["CREATE TABLE a (x)", "CREATE TABLE b (x)"]).unwrap();
+        migrate(
+            &mut db,
+            &[
+                "CREATE TABLE a (x)",
+                "CREATE TABLE b (x)",
+                "CREATE TABLE c (x)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(version(&db), 3);
+        assert!(has_table(&db, "c"));
+    }
+
+    #[test]
+    fn failed_migration_rolls_back() {
+        let mut db = Connection::open_in_memory().unwrap();
+        let res = migrate(
+            &mut db,
+            &[
+                "CREATE TABLE a (x)",
+                "CREATE TABLE b (x); INSERT INTO missing VALUES (1)",
+            ],
+        );
+        assert!(res.is_err());
+        assert_eq!(version(&db), 1);
+        assert!(has_table(&db, "a"));
+        assert!(!has_table(&db, "b"));
+    }
+
+    #[test]
+    fn newer_database() {
+        let mut db = Connection::open_in_memory().unwrap();
+        migrate(&mut db, &["CREATE TABLE a (x)", "CREATE TABLE b (x)"]).unwrap();
+        match migrate(&mut db, &["CREATE TABLE a (x)"]) {
+            Err(SettingsError::Version { found, known }) => assert_eq!((found, known), (2, 1)),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn values() {
+        let settings = Settings::open(":memory:").unwrap();
+        let id = Snowflake("1".to_owned());
+        let key: Key<Vec<String>> = Key::new("test");
+        assert_eq!(settings.get(&key, Scope::Guild, &id).unwrap(), None);
+        settings
+            .set(&key, Scope::Guild, &id, &vec!["a".to_owned()])
+            .unwrap();
+        assert_eq!(
+            settings.get(&key, Scope::Guild, &id).unwrap(),
+            Some(vec!["a".to_owned()])
+        );
+        assert_eq!(settings.get(&key, Scope::User, &id).unwrap(), None);
+        assert!(settings.remove(&key, Scope::Guild, &id).unwrap());
+        assert!(!settings.remove(&key, Scope::Guild, &id).unwrap());
+    }
+}
@@ -0,0 +1,449 @@
+use log::{debug, error};
+
+use crate::builder::{truncate, BuildError, EmbedBuilder, MessageBuilder, DESCRIPTION_LIMIT};
+use crate::command::*;
+use crate::data::POOL;
+use crate::discord::*;
+use crate::guard::Guard;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::i18n::{self, Language};
+use crate::permissions::Permissions;
+use crate::rest::{self, Rest, RestError, RestFuture};
+use crate::settings::{Key, Scope, Settings};
+use crate::storage;
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Name of file in data folder
+const STORAGE_NAME: &str = "starboard";
+/// Colour of reposts
+const STAR_COLOUR: u32 = 0xF1_C4_0F;
+
+/// Starboard chosen by admins of guild, it replaces one from conf/bot.yaml
+pub const STARBOARD: Key<StarboardConfig> = Key::new("starboard");
+
+/// Starboard of one guild.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StarboardConfig {
+    /// Channel for reposts
+    pub channel: Snowflake,
+    /// Unicode emoji or "name:id" of custom emoji
+    #[serde(default = "default_emoji")]
+    pub emoji: String,
+    /// Reactions needed for repost
+    #[serde(default = "default_threshold")]
+    pub threshold: i64,
+}
+
+fn default_emoji() -> String {
+    "\u{2B50}".to_owned()
+}
+
+fn default_threshold() -> i64 {
+    3
+}
+
+/// Starboard of guild from settings, or from config if it is not set.
+pub fn starboard_config(settings: &Settings, guild_id: &Snowflake) -> Option<StarboardConfig> {
+    match settings.get(&STARBOARD, Scope::Guild, guild_id) {
+        Ok(Some(config)) => return Some(config),
+        Ok(None) => (),
+        Err(e) => error!("Failed to get starboard of {:?}: {}", guild_id, e),
+    }
+    POOL.config.starboard.get(guild_id).cloned()
+}
+
+/// Repost of starred message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Repost {
+    /// Starboard channel
+    pub channel_id: Snowflake,
+    pub repost_id: Snowflake,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Original message id -> its repost, kept in data folder
+    reposts: HashMap<Snowflake, Repost>,
+    /// Original messages, that are being reposted right now,
+    /// and whether their reactions changed meanwhile
+    pending: HashMap<Snowflake, bool>,
+}
+
+impl State {
+    fn save(&self) {
+        if let Err(e) = storage::save(STORAGE_NAME, &self.reposts) {
+            error!("Failed to save starboard: {}", e);
+        }
+    }
+}
+
+/// Reposts messages with enough stars to starboard channel from settings or conf/bot.yaml.
+/// Reactions events do not have counts, so the message is requested every time.
+pub struct Starboard {
+    state: Rc<RefCell<State>>,
+}
+
+impl Starboard {
+    pub fn new() -> Self {
+        Starboard {
+            state: Rc::new(RefCell::new(State {
+                reposts: storage::load(STORAGE_NAME),
+                pending: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Requests message with its reactions and updates its repost.
+    fn refresh(&self, ctx: &EventContext, reaction: &MessageReactionPacket) -> HandlerResult {
+        let guild_id = match &reaction.guild_id {
+            Some(guild_id) => guild_id,
+            None => return Ok(()),
+        };
+        let config = match starboard_config(ctx.settings, guild_id) {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+        // Starring reposts would make reposts of reposts
+        if !reaction.emoji.matches(&config.emoji) || reaction.channel_id.eq(&config.channel) {
+            return Ok(());
+        }
+
+        let state = self.state.clone();
+        let rest = ctx.rest.clone();
+        let language = i18n::guild_language(ctx.settings, guild_id);
+        let guild_id = guild_id.clone();
+        let task = ctx
+            .rest
+            .get_message(&reaction.channel_id, &reaction.message_id)
+            .and_then(move |message| update(&rest, language, &state, &config, &guild_id, &message));
+        rest::spawn(Box::new(task));
+        Ok(())
+    }
+
+    /// Forgets repost and deletes it from starboard.
+    fn unstar(&self, ctx: &EventContext, message_id: &Snowflake) {
+        let mut state = self.state.borrow_mut();
+        if let Some(repost) = state.reposts.remove(message_id) {
+            debug!("Message {:?} is not starred anymore", message_id);
+            rest::spawn(
+                ctx.rest
+                    .delete_message(&repost.channel_id, &repost.repost_id),
+            );
+            state.save();
+        }
+    }
+}
+
+impl EventHandler for Starboard {
+    fn name(&self) -> &'static str {
+        "starboard"
+    }
+
+    fn on_reaction_add(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        self.refresh(ctx, reaction)
+    }
+
+    fn on_reaction_remove(
+        &mut self,
+        ctx: &EventContext,
+        reaction: &MessageReactionPacket,
+    ) -> HandlerResult {
+        self.refresh(ctx, reaction)
+    }
+
+    fn on_reaction_remove_all(
+        &mut self,
+        ctx: &EventContext,
+        reactions: &MessageReactionRemoveAllPacket,
+    ) -> HandlerResult {
+        self.unstar(ctx, &reactions.message_id);
+        Ok(())
+    }
+
+    /// Deleted original takes its repost with it, deleted repost is just forgotten.
+    fn on_message_delete(
+        &mut self,
+        ctx: &EventContext,
+        delete: &MessageDeletePacket,
+    ) -> HandlerResult {
+        self.unstar(ctx, &delete.id);
+
+        let mut state = self.state.borrow_mut();
+        let count = state.reposts.len();
+        state.reposts.retain(|_, r| r.repost_id.ne(&delete.id));
+        if state.reposts.len() != count {
+            state.save();
+        }
+        Ok(())
+    }
+}
+
+/// Creates, edits or deletes repost according to current stars of message.
+fn update(
+    rest: &Rest,
+    language: Language,
+    state: &Rc<RefCell<State>>,
+    config: &StarboardConfig,
+    guild_id: &Snowflake,
+    message: &MessagePacket,
+) -> RestFuture<()> {
+    let count = message
+        .reactions
+        .iter()
+        .flatten()
+        .find(|r| r.emoji.matches(&config.emoji))
+        .map(|r| r.count)
+        .unwrap_or(0);
+    let done: RestFuture<()> = Box::new(futures::future::ok(()));
+    let mut current = state.borrow_mut();
+
+    if count < config.threshold {
+        if let Some(repost) = current.reposts.remove(&message.id) {
+            debug!("Message {:?} fell below starboard threshold", &message.id);
+            current.save();
+            return Box::new(
+                rest.delete_message(&repost.channel_id, &repost.repost_id)
+                    .map(|_| ()),
+            );
+        }
+        return done;
+    }
+
+    let packet = match repost(language, config, guild_id, message, count) {
+        Ok(packet) => packet,
+        Err(e) => return Box::new(futures::future::err(RestError::Build(e))),
+    };
+    if let Some(repost) = current.reposts.get(&message.id) {
+        return Box::new(
+            rest.edit_message(&repost.channel_id, &repost.repost_id, &packet)
+                .map(|_| ()),
+        );
+    }
+    // Repost is being sent right now, its count is updated, when it is sent
+    if let Some(changed) = current.pending.get_mut(&message.id) {
+        *changed = true;
+        return done;
+    }
+    current.pending.insert(message.id.clone(), false);
+
+    debug!("Message {:?} got to starboard", &message.id);
+    let state = state.clone();
+    let rest = rest.clone();
+    let config = config.clone();
+    let guild_id = guild_id.clone();
+    let channel_id = message.channel_id.clone();
+    let message_id = message.id.clone();
+    let task = rest.send_message(&config.channel, &packet).then(
+        move |res: Result<MessagePacket, RestError>| -> RestFuture<()> {
+            let changed = {
+                let mut current = state.borrow_mut();
+                let changed = current.pending.remove(&message_id).unwrap_or(false);
+                let posted = match res {
+                    Ok(posted) => posted,
+                    Err(e) => return Box::new(futures::future::err(e)),
+                };
+                current.reposts.insert(
+                    message_id.clone(),
+                    Repost {
+                        channel_id: posted.channel_id,
+                        repost_id: posted.id,
+                    },
+                );
+                current.save();
+                changed
+            };
+            if !changed {
+                return Box::new(futures::future::ok(()));
+            }
+            debug!("Stars of {:?} changed while it was reposted", &message_id);
+            let task = rest
+                .get_message(&channel_id, &message_id)
+                .and_then(move |message| {
+                    update(&rest, language, &state, &config, &guild_id, &message)
+                });
+            Box::new(task)
+        },
+    );
+    Box::new(task)
+}
+
+/// Star count with link to channel and embed with the original message.
+fn repost(
+    language: Language,
+    config: &StarboardConfig,
+    guild_id: &Snowflake,
+    message: &MessagePacket,
+    count: i64,
+) -> Result<MessageRequestPacket, BuildError> {
+    let link = format!(
+        "https://discordapp.com/channels/{}/{}/{}",
+        guild_id.0, message.channel_id.0, message.id.0
+    );
+    let avatar = message.author.avatar_url();
+
+    let mut embed = EmbedBuilder::new()
+        .author(
+            &message.author.username,
+            None,
+            avatar.as_ref().map(String::as_str),
+        )
+        .colour(STAR_COLOUR)
+        .field(
+            &i18n::text(language, "starboard-source", &[]),
+            &i18n::text(language, "starboard-jump", &[("link", &link)]),
+            false,
+        )
+        .timestamp(&message.timestamp)
+        .footer(
+            &i18n::text(language, "message-footer", &[("id", &message.id.0)]),
+            None,
+        );
+    if !message.content.is_empty() {
+        embed = embed.description(&truncate(&message.content, DESCRIPTION_LIMIT));
+    }
+    // Only images have sizes
+    if let Some(image) = message.attachments.iter().find(|a| a.width.is_some()) {
+        embed = embed.image(&image.url);
+    }
+
+    MessageBuilder::new()
+        .content(&format!(
+            "{} **{}** <#{}>",
+            display_emoji(&config.emoji),
+            count,
+            message.channel_id.0
+        ))
+        .embed(embed)
+        .build()
+}
+
+/// Registers commands, that show and change starboard of guild.
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        Command::new(
+            "starboard",
+            "Shows starboard of this guild",
+            |ctx, _args| show(ctx),
+        )
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+
+    registry.register(
+        Command::new(
+            "setstarboard",
+            "Changes one part of starboard: channel, emoji or threshold",
+            |ctx, args| set(ctx, args.text("part").unwrap(), args.text("value").unwrap()),
+        )
+        .argument("part", ArgumentType::Text)
+        .argument("value", ArgumentType::Text)
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+
+    registry.register(
+        Command::new(
+            "resetstarboard",
+            "Returns starboard of this guild from my config",
+            |ctx, _args| {
+                let guild_id = ctx.message.guild_id.as_ref().unwrap();
+                ctx.settings
+                    .remove(&STARBOARD, Scope::Guild, guild_id)
+                    .map_err(|e| {
+                        error!("Failed to reset starboard of {:?}: {}", guild_id, e);
+                        CommandError::Failed(ctx.text("setting-failed", &[]))
+                    })?;
+                show(ctx)
+            },
+        )
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+}
+
+fn show(ctx: &CommandContext) -> CommandResult {
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    match starboard_config(ctx.settings, guild_id) {
+        Some(config) => ctx.reply(&ctx.plural(
+            "starboard-show",
+            config.threshold,
+            &[
+                ("channel", &config.channel.0),
+                ("emoji", &display_emoji(&config.emoji)),
+            ],
+        )),
+        None => ctx.reply(&ctx.text("starboard-none", &[])),
+    }
+    Ok(())
+}
+
+/// Changes one part of starboard, the rest stays as it is in settings or config.
+fn set(ctx: &CommandContext, part: &str, value: &str) -> CommandResult {
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    let current = starboard_config(ctx.settings, guild_id);
+
+    let config = match (part.to_lowercase().as_str(), current) {
+        ("channel", current) => {
+            let channel_id = match parse_value(&ArgumentType::Channel, value) {
+                Some(Value::Channel(channel_id)) => channel_id,
+                _ => return Err(CommandError::Usage(ctx.text("starboard-not-channel", &[]))),
+            };
+            let in_guild = ctx
+                .cache
+                .channel(&channel_id)
+                .map(|c| c.guild_id.as_ref() == Some(guild_id))
+                .unwrap_or(false);
+            if !in_guild {
+                return Err(CommandError::Usage(ctx.text("channel-not-in-guild", &[])));
+            }
+            match current {
+                Some(config) => StarboardConfig {
+                    channel: channel_id,
+                    ..config
+                },
+                None => StarboardConfig {
+                    channel: channel_id,
+                    emoji: default_emoji(),
+                    threshold: default_threshold(),
+                },
+            }
+        }
+        (_, None) => {
+            return Err(CommandError::Usage(
+                ctx.text("starboard-channel-first", &[]),
+            ))
+        }
+        ("emoji", Some(config)) => match parse_emoji(value) {
+            Some(emoji) => StarboardConfig { emoji, ..config },
+            None => {
+                return Err(CommandError::Usage(
+                    ctx.text("not-emoji", &[("text", &value)]),
+                ))
+            }
+        },
+        ("threshold", Some(config)) => match value.parse::<i64>() {
+            Ok(threshold) if threshold > 0 => StarboardConfig {
+                threshold,
+                ..config
+            },
+            _ => return Err(CommandError::Usage(ctx.text("starboard-threshold", &[]))),
+        },
+        _ => return Err(CommandError::Usage(ctx.text("starboard-parts", &[]))),
+    };
+
+    ctx.settings
+        .set(&STARBOARD, Scope::Guild, guild_id, &config)
+        .map_err(|e| {
+            error!("Failed to save starboard of {:?}: {}", guild_id, e);
+            CommandError::Failed(ctx.text("setting-failed", &[]))
+        })?;
+    show(ctx)
+}
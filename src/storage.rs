@@ -0,0 +1,44 @@
+use log::{debug, info};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Folder for state, that has to survive restarts
+const DATA_FOLDER: &str = "data";
+
+fn path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(DATA_FOLDER);
+    path.push(format!("{}.json", name));
+    path
+}
+
+/// Loads data/{name}.json, missing file means nothing was saved yet.
+/// Broken file is fatal, otherwise it would be overwritten with empty state.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    let path = path(name);
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            info!("Could not open {:?}: {}. Starting from scratch", &path, e);
+            return T::default();
+        }
+    };
+
+    serde_json::from_reader(std::io::BufReader::new(file))
+        .unwrap_or_else(|e| panic!("Failed to parse {:?}: {}", &path, e))
+}
+
+/// Saves data/{name}.json.
+/// File is replaced at once, so crash in the middle does not break it.
+pub fn save<T: Serialize>(name: &str, value: &T) -> std::io::Result<()> {
+    std::fs::create_dir_all(DATA_FOLDER)?;
+    let path = path(name);
+    let tmp = path.with_extension("json.tmp");
+
+    let json = serde_json::to_vec_pretty(value)?;
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, &path)?;
+    debug!("Saved {:?}", &path);
+    Ok(())
+}
@@ -0,0 +1,323 @@
+use log::{debug, error};
+
+use crate::builder::{EmbedBuilder, MessageBuilder};
+use crate::command::*;
+use crate::data::POOL;
+use crate::discord::*;
+use crate::guard::Guard;
+use crate::handler::{EventContext, EventHandler, HandlerResult};
+use crate::i18n::interpolate;
+use crate::permissions::Permissions;
+use crate::rest;
+use crate::sanitize::escape_markdown;
+use crate::settings::{Key, Scope, Settings};
+use serde::{Deserialize, Serialize};
+
+/// Colour of welcome embeds
+const WELCOME_COLOUR: u32 = 0x2E_CC_71;
+/// Colour of goodbye embeds
+const GOODBYE_COLOUR: u32 = 0x95_A5_A6;
+
+/// Welcome chosen by admins of guild, it replaces one from conf/bot.yaml
+pub const WELCOME: Key<WelcomeConfig> = Key::new("welcome");
+
+/// Welcome and goodbye of one guild.
+/// Templates may use {mention}, {username}, {guild} and {member_count}.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WelcomeConfig {
+    /// Channel for welcome and goodbye messages
+    pub channel: Snowflake,
+    /// Message on join, none to be silent
+    pub welcome: Option<String>,
+    /// Message on leave, none to be silent
+    pub goodbye: Option<String>,
+    /// Send messages as embeds with avatar
+    #[serde(default)]
+    pub embed: bool,
+    /// Direct message to new member
+    pub direct: Option<String>,
+    /// Starting role of new member
+    pub role: Option<Snowflake>,
+}
+
+/// Welcome of guild from settings, or from config if it is not set.
+pub fn welcome_config(settings: &Settings, guild_id: &Snowflake) -> Option<WelcomeConfig> {
+    match settings.get(&WELCOME, Scope::Guild, guild_id) {
+        Ok(Some(config)) => return Some(config),
+        Ok(None) => (),
+        Err(e) => error!("Failed to get welcome of {:?}: {}", guild_id, e),
+    }
+    POOL.config.welcome.get(guild_id).cloned()
+}
+
+/// Greets new members and says goodbye to leaving ones.
+pub struct Welcome;
+
+impl EventHandler for Welcome {
+    fn name(&self) -> &'static str {
+        "welcome"
+    }
+
+    fn on_member_add(&mut self, ctx: &EventContext, add: &GuildMemberAddPacket) -> HandlerResult {
+        let config = match welcome_config(ctx.settings, &add.guild_id) {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+        let user = add
+            .member
+            .user
+            .as_ref()
+            .ok_or("New member does not have user")?;
+
+        if let Some(role_id) = &config.role {
+            debug!("Giving starting role to {:?}", &user.id);
+            rest::spawn(ctx.rest.add_member_role(&add.guild_id, &user.id, role_id));
+        }
+        if let Some(template) = &config.welcome {
+            let text = render(template, ctx, &add.guild_id, user);
+            send(ctx, &config, user, &text, WELCOME_COLOUR);
+        }
+        if let Some(template) = &config.direct {
+            let text = render(template, ctx, &add.guild_id, user);
+            match MessageBuilder::new().content(&text).build() {
+                Ok(packet) => rest::spawn(ctx.rest.send_direct(&user.id, &packet)),
+                Err(e) => error!("Bad direct welcome of guild {:?}: {}", &add.guild_id, e),
+            }
+        }
+        Ok(())
+    }
+
+    fn on_member_remove(
+        &mut self,
+        ctx: &EventContext,
+        remove: &GuildMemberRemovePacket,
+    ) -> HandlerResult {
+        let config = match welcome_config(ctx.settings, &remove.guild_id) {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+        if let Some(template) = &config.goodbye {
+            let text = render(template, ctx, &remove.guild_id, &remove.user);
+            send(ctx, &config, &remove.user, &text, GOODBYE_COLOUR);
+        }
+        Ok(())
+    }
+}
+
+/// Replaces placeholders of template. Names and counts are never expanded again.
+fn render(template: &str, ctx: &EventContext, guild_id: &Snowflake, user: &UserPacket) -> String {
+    let guild = ctx.cache.guild(guild_id);
+    let guild_name = guild.map(|g| g.name.as_str()).unwrap_or("the guild");
+    let member_count = guild
+        .and_then(|g| g.member_count)
+        .map(|c| c.to_string())
+        .unwrap_or_else(|| "?".to_owned());
+
+    interpolate(
+        template,
+        &[
+            ("mention", &format!("<@{}>", user.id.0)),
+            ("username", &escape_markdown(&user.username)),
+            ("guild", &escape_markdown(guild_name)),
+            ("member_count", &member_count),
+        ],
+    )
+}
+
+/// Sends text to welcome channel, only the member may be pinged.
+fn send(ctx: &EventContext, config: &WelcomeConfig, user: &UserPacket, text: &str, colour: u32) {
+    let message = if config.embed {
+        let mut embed = EmbedBuilder::new().description(text).colour(colour);
+        if let Some(avatar) = user.avatar_url() {
+            embed = embed.thumbnail(&avatar);
+        }
+        MessageBuilder::new().embed(embed)
+    } else {
+        MessageBuilder::new().content(text)
+    };
+
+    match message
+        .allowed_mentions(AllowedMentionsPacket::users(vec![user.id.clone()]))
+        .build()
+    {
+        Ok(packet) => ctx.rest.say(&config.channel, &packet),
+        Err(e) => error!(
+            "Bad welcome message for channel {:?}: {}",
+            &config.channel, e
+        ),
+    }
+}
+
+/// Registers commands, that show and change welcome of guild.
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        Command::new(
+            "welcome",
+            "Shows welcome and goodbye of this guild",
+            |ctx, _args| show(ctx),
+        )
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+
+    registry.register(
+        Command::new(
+            "setwelcome",
+            "Changes one part of welcome: channel, welcome, goodbye, direct, embed or role. \
+             Texts may use {mention}, {username}, {guild} and {member_count}, \
+             no text or role turns that part off",
+            |ctx, args| {
+                set(
+                    ctx,
+                    args.text("part").unwrap(),
+                    args.text("value").unwrap_or(""),
+                )
+            },
+        )
+        .argument("part", ArgumentType::Text)
+        .optional_argument("value", ArgumentType::Rest)
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD | Permissions::MANAGE_ROLES),
+    );
+
+    registry.register(
+        Command::new(
+            "resetwelcome",
+            "Returns welcome of this guild from my config",
+            |ctx, _args| {
+                let guild_id = ctx.message.guild_id.as_ref().unwrap();
+                ctx.settings
+                    .remove(&WELCOME, Scope::Guild, guild_id)
+                    .map_err(|e| {
+                        error!("Failed to reset welcome of {:?}: {}", guild_id, e);
+                        CommandError::Failed(ctx.text("setting-failed", &[]))
+                    })?;
+                show(ctx)
+            },
+        )
+        .guard(Guard::GuildOnly)
+        .permissions(Permissions::MANAGE_GUILD),
+    );
+}
+
+fn show(ctx: &CommandContext) -> CommandResult {
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    let config = match welcome_config(ctx.settings, guild_id) {
+        Some(config) => config,
+        None => {
+            ctx.reply(&ctx.text("welcome-none", &[]));
+            return Ok(());
+        }
+    };
+    let off = ctx.text("welcome-off", &[]);
+    let text = |template: &Option<String>| {
+        template
+            .as_ref()
+            .map(|t| format!("`{}`", t.replace('`', "'")))
+            .unwrap_or_else(|| off.clone())
+    };
+    let role = config
+        .role
+        .as_ref()
+        .map(|r| format!("<@&{}>", r.0))
+        .unwrap_or_else(|| off.clone());
+    let embed = if config.embed { "on" } else { "off" };
+    ctx.reply(&ctx.text(
+        "welcome-show",
+        &[
+            ("channel", &config.channel.0),
+            ("welcome", &text(&config.welcome)),
+            ("goodbye", &text(&config.goodbye)),
+            ("direct", &text(&config.direct)),
+            ("embed", &embed),
+            ("role", &role),
+        ],
+    ));
+    Ok(())
+}
+
+/// Changes one part of welcome, the rest stays as it is in settings or config.
+fn set(ctx: &CommandContext, part: &str, value: &str) -> CommandResult {
+    let guild_id = ctx.message.guild_id.as_ref().unwrap();
+    let current = welcome_config(ctx.settings, guild_id);
+    let value = value.trim();
+    let text = if value.is_empty() {
+        None
+    } else {
+        Some(value.to_owned())
+    };
+
+    let config = match (part.to_lowercase().as_str(), current) {
+        ("channel", current) => {
+            let channel_id = match parse_value(&ArgumentType::Channel, value) {
+                Some(Value::Channel(channel_id)) => channel_id,
+                _ => return Err(CommandError::Usage(ctx.text("welcome-not-channel", &[]))),
+            };
+            let in_guild = ctx
+                .cache
+                .channel(&channel_id)
+                .map(|c| c.guild_id.as_ref() == Some(guild_id))
+                .unwrap_or(false);
+            if !in_guild {
+                return Err(CommandError::Usage(ctx.text("channel-not-in-guild", &[])));
+            }
+            match current {
+                Some(config) => WelcomeConfig {
+                    channel: channel_id,
+                    ..config
+                },
+                None => WelcomeConfig {
+                    channel: channel_id,
+                    welcome: None,
+                    goodbye: None,
+                    embed: false,
+                    direct: None,
+                    role: None,
+                },
+            }
+        }
+        (_, None) => return Err(CommandError::Usage(ctx.text("welcome-channel-first", &[]))),
+        ("welcome", Some(config)) => WelcomeConfig {
+            welcome: text,
+            ..config
+        },
+        ("goodbye", Some(config)) => WelcomeConfig {
+            goodbye: text,
+            ..config
+        },
+        ("direct", Some(config)) => WelcomeConfig {
+            direct: text,
+            ..config
+        },
+        ("embed", Some(config)) => {
+            let embed = match value.to_lowercase().as_str() {
+                "on" => true,
+                "off" => false,
+                _ => return Err(CommandError::Usage(ctx.text("welcome-embed-state", &[]))),
+            };
+            WelcomeConfig { embed, ..config }
+        }
+        ("role", Some(config)) => {
+            let role = match text {
+                None => None,
+                Some(_) => match parse_value(&ArgumentType::Role, value) {
+                    Some(Value::Role(role_id)) => {
+                        check_assignable_role(ctx, guild_id, &role_id)?;
+                        Some(role_id)
+                    }
+                    _ => return Err(CommandError::Usage(ctx.text("role-not-found", &[]))),
+                },
+            };
+            WelcomeConfig { role, ..config }
+        }
+        _ => return Err(CommandError::Usage(ctx.text("welcome-parts", &[]))),
+    };
+
+    ctx.settings
+        .set(&WELCOME, Scope::Guild, guild_id, &config)
+        .map_err(|e| {
+            error!("Failed to save welcome of {:?}: {}", guild_id, e);
+            CommandError::Failed(ctx.text("setting-failed", &[]))
+        })?;
+    show(ctx)
+}